@@ -0,0 +1,115 @@
+use crate::output::{Format, Output};
+use crate::service::Endpoints;
+use atlas_core::alert::Alerter;
+use atlas_core::bus::{ChainEvent, EventBus, RecvError, Topic};
+use atlas_core::config::AtlasConfig;
+use atlas_core::error::AtlasResult;
+use atlas_core::health::HealthRegistry;
+use atlas_core::ratelimit::RateLimiter;
+use atlas_core::supervisor::{self, CancellationToken};
+use atlas_sol2::sol_block::SolanaRpcWrapper;
+use clap::Args;
+use log::warn;
+use serde::Serialize;
+
+//==========================================================================
+#[derive(Debug, Args)]
+pub struct BlocksArgs {
+    /// Stop after this many blocks.
+    #[arg(long)]
+    limit: Option<usize>,
+}
+
+//==========================================================================
+#[derive(Debug, Serialize)]
+struct BlockRow {
+    slot: u64,
+    parent_slot: Option<u64>,
+    block_height: Option<u64>,
+    block_time: Option<i64>,
+    transactions: Option<u64>,
+    blockhash: String,
+}
+
+//==========================================================================
+pub async fn run(
+    config: &AtlasConfig,
+    limiter: &RateLimiter,
+    args: &BlocksArgs,
+    format: Format,
+) -> AtlasResult<()> {
+    let endpoints = Endpoints::start(config);
+    let result = stream(config, limiter, endpoints.health(), args, format).await;
+    endpoints.stop().await;
+    result
+}
+
+//==========================================================================
+async fn stream(
+    config: &AtlasConfig,
+    limiter: &RateLimiter,
+    health: &HealthRegistry,
+    args: &BlocksArgs,
+    format: Format,
+) -> AtlasResult<()> {
+    let bus = EventBus::new();
+    let mut blocks = bus.subscribe("atlas_cli_blocks", &[Topic::BlockMeta], 1024);
+    let wrapper = SolanaRpcWrapper::new(config)
+        .with_event_bus(bus)
+        .with_rate_limiter(limiter.clone())
+        .with_health(health)
+        .with_alerter(Alerter::new(&config.alerting, &config.retry));
+    let shutdown = CancellationToken::new();
+    let stream = wrapper.stream_block(&shutdown);
+    tokio::pin!(stream);
+    let signal = supervisor::shutdown_signal();
+    tokio::pin!(signal);
+
+    let mut out = Output::stdout(
+        format,
+        &[
+            "slot",
+            "parent_slot",
+            "block_height",
+            "block_time",
+            "transactions",
+            "blockhash",
+        ],
+    )
+    .streaming();
+    let mut seen = 0;
+    while args.limit.is_none_or(|limit| seen < limit) {
+        let event = tokio::select! {
+            result = &mut stream => {
+                result?;
+                break;
+            }
+            signal = &mut signal => {
+                signal?;
+                break;
+            }
+            event = blocks.recv() => event,
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(RecvError::Lagged(n)) => {
+                warn!("Output fell behind, skipped {} blocks", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if let ChainEvent::BlockMeta(block) = event.as_ref() {
+            out.emit(BlockRow {
+                slot: block.slot,
+                parent_slot: block.parent_slot,
+                block_height: block.block_height,
+                block_time: block.block_time,
+                transactions: block.executed_transaction_count,
+                blockhash: block.blockhash.clone(),
+            })?;
+            seen += 1;
+        }
+    }
+    shutdown.cancel();
+    out.finish()
+}
@@ -0,0 +1,47 @@
+use crate::output::{Format, Output};
+use atlas_core::config::AtlasConfig;
+use atlas_core::error::{AtlasError, AtlasResult};
+use clap::Subcommand;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+//==========================================================================
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load a config file with its environment overlay and overrides, and
+    /// list every problem found. Exits 1 when there are any.
+    Validate { path: PathBuf },
+}
+
+//==========================================================================
+#[derive(Debug, Serialize)]
+struct ProblemRow {
+    field: String,
+    problem: String,
+}
+
+//==========================================================================
+pub fn run(command: &ConfigCommand, format: Format) -> AtlasResult<ExitCode> {
+    let ConfigCommand::Validate { path } = command;
+    let problems = match AtlasConfig::load(path) {
+        Ok(_) => Vec::new(),
+        Err(AtlasError::ConfigValidation(problems)) => problems,
+        Err(e) => return Err(e),
+    };
+    let mut out = Output::stdout(format, &["field", "problem"]);
+    for problem in &problems {
+        out.emit(ProblemRow {
+            field: problem.field.clone(),
+            problem: problem.message.clone(),
+        })?;
+    }
+    out.finish()?;
+    if problems.is_empty() {
+        eprintln!("{}: ok", path.display());
+        Ok(ExitCode::SUCCESS)
+    } else {
+        eprintln!("{}: {} problem(s)", path.display(), problems.len());
+        Ok(ExitCode::FAILURE)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::output::{Format, Output};
+use alloy::eips::BlockNumberOrTag;
+use alloy_primitives::U256;
+use atlas_core::config::AtlasConfig;
+use atlas_core::decimal::Decimal;
+use atlas_core::error::{AtlasError, AtlasResult};
+use atlas_core::ratelimit::RateLimiter;
+use atlas_eth::common::provider::{BlockGas, EthProvider};
+use atlas_eth::common::util::AtlasEthUtil;
+use clap::Subcommand;
+use serde::Serialize;
+
+//==========================================================================
+#[derive(Debug, Subcommand)]
+pub enum EthCommand {
+    /// EIP-1559 base fee of the block after `--block` (default latest), or
+    /// after the block described by `--gas-used`, `--gas-limit` and
+    /// `--base-fee` without calling the node.
+    BaseFee {
+        #[arg(long, conflicts_with = "gas_used")]
+        block: Option<u64>,
+        #[arg(long, requires_all = ["gas_limit", "base_fee"])]
+        gas_used: Option<u64>,
+        #[arg(long, requires = "gas_used")]
+        gas_limit: Option<u64>,
+        /// In wei.
+        #[arg(long, requires = "gas_used")]
+        base_fee: Option<u64>,
+    },
+}
+
+//==========================================================================
+#[derive(Debug, Serialize)]
+struct BaseFeeRow {
+    block: Option<u64>,
+    gas_used: u64,
+    gas_limit: u64,
+    base_fee_wei: String,
+    next_base_fee_wei: String,
+    next_base_fee_gwei: Decimal,
+}
+
+//==========================================================================
+pub async fn run(
+    config: &AtlasConfig,
+    limiter: &RateLimiter,
+    command: &EthCommand,
+    format: Format,
+) -> AtlasResult<()> {
+    let EthCommand::BaseFee {
+        block,
+        gas_used,
+        gas_limit,
+        base_fee,
+    } = command;
+    let gas = match (gas_used, gas_limit, base_fee) {
+        (Some(gas_used), Some(gas_limit), Some(base_fee)) => BlockGas {
+            number: 0,
+            gas_used: *gas_used,
+            gas_limit: *gas_limit,
+            base_fee_per_gas: *base_fee,
+        },
+        _ => {
+            let number = block.map_or(BlockNumberOrTag::Latest, BlockNumberOrTag::Number);
+            EthProvider::new(config)?
+                .with_rate_limiter(limiter.clone())
+                .get_block_gas(number)
+                .await?
+        }
+    };
+    if gas.gas_limit < 2 {
+        return Err(AtlasError::config("gas_limit", "must be at least 2"));
+    }
+    let next = AtlasEthUtil::calculate_next_block_base_fee(
+        U256::from(gas.gas_used),
+        U256::from(gas.gas_limit),
+        U256::from(gas.base_fee_per_gas),
+    );
+    let gwei = i128::try_from(next)
+        .map(|wei| Decimal::new(wei, 9))
+        .map_err(|e| AtlasError::decode("next base fee", e))?;
+
+    let mut out = Output::stdout(
+        format,
+        &[
+            "block",
+            "gas_used",
+            "gas_limit",
+            "base_fee_wei",
+            "next_base_fee_wei",
+            "next_base_fee_gwei",
+        ],
+    );
+    out.emit(BaseFeeRow {
+        block: gas_used.is_none().then_some(gas.number),
+        gas_used: gas.gas_used,
+        gas_limit: gas.gas_limit,
+        base_fee_wei: gas.base_fee_per_gas.to_string(),
+        next_base_fee_wei: next.to_string(),
+        next_base_fee_gwei: gwei.normalize(),
+    })?;
+    out.finish()
+}
@@ -0,0 +1,5 @@
+pub mod blocks;
+pub mod config;
+pub mod eth;
+pub mod price;
+pub mod tx;
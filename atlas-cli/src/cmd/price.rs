@@ -0,0 +1,195 @@
+use crate::output::{Format, Output};
+use crate::service::Endpoints;
+use atlas_core::alert::Alerter;
+use atlas_core::bus::{ChainEvent, EventBus, RecvError, Topic};
+use atlas_core::config::AtlasConfig;
+use atlas_core::decimal::Decimal;
+use atlas_core::error::{AtlasError, AtlasResult};
+use atlas_core::ratelimit::RateLimiter;
+use atlas_core::supervisor::{self, CancellationToken};
+use atlas_data::pyth::HermesClient;
+use chrono::{DateTime, SecondsFormat};
+use clap::Subcommand;
+use log::warn;
+use serde::Serialize;
+
+//==========================================================================
+#[derive(Debug, Subcommand)]
+pub enum PriceCommand {
+    /// Latest price of each feed.
+    Get {
+        /// Feed ids (hex) or symbols such as `SOL/USD`.
+        #[arg(required = true)]
+        feeds: Vec<String>,
+    },
+    /// Stream price updates until interrupted.
+    Stream {
+        #[arg(required = true)]
+        feeds: Vec<String>,
+        /// Stop after this many updates.
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// List feeds whose symbol matches `query`.
+    Search { query: String },
+}
+
+//==========================================================================
+#[derive(Debug, Serialize)]
+struct PriceRow {
+    feed_id: String,
+    price: Decimal,
+    conf: Decimal,
+    publish_time: String,
+}
+
+//==========================================================================
+impl PriceRow {
+    fn new(
+        feed_id: &str,
+        price: i64,
+        conf: u64,
+        expo: i32,
+        publish_time: i64,
+    ) -> AtlasResult<Self> {
+        let conf = i64::try_from(conf).map_err(|e| AtlasError::decode("pyth price conf", e))?;
+        Ok(PriceRow {
+            feed_id: feed_id.to_string(),
+            price: Decimal::from_price(price, expo)?,
+            conf: Decimal::from_price(conf, expo)?,
+            publish_time: DateTime::from_timestamp(publish_time, 0)
+                .unwrap_or_default()
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        })
+    }
+}
+
+const PRICE_COLUMNS: &[&str] = &["feed_id", "price", "conf", "publish_time"];
+/// Hermes REST API used by `price get`: `/v2/updates/price/latest`.
+const HERMES_REST_VERSION: u8 = 2;
+/// Hermes websocket protocol used by `price stream`; only v1 exists.
+const HERMES_WS_VERSION: u32 = 1;
+
+//==========================================================================
+pub async fn run(
+    config: &AtlasConfig,
+    limiter: &RateLimiter,
+    command: PriceCommand,
+    format: Format,
+) -> AtlasResult<()> {
+    let client = || HermesClient::new(config).with_rate_limiter(limiter.clone());
+    match command {
+        PriceCommand::Get { feeds } => {
+            let client = client();
+            let ids = resolve(&client, &feeds).await?;
+            let mut out = Output::stdout(format, PRICE_COLUMNS);
+            for feed in client
+                .get_pyth_prices_latest(ids, HERMES_REST_VERSION)
+                .await?
+            {
+                let p = &feed.price;
+                out.emit(PriceRow::new(
+                    &feed.id,
+                    p.price,
+                    p.conf,
+                    p.expo,
+                    p.publish_time,
+                )?)?;
+            }
+            out.finish()
+        }
+        PriceCommand::Stream { feeds, limit } => {
+            let endpoints = Endpoints::start(config);
+            let client = client().with_health(endpoints.health());
+            let result = stream(config, client, &feeds, limit, format).await;
+            endpoints.stop().await;
+            result
+        }
+        PriceCommand::Search { query } => {
+            let client = client();
+            let mut out = Output::stdout(format, &["symbol", "asset_type", "id"]);
+            for feed in client.search_feeds(&query).await? {
+                out.emit(feed)?;
+            }
+            out.finish()
+        }
+    }
+}
+
+//==========================================================================
+async fn stream(
+    config: &AtlasConfig,
+    client: HermesClient,
+    feeds: &[String],
+    limit: Option<usize>,
+    format: Format,
+) -> AtlasResult<()> {
+    let bus = EventBus::new();
+    let mut prices = bus.subscribe("atlas_cli_prices", &[Topic::Price], 1024);
+    let mut client = client
+        .with_event_bus(bus)
+        .with_alerter(Alerter::new(&config.alerting, &config.retry));
+    let ids = resolve(&client, feeds).await?;
+    client.add_feed_ids(ids).await;
+    let shutdown = CancellationToken::new();
+    let updates = client.ws_pyth_prices(HERMES_WS_VERSION, &shutdown);
+    tokio::pin!(updates);
+    let signal = supervisor::shutdown_signal();
+    tokio::pin!(signal);
+
+    let mut out = Output::stdout(format, PRICE_COLUMNS).streaming();
+    let mut seen = 0;
+    while limit.is_none_or(|limit| seen < limit) {
+        let event = tokio::select! {
+            result = &mut updates => {
+                result?;
+                break;
+            }
+            signal = &mut signal => {
+                signal?;
+                break;
+            }
+            event = prices.recv() => event,
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(RecvError::Lagged(n)) => {
+                warn!("Output fell behind, skipped {} prices", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if let ChainEvent::Price(p) = event.as_ref() {
+            out.emit(PriceRow::new(
+                &p.feed_id,
+                p.price,
+                p.conf,
+                p.expo,
+                p.publish_time,
+            )?)?;
+            seen += 1;
+        }
+    }
+    shutdown.cancel();
+    out.finish()
+}
+
+//==========================================================================
+/// Hex ids pass through; anything else is looked up as a symbol.
+async fn resolve(client: &HermesClient, feeds: &[String]) -> AtlasResult<Vec<String>> {
+    let mut ids = Vec::with_capacity(feeds.len());
+    for feed in feeds {
+        if is_feed_id(feed) {
+            ids.push(feed.trim_start_matches("0x").to_lowercase());
+        } else {
+            ids.push(client.resolve_symbol(feed).await?);
+        }
+    }
+    Ok(ids)
+}
+
+//==========================================================================
+fn is_feed_id(feed: &str) -> bool {
+    let hex = feed.strip_prefix("0x").unwrap_or(feed);
+    hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())
+}
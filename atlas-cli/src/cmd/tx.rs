@@ -0,0 +1,83 @@
+use crate::output::{Format, Output};
+use atlas_core::config::AtlasConfig;
+use atlas_core::error::{AtlasError, AtlasResult};
+use atlas_core::ratelimit::RateLimiter;
+use atlas_sol::alt::{parse_loaded_addresses, AltResolver};
+use atlas_sol::transaction::decode_message_at;
+use atlas_sol2::sol_block::SolanaRpc;
+use clap::Args;
+use serde::Serialize;
+use solana_sdk::bs58;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::UiLoadedAddresses;
+
+//==========================================================================
+#[derive(Debug, Args)]
+pub struct TxArgs {
+    /// Base58 transaction signature.
+    signature: String,
+}
+
+//==========================================================================
+/// One decoded instruction. `data` stays base58, as explorers show it.
+#[derive(Debug, Serialize)]
+struct InstructionRow {
+    slot: u64,
+    index: usize,
+    program_id: String,
+    accounts: Vec<String>,
+    data: String,
+}
+
+//==========================================================================
+pub async fn run(
+    config: &AtlasConfig,
+    limiter: &RateLimiter,
+    args: &TxArgs,
+    format: Format,
+) -> AtlasResult<()> {
+    let signature: Signature = args
+        .signature
+        .parse()
+        .map_err(|e| AtlasError::decode("transaction signature", e))?;
+    let rpc = SolanaRpc::new(config).with_rate_limiter(limiter.clone());
+    let tx = rpc.get_transaction(&signature).await?;
+    let versioned = tx
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| AtlasError::decode("transaction", "not base64 encoded"))?;
+    let loaded = tx
+        .transaction
+        .meta
+        .as_ref()
+        .and_then(|meta| Option::<&UiLoadedAddresses>::from(meta.loaded_addresses.as_ref()))
+        .map(|ui| parse_loaded_addresses(&ui.writable, &ui.readonly))
+        .transpose()?;
+    let decoded = decode_message_at(
+        rpc.client(),
+        &AltResolver::new(),
+        &Pubkey::default(),
+        &versioned.message.serialize(),
+        tx.slot,
+        loaded.as_ref(),
+    )
+    .await?;
+
+    let mut out = Output::stdout(format, &["slot", "index", "program_id", "accounts", "data"]);
+    for (index, instruction) in decoded.instructions.iter().enumerate() {
+        out.emit(InstructionRow {
+            slot: tx.slot,
+            index,
+            program_id: instruction.program_id.to_string(),
+            accounts: instruction
+                .keys
+                .iter()
+                .map(|k| k.pubkey.to_string())
+                .collect(),
+            data: bs58::encode(&instruction.data).into_string(),
+        })?;
+    }
+    out.finish()
+}
@@ -0,0 +1,91 @@
+use atlas_core::config::AtlasConfig;
+use atlas_core::error::AtlasResult;
+use atlas_core::ratelimit::RateLimiter;
+use atlas_core::util::AtlasUtil;
+use clap::{Parser, Subcommand};
+use output::Format;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+mod cmd;
+mod output;
+mod service;
+
+//==========================================================================
+/// Query and stream chain data with the same clients the collectors use.
+#[derive(Debug, Parser)]
+#[command(name = "atlas", version)]
+struct Cli {
+    /// Config file; defaults to `$ATLAS_CONFIG`, then built-in defaults.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[arg(long, short, global = true, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    /// Overrides `logging.level`. Logs go to stderr.
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+//==========================================================================
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Stream Solana block metadata until interrupted.
+    Blocks(cmd::blocks::BlocksArgs),
+    /// Fetch a Solana transaction and decode its instructions.
+    Tx(cmd::tx::TxArgs),
+    /// Pyth prices by feed id or symbol.
+    #[command(subcommand)]
+    Price(cmd::price::PriceCommand),
+    /// Ethereum helpers.
+    #[command(subcommand)]
+    Eth(cmd::eth::EthCommand),
+    /// Config file helpers.
+    #[command(subcommand)]
+    Config(cmd::config::ConfigCommand),
+}
+
+//==========================================================================
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+//==========================================================================
+async fn run(cli: Cli) -> AtlasResult<ExitCode> {
+    // Validating a file must not depend on loading one first.
+    if let Command::Config(command) = &cli.command {
+        return cmd::config::run(command, cli.format);
+    }
+    let mut config = match &cli.config {
+        Some(path) => AtlasConfig::load(path)?,
+        None => AtlasConfig::from_env()?,
+    };
+    if let Some(level) = cli.log_level {
+        config.logging.level = level;
+    }
+    AtlasUtil::setup_logger_with(&config.logging)?;
+    // One set of buckets for every client, so together they stay under each
+    // provider's limit.
+    let limiter = RateLimiter::new(&config.rate_limit);
+
+    match cli.command {
+        Command::Blocks(args) => cmd::blocks::run(&config, &limiter, &args, cli.format).await?,
+        Command::Tx(args) => cmd::tx::run(&config, &limiter, &args, cli.format).await?,
+        Command::Price(command) => cmd::price::run(&config, &limiter, command, cli.format).await?,
+        Command::Eth(command) => cmd::eth::run(&config, &limiter, &command, cli.format).await?,
+        Command::Config(_) => unreachable!(),
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,235 @@
+use atlas_core::error::AtlasResult;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Stdout, Write};
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned columns for reading.
+    Table,
+    /// One JSON array once the command finishes.
+    Json,
+    /// One JSON object per line, as records arrive.
+    Jsonl,
+}
+
+//==========================================================================
+/// Writes every command's records the same way in each format. Records are
+/// any `Serialize` struct; `columns` picks and orders the fields a table shows.
+pub struct Output<W: Write> {
+    out: W,
+    format: Format,
+    columns: Vec<&'static str>,
+    rows: Vec<Value>,
+    /// Set for commands that run until interrupted: table rows are printed
+    /// as they arrive. A row that does not fit widens its columns and
+    /// repeats the header, so every row lines up with the header above it.
+    streaming: bool,
+    widths: Option<Vec<usize>>,
+}
+
+//==========================================================================
+impl Output<Stdout> {
+    //==========================================================================
+    pub fn stdout(format: Format, columns: &[&'static str]) -> Self {
+        Output::new(io::stdout(), format, columns)
+    }
+}
+
+//==========================================================================
+impl<W: Write> Output<W> {
+    //==========================================================================
+    pub fn new(out: W, format: Format, columns: &[&'static str]) -> Self {
+        Output {
+            out,
+            format,
+            columns: columns.to_vec(),
+            rows: Vec::new(),
+            streaming: false,
+            widths: None,
+        }
+    }
+
+    //==========================================================================
+    pub fn streaming(mut self) -> Self {
+        self.streaming = true;
+        self
+    }
+
+    //==========================================================================
+    pub fn emit(&mut self, record: impl Serialize) -> AtlasResult<()> {
+        let record = serde_json::to_value(record)?;
+        match self.format {
+            Format::Jsonl => {
+                writeln!(self.out, "{}", record)?;
+                self.out.flush()?;
+            }
+            Format::Table if self.streaming => {
+                let cells = self.cells(&record);
+                let current = self.widths.clone();
+                let widths: Vec<_> = self
+                    .columns
+                    .iter()
+                    .zip(&cells)
+                    .enumerate()
+                    .map(|(i, (c, v))| {
+                        let width = current.as_ref().map_or(0, |w| w[i]);
+                        width.max(c.len()).max(v.len())
+                    })
+                    .collect();
+                if current.as_ref() != Some(&widths) {
+                    let header = self.header();
+                    self.write_line(&header, &widths)?;
+                    self.widths = Some(widths.clone());
+                }
+                self.write_line(&cells, &widths)?;
+                self.out.flush()?;
+            }
+            Format::Table | Format::Json => self.rows.push(record),
+        }
+        Ok(())
+    }
+
+    //==========================================================================
+    /// Write whatever was held back for the end.
+    pub fn finish(mut self) -> AtlasResult<()> {
+        match self.format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut self.out, &self.rows)?;
+                writeln!(self.out)?;
+            }
+            Format::Table if !self.streaming && !self.rows.is_empty() => {
+                let header = self.header();
+                let rows: Vec<_> = self.rows.iter().map(|r| self.cells(r)).collect();
+                let widths: Vec<_> = (0..self.columns.len())
+                    .map(|i| {
+                        rows.iter()
+                            .map(|r| r[i].len())
+                            .chain([header[i].len()])
+                            .max()
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                self.write_line(&header, &widths)?;
+                for row in &rows {
+                    self.write_line(row, &widths)?;
+                }
+            }
+            _ => {}
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    //==========================================================================
+    fn header(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.to_uppercase()).collect()
+    }
+
+    //==========================================================================
+    fn cells(&self, record: &Value) -> Vec<String> {
+        self.columns.iter().map(|c| cell(&record[c])).collect()
+    }
+
+    //==========================================================================
+    fn write_line(&mut self, cells: &[String], widths: &[usize]) -> io::Result<()> {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(self.out, "{}", line.trim_end())
+    }
+}
+
+//==========================================================================
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(cell).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        slot: u64,
+        blockhash: &'static str,
+        block_time: Option<i64>,
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![
+            Row {
+                slot: 7,
+                blockhash: "4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ",
+                block_time: None,
+            },
+            Row {
+                slot: 301_000_000,
+                blockhash: "9xQe",
+                block_time: Some(1_733_011_200),
+            },
+            Row {
+                slot: 8,
+                blockhash: "Ggbm",
+                block_time: None,
+            },
+        ]
+    }
+
+    fn render(format: Format, streaming: bool) -> String {
+        let mut written = Vec::new();
+        let mut out = Output::new(&mut written, format, &["slot", "blockhash", "block_time"]);
+        if streaming {
+            out = out.streaming();
+        }
+        for row in rows() {
+            out.emit(row).unwrap();
+        }
+        out.finish().unwrap();
+        String::from_utf8(written).unwrap()
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            render(Format::Table, false),
+            "\
+SLOT       BLOCKHASH                         BLOCK_TIME
+7          4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ  -
+301000000  9xQe                              1733011200
+8          Ggbm                              -
+"
+        );
+        assert_eq!(
+            render(Format::Table, true),
+            "\
+SLOT  BLOCKHASH                         BLOCK_TIME
+7     4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ  -
+SLOT       BLOCKHASH                         BLOCK_TIME
+301000000  9xQe                              1733011200
+8          Ggbm                              -
+"
+        );
+        assert_eq!(
+            render(Format::Jsonl, true),
+            "{\"block_time\":null,\"blockhash\":\"4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ\",\"slot\":7}\n\
+             {\"block_time\":1733011200,\"blockhash\":\"9xQe\",\"slot\":301000000}\n\
+             {\"block_time\":null,\"blockhash\":\"Ggbm\",\"slot\":8}\n"
+        );
+        let json: Value = serde_json::from_str(&render(Format::Json, false)).unwrap();
+        assert_eq!(json[1]["slot"], 301_000_000);
+        assert_eq!(json.as_array().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,67 @@
+use atlas_core::config::AtlasConfig;
+use atlas_core::health::{self, HealthRegistry};
+use atlas_core::metrics;
+use atlas_core::supervisor::{Stage, Supervisor};
+use log::info;
+
+//==========================================================================
+/// The metrics and health endpoints a streaming command serves while it
+/// runs, as configured under `[metrics]` and `[health]`. Supervised the same
+/// way the geyser plugin runs them.
+pub struct Endpoints {
+    registry: HealthRegistry,
+    supervisor: Supervisor,
+}
+
+//==========================================================================
+impl Endpoints {
+    //==========================================================================
+    /// Must be called inside a tokio runtime.
+    pub fn start(config: &AtlasConfig) -> Self {
+        let registry = HealthRegistry::new(&config.health);
+        let mut supervisor = Supervisor::new(&config.supervisor, &config.retry);
+        if config.metrics.enabled {
+            let listen = config.metrics.listen.clone();
+            supervisor.spawn("metrics", Stage::Sink, move |token| {
+                let listen = listen.clone();
+                async move {
+                    tokio::select! {
+                        result = metrics::serve(&listen, metrics::global()) => result,
+                        _ = token.cancelled() => Ok(()),
+                    }
+                }
+            });
+        }
+        if config.health.enabled {
+            let listen = config.health.listen.clone();
+            let served = registry.clone();
+            supervisor.spawn("health", Stage::Sink, move |token| {
+                let listen = listen.clone();
+                let registry = served.clone();
+                async move {
+                    tokio::select! {
+                        result = health::serve(&listen, registry) => result,
+                        _ = token.cancelled() => Ok(()),
+                    }
+                }
+            });
+        }
+        Endpoints {
+            registry,
+            supervisor,
+        }
+    }
+
+    //==========================================================================
+    /// Components registered here show up on `/readyz`.
+    pub fn health(&self) -> &HealthRegistry {
+        &self.registry
+    }
+
+    //==========================================================================
+    pub async fn stop(self) {
+        for report in self.supervisor.shutdown().await {
+            info!(task = report.name.as_str(); "Stopped: {:?}", report.exit);
+        }
+    }
+}
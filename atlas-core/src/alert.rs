@@ -0,0 +1,760 @@
+use crate::clock::{self, SharedClock};
+use crate::config::{
+    AlertingConfig, EndpointLimit, RateLimitConfig, RetryConfig, Severity, TelegramAlertConfig,
+};
+use crate::error::{AtlasError, AtlasResult};
+use crate::health::ConnectionState;
+use crate::metrics;
+use crate::ratelimit::RateLimiter;
+use crate::retry::CallPolicy;
+use crate::secret::Secret;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use zeroize::Zeroizing;
+
+/// Key the alert budget is kept under in the alerter's own rate limiter.
+const BUDGET_KEY: &str = "alerts";
+
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = AtlasResult<()>> + Send + 'a>>;
+
+//==========================================================================
+/// A channel alerts are delivered to, given the final rendered text.
+pub trait Notifier: Send + Sync + fmt::Debug {
+    fn name(&self) -> &str;
+
+    fn notify<'a>(&'a self, text: &'a str) -> NotifyFuture<'a>;
+}
+
+//==========================================================================
+/// Something worth telling a human about. `kind` picks the template and,
+/// together with `source`, identifies repeats unless a key is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub severity: Severity,
+    pub kind: String,
+    pub source: String,
+    pub summary: String,
+    pub fields: BTreeMap<String, String>,
+    key: Option<String>,
+}
+
+//==========================================================================
+impl Alert {
+    //==========================================================================
+    pub fn new(severity: Severity, kind: &str, source: &str, summary: impl ToString) -> Self {
+        Alert {
+            severity,
+            kind: kind.to_string(),
+            source: source.to_string(),
+            summary: summary.to_string(),
+            fields: BTreeMap::new(),
+            key: None,
+        }
+    }
+
+    //==========================================================================
+    pub fn with_field(mut self, name: &str, value: impl fmt::Display) -> Self {
+        self.fields.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    //==========================================================================
+    /// Deduplicate on `key` instead of kind and source.
+    pub fn with_key(mut self, key: impl ToString) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    //==========================================================================
+    pub fn key(&self) -> String {
+        self.key
+            .clone()
+            .unwrap_or_else(|| format!("{}:{}", self.kind, self.source))
+    }
+
+    //==========================================================================
+    pub fn disconnected(source: &str, endpoint: &str) -> Self {
+        Alert::new(Severity::Warning, "disconnected", source, "connection lost")
+            .with_field("endpoint", endpoint)
+    }
+
+    //==========================================================================
+    /// As severe as `disconnected`, so every chat told about the outage also
+    /// hears that it ended.
+    pub fn reconnected(source: &str, endpoint: &str) -> Self {
+        Alert::new(
+            Severity::Warning,
+            "reconnected",
+            source,
+            "connection restored",
+        )
+        .with_field("endpoint", endpoint)
+    }
+
+    //==========================================================================
+    pub fn stale_price(source: &str, feed: &str, age: Duration) -> Self {
+        Alert::new(
+            Severity::Warning,
+            "stale_price",
+            source,
+            "price feed is stale",
+        )
+        .with_field("feed", feed)
+        .with_field("age_s", age.as_secs())
+        .with_key(format!("stale_price:{}", feed))
+    }
+
+    //==========================================================================
+    /// A bot action that gave up. Fatal errors are critical; retryable ones
+    /// that ran out of attempts are warnings.
+    pub fn action_failed(source: &str, action: &str, err: &AtlasError) -> Self {
+        let severity = if err.is_fatal() {
+            Severity::Critical
+        } else {
+            Severity::Warning
+        };
+        Alert::new(
+            severity,
+            "action_failed",
+            source,
+            format!("{} failed", action),
+        )
+        .with_field("action", action)
+        .with_field("error", err)
+        .with_key(format!("action_failed:{}:{}", source, action))
+    }
+}
+
+//==========================================================================
+/// Fill `{name}` placeholders from the alert's fields and its `severity`,
+/// `kind`, `source` and `summary`. Unknown placeholders are left as written.
+pub fn render(template: &str, alert: &Alert) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let Some(end) = tail.find('}') else {
+            break;
+        };
+        let name = &tail[1..end];
+        match lookup(alert, name) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&tail[..=end]),
+        }
+        rest = &tail[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+//==========================================================================
+fn lookup(alert: &Alert, name: &str) -> Option<String> {
+    match name {
+        "severity" => Some(severity_label(alert.severity).to_string()),
+        "kind" => Some(alert.kind.clone()),
+        "source" => Some(alert.source.clone()),
+        "summary" => Some(alert.summary.clone()),
+        _ => alert.fields.get(name).cloned(),
+    }
+}
+
+//==========================================================================
+fn severity_label(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "INFO",
+        Severity::Warning => "WARNING",
+        Severity::Critical => "CRITICAL",
+    }
+}
+
+//==========================================================================
+/// Used for kinds without a template: headline, then one line per field.
+fn render_default(alert: &Alert) -> String {
+    let mut text = render("[{severity}] {source}: {summary}", alert);
+    for (name, value) in &alert.fields {
+        text.push_str(&format!("\n{}: {}", name, value));
+    }
+    text
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Delivered to this many channels.
+    Sent(usize),
+    Deduplicated,
+    RateLimited,
+    /// No channel takes alerts of this severity.
+    Unrouted,
+    Disabled,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy)]
+struct Recent {
+    sent_at: DateTime<Utc>,
+    severity: Severity,
+    suppressed: u64,
+}
+
+//==========================================================================
+#[derive(Debug, Clone)]
+struct Route {
+    min_severity: Severity,
+    notifier: Arc<dyn Notifier>,
+}
+
+//==========================================================================
+/// Renders, deduplicates, rate limits and routes alerts to notifiers by
+/// severity. Clones share their state. A disabled alerter accepts every
+/// alert and sends nothing.
+#[derive(Debug, Clone)]
+pub struct Alerter {
+    enabled: bool,
+    routes: Arc<Vec<Route>>,
+    templates: Arc<HashMap<String, String>>,
+    dedup_window: Duration,
+    recent: Arc<Mutex<HashMap<String, Recent>>>,
+    limiter: RateLimiter,
+    clock: SharedClock,
+}
+
+//==========================================================================
+impl Default for Alerter {
+    fn default() -> Self {
+        Alerter::with_routes(&AlertingConfig::default(), Vec::new())
+    }
+}
+
+//==========================================================================
+impl Alerter {
+    //==========================================================================
+    /// Telegram routes as configured. Disabled unless `config.enabled`.
+    pub fn new(config: &AlertingConfig, retry: &RetryConfig) -> Self {
+        let routes = match (&config.telegram, config.enabled) {
+            (Some(telegram), true) => TelegramNotifier::routes(telegram, retry),
+            _ => Vec::new(),
+        };
+        Alerter::with_routes(config, routes)
+    }
+
+    //==========================================================================
+    /// Route each alert to every notifier whose minimum severity it meets.
+    pub fn with_routes(
+        config: &AlertingConfig,
+        routes: Vec<(Severity, Arc<dyn Notifier>)>,
+    ) -> Self {
+        let per_minute = config.max_per_minute.max(1) as f64;
+        let mut budget = RateLimitConfig::default();
+        budget.endpoints.insert(
+            BUDGET_KEY.to_string(),
+            EndpointLimit {
+                per_second: per_minute / 60.0,
+                burst: per_minute,
+                ..EndpointLimit::default()
+            },
+        );
+        Alerter {
+            enabled: config.enabled,
+            routes: Arc::new(
+                routes
+                    .into_iter()
+                    .map(|(min_severity, notifier)| Route {
+                        min_severity,
+                        notifier,
+                    })
+                    .collect(),
+            ),
+            templates: Arc::new(config.templates.clone()),
+            dedup_window: Duration::from_millis(config.dedup_window_ms),
+            recent: Arc::default(),
+            limiter: RateLimiter::new(&budget),
+            clock: clock::system(),
+        }
+    }
+
+    //==========================================================================
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.limiter = self.limiter.with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    //==========================================================================
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    //==========================================================================
+    pub fn render(&self, alert: &Alert) -> String {
+        match self.templates.get(&alert.kind) {
+            Some(template) => render(template, alert),
+            None => render_default(alert),
+        }
+    }
+
+    //==========================================================================
+    /// Deliver `alert` now. A repeat within the dedup window is only counted,
+    /// unless it is more severe than what was sent; the count is reported
+    /// with the next alert that does go out.
+    pub async fn send(&self, mut alert: Alert) -> AtlasResult<Delivery> {
+        if !self.enabled {
+            return Ok(Delivery::Disabled);
+        }
+        let routes: Vec<_> = self
+            .routes
+            .iter()
+            .filter(|r| alert.severity >= r.min_severity)
+            .collect();
+        if routes.is_empty() {
+            return Ok(self.outcome(&alert, Delivery::Unrouted));
+        }
+        let key = alert.key();
+        let now = self.clock.now();
+        {
+            let mut recent = self.recent.lock().unwrap();
+            if let Some(last) = recent.get_mut(&key) {
+                let fresh = self.clock.since(last.sent_at) < self.dedup_window;
+                if fresh && alert.severity <= last.severity {
+                    last.suppressed += 1;
+                    return Ok(self.outcome(&alert, Delivery::Deduplicated));
+                }
+            }
+            if alert.severity < Severity::Critical
+                && !self.limiter.try_acquire(BUDGET_KEY, &alert.kind)
+            {
+                return Ok(self.outcome(&alert, Delivery::RateLimited));
+            }
+            let previous = recent.insert(
+                key,
+                Recent {
+                    sent_at: now,
+                    severity: alert.severity,
+                    suppressed: 0,
+                },
+            );
+            if let Some(n) = previous.map(|p| p.suppressed).filter(|n| *n > 0) {
+                alert = alert.with_field("repeats_suppressed", n);
+            }
+        }
+        let text = self.render(&alert);
+        let mut delivered = 0;
+        let mut failure = None;
+        for route in routes {
+            match route.notifier.notify(&text).await {
+                Ok(()) => delivered += 1,
+                Err(e) => {
+                    warn!(notifier = route.notifier.name(); "Alert delivery failed: {}", e);
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) if delivered == 0 => {
+                metrics::global()
+                    .counter(
+                        "atlas_alerts_total",
+                        "Alerts raised, by kind and outcome",
+                        &[("kind", alert.kind.as_str()), ("outcome", "failed")],
+                    )
+                    .inc();
+                Err(e)
+            }
+            _ => Ok(self.outcome(&alert, Delivery::Sent(delivered))),
+        }
+    }
+
+    //==========================================================================
+    fn outcome(&self, alert: &Alert, delivery: Delivery) -> Delivery {
+        let outcome = match delivery {
+            Delivery::Sent(_) => "sent",
+            Delivery::Deduplicated => "deduplicated",
+            Delivery::RateLimited => "rate_limited",
+            Delivery::Unrouted => "unrouted",
+            Delivery::Disabled => "disabled",
+        };
+        metrics::global()
+            .counter(
+                "atlas_alerts_total",
+                "Alerts raised, by kind and outcome",
+                &[("kind", alert.kind.as_str()), ("outcome", outcome)],
+            )
+            .inc();
+        delivery
+    }
+
+    //==========================================================================
+    /// Send in the background so the caller never waits on a notifier.
+    /// Must be called inside a tokio runtime when enabled.
+    pub fn fire(&self, alert: Alert) {
+        if !self.enabled {
+            return;
+        }
+        let alerter = self.clone();
+        tokio::spawn(async move {
+            let kind = alert.kind.clone();
+            if let Err(e) = alerter.send(alert).await {
+                error!(kind = kind.as_str(); "Alert not delivered: {}", e);
+            }
+        });
+    }
+
+    //==========================================================================
+    /// Alert when `state` drops from connected and again once it recovers.
+    /// The watcher stops with the connection's owner.
+    pub fn watch_connection(
+        &self,
+        source: &str,
+        endpoint: &str,
+        mut state: watch::Receiver<ConnectionState>,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let alerter = self.clone();
+        let source = source.to_string();
+        let endpoint = endpoint.to_string();
+        tokio::spawn(async move {
+            let mut was_connected = *state.borrow_and_update() == ConnectionState::Connected;
+            let mut lost = false;
+            while state.changed().await.is_ok() {
+                let connected = *state.borrow_and_update() == ConnectionState::Connected;
+                if was_connected && !connected {
+                    lost = true;
+                    alerter.fire(Alert::disconnected(&source, &endpoint));
+                } else if lost && connected {
+                    lost = false;
+                    alerter.fire(Alert::reconnected(&source, &endpoint));
+                }
+                was_connected = connected;
+            }
+        });
+    }
+}
+
+//==========================================================================
+/// Sends through the Telegram Bot API's `sendMessage`. The base URL is
+/// configurable so tests can point it at a local server.
+pub struct TelegramNotifier {
+    name: String,
+    base_url: String,
+    token: Secret,
+    chat_id: String,
+    http: reqwest::Client,
+    policy: CallPolicy,
+}
+
+//==========================================================================
+impl fmt::Debug for TelegramNotifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TelegramNotifier")
+            .field("base_url", &self.base_url)
+            .field("chat_id", &self.chat_id)
+            .finish_non_exhaustive()
+    }
+}
+
+//==========================================================================
+impl TelegramNotifier {
+    //==========================================================================
+    pub fn new(base_url: &str, token: &Secret, chat_id: &str, retry: &RetryConfig) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        TelegramNotifier {
+            name: format!("telegram:{}", chat_id),
+            policy: CallPolicy::from_config(&base_url, retry),
+            base_url,
+            token: token.clone(),
+            chat_id: chat_id.to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    //==========================================================================
+    fn routes(
+        config: &TelegramAlertConfig,
+        retry: &RetryConfig,
+    ) -> Vec<(Severity, Arc<dyn Notifier>)> {
+        let chat = |chat_id: &str| -> Arc<dyn Notifier> {
+            Arc::new(TelegramNotifier::new(
+                &config.base_url,
+                &config.token,
+                chat_id,
+                retry,
+            ))
+        };
+        std::iter::once((config.min_severity, chat(&config.chat_id)))
+            .chain(
+                config
+                    .routes
+                    .iter()
+                    .map(|r| (r.min_severity, chat(&r.chat_id))),
+            )
+            .collect()
+    }
+
+    //==========================================================================
+    async fn send_message(&self, text: &str) -> AtlasResult<()> {
+        // The URL carries the bot token, so it is kept out of every error.
+        let url = Zeroizing::new(format!(
+            "{}/bot{}/sendMessage",
+            self.base_url,
+            self.token.expose_str()?
+        ));
+        let body = json!({
+            "chat_id": self.chat_id,
+            "text": text,
+            "disable_web_page_preview": true,
+        });
+        let response = self
+            .http
+            .post(url.as_str())
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| AtlasError::transport(&self.base_url, e.without_url()))?;
+        let status = response.status();
+        let reply: Value = response.json().await.unwrap_or_default();
+        if status.is_success() && reply["ok"] == true {
+            return Ok(());
+        }
+        Err(AtlasError::Http {
+            endpoint: self.base_url.clone(),
+            status: status.as_u16(),
+            message: reply["description"]
+                .as_str()
+                .unwrap_or("sendMessage failed")
+                .to_string(),
+        })
+    }
+}
+
+//==========================================================================
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify<'a>(&'a self, text: &'a str) -> NotifyFuture<'a> {
+        Box::pin(
+            self.policy
+                .call("sendMessage", move || self.send_message(text)),
+        )
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn notify<'a>(&'a self, text: &'a str) -> NotifyFuture<'a> {
+            self.sent.lock().unwrap().push(text.to_string());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn enabled() -> AlertingConfig {
+        AlertingConfig {
+            enabled: true,
+            max_per_minute: 2,
+            ..AlertingConfig::default()
+        }
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_routing_dedup_and_rate_limit() {
+        let mut config = enabled();
+        config.templates.insert(
+            "stale_price".to_string(),
+            "{severity} {feed} silent for {age_s}s {missing}".to_string(),
+        );
+        let ops = Arc::new(Recorder::default());
+        let oncall = Arc::new(Recorder::default());
+        let clock = ManualClock::new(Utc::now());
+        let alerter = Alerter::with_routes(
+            &config,
+            vec![
+                (Severity::Warning, ops.clone() as Arc<dyn Notifier>),
+                (Severity::Critical, oncall.clone() as Arc<dyn Notifier>),
+            ],
+        )
+        .with_clock(Arc::new(clock.clone()));
+
+        let stale = || Alert::stale_price("hermes", "SOL/USD", Duration::from_secs(90));
+        assert_eq!(alerter.send(stale()).await.unwrap(), Delivery::Sent(1));
+        assert_eq!(alerter.send(stale()).await.unwrap(), Delivery::Deduplicated);
+        assert_eq!(
+            ops.sent.lock().unwrap()[0],
+            "WARNING SOL/USD silent for 90s {missing}"
+        );
+        let info = Alert::new(Severity::Info, "note", "hermes", "feed list reloaded");
+        assert_eq!(alerter.send(info).await.unwrap(), Delivery::Unrouted);
+
+        let disconnected = Alert::disconnected("blocks", "wss://rpc");
+        assert_eq!(
+            alerter.send(disconnected.clone()).await.unwrap(),
+            Delivery::Sent(1)
+        );
+        let other = Alert::disconnected("geyser", "local");
+        assert_eq!(alerter.send(other).await.unwrap(), Delivery::RateLimited);
+        let critical = Alert::action_failed(
+            "bot",
+            "submit_bundle",
+            &AtlasError::config("bot.address", "invalid"),
+        );
+        assert_eq!(alerter.send(critical).await.unwrap(), Delivery::Sent(2));
+        assert_eq!(oncall.sent.lock().unwrap().len(), 1);
+
+        // After the window the repeat goes out with the suppressed count.
+        clock.advance(Duration::from_secs(301));
+        assert_eq!(alerter.send(stale()).await.unwrap(), Delivery::Sent(1));
+        let text = alerter.render(&stale().with_field("repeats_suppressed", 1));
+        assert!(text.starts_with("WARNING SOL/USD"));
+        assert_eq!(alerter.send(disconnected).await.unwrap(), Delivery::Sent(1));
+        let last = ops.sent.lock().unwrap().last().cloned().unwrap();
+        assert_eq!(
+            last,
+            "[WARNING] blocks: connection lost\nendpoint: wss://rpc"
+        );
+
+        let disabled = Alerter::default();
+        assert_eq!(disabled.send(stale()).await.unwrap(), Delivery::Disabled);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_watch_connection_reports_outage_and_recovery() {
+        let chat = Arc::new(Recorder::default());
+        let min_severity = TelegramAlertConfig::default().min_severity;
+        let alerter = Alerter::with_routes(
+            &enabled(),
+            vec![(min_severity, chat.clone() as Arc<dyn Notifier>)],
+        );
+        let (state, watched) = watch::channel(ConnectionState::Connected);
+        alerter.watch_connection("blocks", "wss://rpc", watched);
+        // Let the watcher see the connection up before it drops.
+        tokio::task::yield_now().await;
+
+        for next in [ConnectionState::Disconnected, ConnectionState::Connected] {
+            let sent = chat.sent.lock().unwrap().len();
+            state.send(next).unwrap();
+            for _ in 0..100 {
+                if chat.sent.lock().unwrap().len() > sent {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        let sent = chat.sent.lock().unwrap();
+        assert_eq!(sent.len(), 2, "{:?}", sent);
+        assert!(sent[0].contains("connection lost"));
+        assert!(sent[1].contains("connection restored"));
+    }
+
+    //==========================================================================
+    /// Answers one `sendMessage` per connection with `status` and `reply`,
+    /// passing the request path and body on.
+    async fn mock_bot_api(
+        listener: TcpListener,
+        status: u16,
+        reply: Value,
+        seen: tokio::sync::mpsc::UnboundedSender<(String, Value)>,
+    ) {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(&mut stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).await.unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+            let _ = seen.send((path, serde_json::from_slice(&body).unwrap()));
+            let reply = reply.to_string();
+            let head = format!(
+                "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                reply.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        }
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_telegram_against_mock() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, mut seen) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(mock_bot_api(listener, 200, json!({"ok": true}), tx));
+
+        let mut config = enabled();
+        config.telegram = Some(TelegramAlertConfig {
+            token: Secret::new(b"123:abc".to_vec()),
+            chat_id: "-100".to_string(),
+            base_url: base_url.clone(),
+            ..TelegramAlertConfig::default()
+        });
+        assert!(!format!("{:?}", config).contains("123:abc"));
+        let alerter = Alerter::new(&config, &RetryConfig::default());
+        let alert = Alert::disconnected("blocks", "wss://rpc");
+        assert_eq!(alerter.send(alert).await.unwrap(), Delivery::Sent(1));
+        let (path, body) = seen.recv().await.unwrap();
+        assert_eq!(path, "/bot123:abc/sendMessage");
+        assert_eq!(body["chat_id"], "-100");
+        assert_eq!(
+            body["text"],
+            "[WARNING] blocks: connection lost\nendpoint: wss://rpc"
+        );
+
+        // A rejected message is fatal and the error never shows the token.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, _seen) = tokio::sync::mpsc::unbounded_channel();
+        let reply = json!({"ok": false, "description": "Bad Request: chat not found"});
+        tokio::spawn(mock_bot_api(listener, 400, reply, tx));
+        let token = Secret::new(b"123:abc".to_vec());
+        let notifier = TelegramNotifier::new(&base_url, &token, "-1", &RetryConfig::default());
+        let err = notifier.notify("hello").await.unwrap_err();
+        assert!(err.is_fatal());
+        assert!(err.to_string().contains("chat not found"), "{}", err);
+        assert!(!err.to_string().contains("123:abc"));
+        assert!(!format!("{:?}", notifier).contains("123:abc"));
+    }
+}
@@ -0,0 +1,464 @@
+use crate::decimal::Decimal;
+use crate::error::AtlasResult;
+use crate::metrics::{self, Counter, Gauge};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::Notify;
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountUpdate {
+    pub slot: u64,
+    pub pubkey: [u8; 32],
+    pub owner: [u8; 32],
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub write_version: u64,
+    pub is_startup: bool,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlotStatus {
+    Processed,
+    Confirmed,
+    Rooted,
+    /// Any other status the source reports, by name.
+    Other(String),
+}
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotUpdate {
+    pub slot: u64,
+    pub parent: Option<u64>,
+    pub status: SlotStatus,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionUpdate {
+    pub slot: u64,
+    pub signature: [u8; 64],
+    pub is_vote: bool,
+    /// Position within the block, when the source knows it.
+    pub index: Option<usize>,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMetaUpdate {
+    pub slot: u64,
+    pub parent_slot: Option<u64>,
+    pub blockhash: String,
+    pub block_time: Option<i64>,
+    pub block_height: Option<u64>,
+    pub executed_transaction_count: Option<u64>,
+}
+
+//==========================================================================
+/// What a block's non-vote transactions bid for inclusion. Unit prices are
+/// in micro-lamports per compute unit, fees in lamports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockFeeUpdate {
+    pub slot: u64,
+    pub transactions: u64,
+    pub units_consumed: u64,
+    pub base_fees: u64,
+    pub priority_fees: u64,
+    /// `(percentile, unit price)` pairs, lowest percentile first.
+    pub unit_prices: Vec<(u8, u64)>,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceUpdate {
+    pub feed_id: String,
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+//==========================================================================
+impl PriceUpdate {
+    pub fn value(&self) -> AtlasResult<Decimal> {
+        Decimal::from_price(self.price, self.expo)
+    }
+}
+
+//==========================================================================
+/// Everything the collectors hand downstream, whichever source it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    Account(AccountUpdate),
+    Slot(SlotUpdate),
+    Transaction(TransactionUpdate),
+    BlockMeta(BlockMetaUpdate),
+    BlockFees(BlockFeeUpdate),
+    Price(PriceUpdate),
+}
+
+//==========================================================================
+impl ChainEvent {
+    pub fn topic(&self) -> Topic {
+        match self {
+            ChainEvent::Account(_) => Topic::Account,
+            ChainEvent::Slot(_) => Topic::Slot,
+            ChainEvent::Transaction(_) => Topic::Transaction,
+            ChainEvent::BlockMeta(_) => Topic::BlockMeta,
+            ChainEvent::BlockFees(_) => Topic::BlockFees,
+            ChainEvent::Price(_) => Topic::Price,
+        }
+    }
+
+    //==========================================================================
+    /// Slot the event belongs to; prices are off-chain and have none.
+    pub fn slot(&self) -> Option<u64> {
+        match self {
+            ChainEvent::Account(a) => Some(a.slot),
+            ChainEvent::Slot(s) => Some(s.slot),
+            ChainEvent::Transaction(t) => Some(t.slot),
+            ChainEvent::BlockMeta(b) => Some(b.slot),
+            ChainEvent::BlockFees(f) => Some(f.slot),
+            ChainEvent::Price(_) => None,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Topic {
+    Account,
+    Slot,
+    Transaction,
+    BlockMeta,
+    BlockFees,
+    Price,
+}
+
+//==========================================================================
+impl Topic {
+    pub const ALL: &'static [Topic] = &[
+        Topic::Account,
+        Topic::Slot,
+        Topic::Transaction,
+        Topic::BlockMeta,
+        Topic::BlockFees,
+        Topic::Price,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Topic::Account => "account",
+            Topic::Slot => "slot",
+            Topic::Transaction => "transaction",
+            Topic::BlockMeta => "block_meta",
+            Topic::BlockFees => "block_fees",
+            Topic::Price => "price",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The subscriber fell behind and this many of the oldest events were
+    /// dropped from its queue. Receiving again continues with what is left.
+    Lagged(u64),
+    /// Every bus handle is gone and the queue is empty.
+    Closed,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Lagged(u64),
+    Closed,
+}
+
+//==========================================================================
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvError::Lagged(n) => write!(f, "subscriber lagged, {} events dropped", n),
+            RecvError::Closed => write!(f, "event bus closed"),
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Default)]
+struct Queue {
+    events: VecDeque<Arc<ChainEvent>>,
+    lagged: u64,
+    closed: bool,
+}
+
+//==========================================================================
+#[derive(Debug)]
+struct SubscriberShared {
+    topics: u8,
+    capacity: usize,
+    queue: Mutex<Queue>,
+    notify: Notify,
+    lagged: Counter,
+    depth: Gauge,
+}
+
+//==========================================================================
+#[derive(Debug, Default)]
+struct BusInner {
+    subscribers: RwLock<Vec<Arc<SubscriberShared>>>,
+}
+
+//==========================================================================
+impl Drop for BusInner {
+    fn drop(&mut self) {
+        for sub in self.subscribers.get_mut().unwrap().drain(..) {
+            sub.queue.lock().unwrap().closed = true;
+            sub.notify.notify_one();
+        }
+    }
+}
+
+//==========================================================================
+/// Fan-out bus: every subscriber gets its own bounded queue of the topics it
+/// asked for. Publishing never blocks; a full queue drops its oldest event
+/// and the subscriber is told how many it missed on its next receive.
+#[derive(Debug, Clone, Default)]
+pub struct EventBus {
+    inner: Arc<BusInner>,
+}
+
+//==========================================================================
+impl EventBus {
+    //==========================================================================
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //==========================================================================
+    /// `name` labels the subscriber's lag and queue depth metrics.
+    pub fn subscribe(&self, name: &str, topics: &[Topic], capacity: usize) -> EventSubscriber {
+        let registry = metrics::global();
+        let labels = [("subscriber", name)];
+        let shared = Arc::new(SubscriberShared {
+            topics: topics.iter().fold(0, |mask, t| mask | t.bit()),
+            capacity: capacity.max(1),
+            queue: Mutex::new(Queue::default()),
+            notify: Notify::new(),
+            lagged: registry.counter(
+                "atlas_bus_lagged_events_total",
+                "Events dropped because a bus subscriber fell behind",
+                &labels,
+            ),
+            depth: registry.gauge(
+                "atlas_bus_queue_depth",
+                "Events queued for a bus subscriber",
+                &labels,
+            ),
+        });
+        self.inner.subscribers.write().unwrap().push(shared.clone());
+        EventSubscriber { shared }
+    }
+
+    //==========================================================================
+    /// Queue `event` for every interested subscriber and return how many
+    /// that was.
+    pub fn publish(&self, event: ChainEvent) -> usize {
+        let bit = event.topic().bit();
+        let event = Arc::new(event);
+        let mut delivered = 0;
+        let mut stale = false;
+        for sub in self.inner.subscribers.read().unwrap().iter() {
+            if Arc::strong_count(sub) == 1 {
+                stale = true;
+                continue;
+            }
+            if sub.topics & bit == 0 {
+                continue;
+            }
+            let mut queue = sub.queue.lock().unwrap();
+            if queue.events.len() >= sub.capacity {
+                queue.events.pop_front();
+                queue.lagged += 1;
+                sub.lagged.inc();
+            }
+            queue.events.push_back(event.clone());
+            sub.depth.set(queue.events.len() as f64);
+            drop(queue);
+            sub.notify.notify_one();
+            delivered += 1;
+        }
+        if stale {
+            // Only the bus still holds these; their subscriber was dropped.
+            self.inner
+                .subscribers
+                .write()
+                .unwrap()
+                .retain(|sub| Arc::strong_count(sub) > 1);
+        }
+        delivered
+    }
+
+    //==========================================================================
+    pub fn subscriber_count(&self) -> usize {
+        self.inner
+            .subscribers
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|sub| Arc::strong_count(sub) > 1)
+            .count()
+    }
+}
+
+//==========================================================================
+pub struct EventSubscriber {
+    shared: Arc<SubscriberShared>,
+}
+
+//==========================================================================
+impl fmt::Debug for EventSubscriber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventSubscriber")
+            .field("capacity", &self.shared.capacity)
+            .field("queued", &self.len())
+            .finish()
+    }
+}
+
+//==========================================================================
+impl EventSubscriber {
+    //==========================================================================
+    pub async fn recv(&mut self) -> Result<Arc<ChainEvent>, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(event) => return Ok(event),
+                Err(TryRecvError::Lagged(n)) => return Err(RecvError::Lagged(n)),
+                Err(TryRecvError::Closed) => return Err(RecvError::Closed),
+                Err(TryRecvError::Empty) => self.shared.notify.notified().await,
+            }
+        }
+    }
+
+    //==========================================================================
+    pub fn try_recv(&mut self) -> Result<Arc<ChainEvent>, TryRecvError> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.lagged > 0 {
+            return Err(TryRecvError::Lagged(std::mem::take(&mut queue.lagged)));
+        }
+        match queue.events.pop_front() {
+            Some(event) => {
+                self.shared.depth.set(queue.events.len() as f64);
+                Ok(event)
+            }
+            None if queue.closed => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    //==========================================================================
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().unwrap().events.len()
+    }
+
+    //==========================================================================
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn slot(n: u64) -> ChainEvent {
+        ChainEvent::Slot(SlotUpdate {
+            slot: n,
+            parent: n.checked_sub(1),
+            status: SlotStatus::Processed,
+        })
+    }
+
+    fn price(feed: &str) -> ChainEvent {
+        ChainEvent::Price(PriceUpdate {
+            feed_id: feed.to_string(),
+            price: 100,
+            conf: 1,
+            expo: -2,
+            publish_time: 0,
+        })
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_topic_filtering() {
+        let bus = EventBus::new();
+        let mut slots = bus.subscribe("slots", &[Topic::Slot], 8);
+        let mut everything = bus.subscribe("all", Topic::ALL, 8);
+        assert_eq!(bus.publish(slot(1)), 2);
+        assert_eq!(bus.publish(price("btc")), 1);
+
+        assert_eq!(*slots.try_recv().unwrap(), slot(1));
+        assert_eq!(slots.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(*everything.try_recv().unwrap(), slot(1));
+        assert_eq!(*everything.try_recv().unwrap(), price("btc"));
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_lag_drops_oldest() {
+        let bus = EventBus::new();
+        let mut slow = bus.subscribe("slow", &[Topic::Slot], 2);
+        for n in 0..5 {
+            bus.publish(slot(n));
+        }
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Lagged(3)));
+        assert_eq!(*slow.try_recv().unwrap(), slot(3));
+        assert_eq!(*slow.try_recv().unwrap(), slot(4));
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_dropped_subscriber_is_pruned() {
+        let bus = EventBus::new();
+        let kept = bus.subscribe("kept", Topic::ALL, 4);
+        drop(bus.subscribe("gone", Topic::ALL, 4));
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(bus.publish(slot(1)), 1);
+        assert_eq!(kept.len(), 1);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_recv_across_tasks_and_close() {
+        let bus = EventBus::new();
+        let mut sub = bus.subscribe("consumer", &[Topic::Slot], 16);
+        let producer = {
+            let bus = bus.clone();
+            tokio::spawn(async move {
+                for n in 0..3 {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    bus.publish(slot(n));
+                }
+            })
+        };
+        for n in 0..3 {
+            assert_eq!(*sub.recv().await.unwrap(), slot(n));
+        }
+        producer.await.unwrap();
+        drop(bus);
+        assert_eq!(sub.recv().await, Err(RecvError::Closed));
+    }
+}
@@ -0,0 +1,197 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub type SharedClock = Arc<dyn Clock>;
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+//==========================================================================
+/// Source of "now" and of timers. Everything that measures latency, ages,
+/// backoff or stamps log lines goes through one of these so tests and
+/// replays can control time.
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Resolves once `duration` has passed on this clock.
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// Time since `earlier`, or zero if `earlier` is in the future.
+    fn since(&self, earlier: DateTime<Utc>) -> Duration {
+        (self.now() - earlier).to_std().unwrap_or_default()
+    }
+}
+
+//==========================================================================
+fn delta(duration: Duration) -> TimeDelta {
+    TimeDelta::from_std(duration).unwrap_or(TimeDelta::MAX)
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+//==========================================================================
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+//==========================================================================
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+//==========================================================================
+/// Time stands still until `advance` or `set` moves it. Sleepers wake as
+/// soon as the clock reaches their deadline.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    time: Arc<watch::Sender<DateTime<Utc>>>,
+}
+
+//==========================================================================
+impl ManualClock {
+    //==========================================================================
+    pub fn new(start: DateTime<Utc>) -> Self {
+        ManualClock {
+            time: Arc::new(watch::Sender::new(start)),
+        }
+    }
+
+    //==========================================================================
+    pub fn advance(&self, duration: Duration) {
+        self.time
+            .send_modify(|t| *t = t.checked_add_signed(delta(duration)).unwrap_or(*t));
+    }
+
+    //==========================================================================
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.time.send_replace(now);
+    }
+}
+
+//==========================================================================
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.time.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let deadline = self.now().checked_add_signed(delta(duration));
+        let mut rx = self.time.subscribe();
+        Box::pin(async move {
+            match deadline {
+                Some(deadline) => {
+                    let _ = rx.wait_for(|now| *now >= deadline).await;
+                }
+                None => std::future::pending().await,
+            }
+        })
+    }
+}
+
+//==========================================================================
+/// Clock for replaying a recorded session. The replay driver calls `until`
+/// with each recorded timestamp before handing the event on; with a speed
+/// set the call waits out the original gap (scaled), without one it jumps
+/// straight there. Timers on this clock fire as the replay reaches them.
+#[derive(Debug, Clone)]
+pub struct ReplayClock {
+    clock: ManualClock,
+    speed: Option<f64>,
+}
+
+//==========================================================================
+impl ReplayClock {
+    //==========================================================================
+    /// `speed` of `Some(1.0)` keeps the original timing, `Some(10.0)` runs ten
+    /// times faster and `None` runs as fast as events can be processed.
+    pub fn new(start: DateTime<Utc>, speed: Option<f64>) -> Self {
+        ReplayClock {
+            clock: ManualClock::new(start),
+            speed: speed.filter(|s| *s > 0.0),
+        }
+    }
+
+    //==========================================================================
+    /// Move the clock to the recorded time `at`. Never goes backwards.
+    pub async fn until(&self, at: DateTime<Utc>) {
+        let now = self.clock.now();
+        if at <= now {
+            return;
+        }
+        if let (Some(speed), Ok(gap)) = (self.speed, (at - now).to_std()) {
+            tokio::time::sleep(gap.div_f64(speed)).await;
+        }
+        self.clock.set(at);
+    }
+}
+
+//==========================================================================
+impl Clock for ReplayClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self.clock.sleep(duration)
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 12, 1, 0, 0, 0).unwrap()
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_manual_sleep_wakes_on_advance() {
+        let clock = ManualClock::new(start());
+        let sleeper = tokio::spawn(clock.sleep(Duration::from_secs(30)));
+        clock.advance(Duration::from_secs(29));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+        clock.advance(Duration::from_secs(1));
+        tokio::time::timeout(Duration::from_secs(1), sleeper)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(clock.since(start()), Duration::from_secs(30));
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_replay_keeps_original_gaps() {
+        let clock = ReplayClock::new(start(), Some(100.0));
+        let real = std::time::Instant::now();
+        clock.until(start() + TimeDelta::seconds(2)).await;
+        let waited = real.elapsed();
+        assert!(waited >= Duration::from_millis(20), "{:?}", waited);
+        assert!(waited < Duration::from_secs(2), "{:?}", waited);
+        assert_eq!(clock.now(), start() + TimeDelta::seconds(2));
+        // Going backwards is a no-op.
+        clock.until(start()).await;
+        assert_eq!(clock.now(), start() + TimeDelta::seconds(2));
+
+        let unpaced = ReplayClock::new(start(), None);
+        let real = std::time::Instant::now();
+        unpaced.until(start() + TimeDelta::hours(1)).await;
+        assert!(real.elapsed() < Duration::from_secs(1));
+        assert_eq!(unpaced.now(), start() + TimeDelta::hours(1));
+    }
+}
@@ -0,0 +1,989 @@
+use crate::error::{AtlasError, AtlasResult, ConfigProblem};
+use crate::secret::Secret;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::{Table, Value};
+
+pub const ENV_PREFIX: &str = "ATLAS_";
+pub const ENV_SEPARATOR: &str = "__";
+pub const ENV_NAME_VAR: &str = "ATLAS_ENV";
+pub const CONFIG_PATH_VAR: &str = "ATLAS_CONFIG";
+
+//==========================================================================
+/// Builds a single TOML tree out of ordered layers. Later layers win; tables
+/// are merged key by key, every other value is replaced whole.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigLoader {
+    merged: Table,
+    sources: Vec<String>,
+}
+
+//==========================================================================
+impl ConfigLoader {
+    //==========================================================================
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //==========================================================================
+    /// Add a TOML or JSON file (picked by extension) as the next layer.
+    pub fn with_file(mut self, path: impl AsRef<Path>) -> AtlasResult<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let table = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str::<Table>(&text)?,
+            _ => toml::from_str::<Table>(&text)?,
+        };
+        self.merge(table, path.display().to_string());
+        Ok(self)
+    }
+
+    //==========================================================================
+    /// Same as `with_file` but silently skips a file that does not exist.
+    pub fn with_optional_file(self, path: impl AsRef<Path>) -> AtlasResult<Self> {
+        if path.as_ref().exists() {
+            self.with_file(path)
+        } else {
+            Ok(self)
+        }
+    }
+
+    //==========================================================================
+    /// Layer `<stem>.<env>.<ext>` found next to `base` on top, if present.
+    /// e.g. `config/atlas.toml` + `prod` -> `config/atlas.prod.toml`.
+    pub fn with_environment(self, base: impl AsRef<Path>, env: &str) -> AtlasResult<Self> {
+        self.with_optional_file(overlay_path(base.as_ref(), env))
+    }
+
+    //==========================================================================
+    /// Apply `ATLAS_SECTION__KEY=value` overrides. Values are parsed as TOML
+    /// literals when possible (numbers, booleans, arrays) and fall back to a
+    /// plain string.
+    pub fn with_env_vars<I>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut overrides = Table::new();
+        let mut applied = false;
+        for (key, raw) in vars {
+            if key == ENV_NAME_VAR || key == CONFIG_PATH_VAR {
+                continue;
+            }
+            let Some(path) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let path: Vec<String> = path
+                .split(ENV_SEPARATOR)
+                .map(|s| s.to_ascii_lowercase())
+                .collect();
+            if path.iter().any(|s| s.is_empty()) {
+                continue;
+            }
+            insert_path(&mut overrides, &path, parse_env_value(&raw));
+            applied = true;
+        }
+        if applied {
+            self.merge(overrides, "environment".to_string());
+        }
+        self
+    }
+
+    //==========================================================================
+    pub fn with_process_env(self) -> Self {
+        self.with_env_vars(std::env::vars())
+    }
+
+    //==========================================================================
+    pub fn table(&self) -> &Table {
+        &self.merged
+    }
+
+    //==========================================================================
+    /// Descriptions of every layer applied so far, in order.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    //==========================================================================
+    /// Deserialize the whole merged tree into `T`.
+    pub fn extract<T: DeserializeOwned>(&self) -> AtlasResult<T> {
+        Ok(Value::Table(self.merged.clone()).try_into()?)
+    }
+
+    //==========================================================================
+    /// Deserialize a dotted sub-section of the merged tree, e.g.
+    /// `solana.ws`, into `T`.
+    pub fn extract_section<T: DeserializeOwned>(&self, section: &str) -> AtlasResult<T> {
+        let mut keys = section.split('.');
+        let first = keys.next().unwrap_or_default();
+        let mut value = self
+            .merged
+            .get(first)
+            .ok_or_else(|| AtlasError::config(section, "section is missing"))?;
+        let mut path = first.to_string();
+        for key in keys {
+            let Value::Table(table) = value else {
+                return Err(AtlasError::config(
+                    &path,
+                    format!("is a {}, not a table", value.type_str()),
+                ));
+            };
+            path = format!("{}.{}", path, key);
+            value = table
+                .get(key)
+                .ok_or_else(|| AtlasError::config(section, "section is missing"))?;
+        }
+        Ok(value.clone().try_into()?)
+    }
+
+    //==========================================================================
+    fn merge(&mut self, layer: Table, source: String) {
+        merge_tables(&mut self.merged, layer);
+        self.sources.push(source);
+    }
+}
+
+//==========================================================================
+fn merge_tables(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(incoming)) => {
+                merge_tables(existing, incoming)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+//==========================================================================
+fn insert_path(table: &mut Table, path: &[String], value: Value) {
+    let (head, rest) = path.split_first().expect("path is never empty");
+    if rest.is_empty() {
+        table.insert(head.clone(), value);
+        return;
+    }
+    let entry = table
+        .entry(head.clone())
+        .or_insert_with(|| Value::Table(Table::new()));
+    if !entry.is_table() {
+        *entry = Value::Table(Table::new());
+    }
+    if let Value::Table(inner) = entry {
+        insert_path(inner, rest, value);
+    }
+}
+
+//==========================================================================
+fn parse_env_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+//==========================================================================
+fn overlay_path(base: &Path, env: &str) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("config");
+    let name = match base.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}.{}.{}", stem, env, ext),
+        None => format!("{}.{}", stem, env),
+    };
+    base.with_file_name(name)
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SolanaConfig {
+    pub rpc_url: String,
+    pub ws_url: String,
+    pub commitment: String,
+    pub geyser: GeyserConfig,
+}
+
+//==========================================================================
+impl Default for SolanaConfig {
+    fn default() -> Self {
+        SolanaConfig {
+            rpc_url: "https://api.mainnet-beta.solana.com".to_string(),
+            ws_url: "wss://api.mainnet-beta.solana.com".to_string(),
+            commitment: "confirmed".to_string(),
+            geyser: GeyserConfig::default(),
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeyserConfig {
+    pub channel_size: usize,
+}
+
+//==========================================================================
+impl Default for GeyserConfig {
+    fn default() -> Self {
+        GeyserConfig {
+            channel_size: 10_000,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EthereumConfig {
+    pub https_url: String,
+    pub wss_url: String,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PythConfig {
+    pub https_url: String,
+    pub wss_url: String,
+    pub feed_batch_size: usize,
+    pub feed_ids: Vec<String>,
+}
+
+//==========================================================================
+impl Default for PythConfig {
+    fn default() -> Self {
+        PythConfig {
+            https_url: "https://hermes.pyth.network".to_string(),
+            wss_url: "wss://hermes.pyth.network/ws".to_string(),
+            feed_batch_size: 100,
+            feed_ids: Vec::new(),
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub data_dir: PathBuf,
+}
+
+//==========================================================================
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            data_dir: PathBuf::from("data"),
+        }
+    }
+}
+
+//==========================================================================
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertingConfig {
+    pub enabled: bool,
+    pub telegram: Option<TelegramAlertConfig>,
+    /// Repeats of an alert within this window are counted, not sent.
+    pub dedup_window_ms: u64,
+    /// Alerts sent per minute, critical ones excepted; the rest are dropped.
+    pub max_per_minute: u32,
+    /// Message templates by alert kind, e.g.
+    /// `stale_price = "{feed} has not updated for {age_s}s"`.
+    pub templates: HashMap<String, String>,
+}
+
+//==========================================================================
+impl Default for AlertingConfig {
+    fn default() -> Self {
+        AlertingConfig {
+            enabled: false,
+            telegram: None,
+            dedup_window_ms: 300_000,
+            max_per_minute: 20,
+            templates: HashMap::new(),
+        }
+    }
+}
+
+//==========================================================================
+/// Alerts at or above `min_severity` go to `chat_id`; each route adds
+/// another chat, e.g. an on-call group for critical alerts only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelegramAlertConfig {
+    /// Bot token, e.g. `token = { env = "TELEGRAM_BOT_TOKEN" }`.
+    #[serde(skip_serializing)]
+    pub token: Secret,
+    pub chat_id: String,
+    pub base_url: String,
+    pub min_severity: Severity,
+    pub routes: Vec<TelegramRoute>,
+}
+
+//==========================================================================
+impl Default for TelegramAlertConfig {
+    fn default() -> Self {
+        TelegramAlertConfig {
+            token: Secret::new(Vec::new()),
+            chat_id: String::new(),
+            base_url: "https://api.telegram.org".to_string(),
+            min_severity: Severity::Warning,
+            routes: Vec::new(),
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramRoute {
+    pub chat_id: String,
+    pub min_severity: Severity,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub level: String,
+    /// Per-module overrides, e.g. `"atlas_sol2::sol_block" = "debug"`.
+    pub modules: HashMap<String, String>,
+    pub format: LogFormat,
+    pub console: bool,
+    pub file: Option<LogFileConfig>,
+}
+
+//==========================================================================
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            modules: HashMap::new(),
+            format: LogFormat::Text,
+            console: true,
+            file: None,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    pub max_size_mb: u64,
+    pub max_age_hours: Option<u64>,
+    pub max_files: usize,
+}
+
+//==========================================================================
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        LogFileConfig {
+            path: PathBuf::from("logs/atlas.log"),
+            format: LogFormat::Json,
+            max_size_mb: 100,
+            max_age_hours: None,
+            max_files: 10,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub listen: String,
+}
+
+//==========================================================================
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: false,
+            listen: "127.0.0.1:9184".to_string(),
+        }
+    }
+}
+
+//==========================================================================
+/// `/healthz` and `/readyz`. A component stops being ready once it has gone
+/// longer than the matching threshold without a message, a new slot or a
+/// fresh price.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    pub listen: String,
+    pub message_stale_ms: u64,
+    pub slot_stale_ms: u64,
+    /// Measured from each feed's publish time.
+    pub price_stale_ms: u64,
+}
+
+//==========================================================================
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            enabled: false,
+            listen: "127.0.0.1:9185".to_string(),
+            message_stale_ms: 30_000,
+            slot_stale_ms: 30_000,
+            price_stale_ms: 60_000,
+        }
+    }
+}
+
+//==========================================================================
+/// Token buckets per endpoint. Keys are a full endpoint URL or just its host;
+/// endpoints not listed are not limited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub endpoints: HashMap<String, EndpointLimit>,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointLimit {
+    /// Budget refilled per second, in cost units.
+    pub per_second: f64,
+    /// Most budget that can build up while idle.
+    pub burst: f64,
+    /// Cost of a method not listed in `methods`.
+    pub default_cost: f64,
+    pub methods: HashMap<String, MethodLimit>,
+}
+
+//==========================================================================
+impl Default for EndpointLimit {
+    fn default() -> Self {
+        EndpointLimit {
+            per_second: 10.0,
+            burst: 20.0,
+            default_cost: 1.0,
+            methods: HashMap::new(),
+        }
+    }
+}
+
+//==========================================================================
+/// Weight of one method against its endpoint's budget, optionally with a
+/// bucket of its own on top, e.g. `getBlock = { cost = 10, per_second = 2 }`.
+/// Methods are JSON-RPC method names, or the request path for REST APIs
+/// such as Hermes (`"/v2/price_feeds"`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MethodLimit {
+    pub cost: f64,
+    pub per_second: Option<f64>,
+    /// Defaults to `per_second`.
+    pub burst: Option<f64>,
+}
+
+//==========================================================================
+impl Default for MethodLimit {
+    fn default() -> Self {
+        MethodLimit {
+            cost: 1.0,
+            per_second: None,
+            burst: None,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub attempt_timeout_ms: Option<u64>,
+    pub breaker_failure_threshold: u32,
+    pub breaker_reset_ms: u64,
+}
+
+//==========================================================================
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 5,
+            initial_backoff_ms: 200,
+            max_backoff_ms: 10_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            attempt_timeout_ms: Some(30_000),
+            breaker_failure_threshold: 10,
+            breaker_reset_ms: 30_000,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebSocketConfig {
+    pub ping_interval_ms: u64,
+    pub idle_timeout_ms: u64,
+    pub ack_timeout_ms: u64,
+    pub subscriber_buffer: usize,
+}
+
+//==========================================================================
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            ping_interval_ms: 15_000,
+            idle_timeout_ms: 60_000,
+            ack_timeout_ms: 10_000,
+            subscriber_buffer: 1_024,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    Never,
+    #[default]
+    OnFailure,
+    Always,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    pub restart: RestartPolicy,
+    /// Restarts allowed per task before it is reported as failed for good.
+    pub max_restarts: u32,
+    /// How long each shutdown stage may take before its tasks are aborted.
+    pub shutdown_timeout_ms: u64,
+}
+
+//==========================================================================
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        SupervisorConfig {
+            restart: RestartPolicy::OnFailure,
+            max_restarts: 5,
+            shutdown_timeout_ms: 10_000,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AtlasConfig {
+    pub solana: SolanaConfig,
+    pub ethereum: Option<EthereumConfig>,
+    pub pyth: PythConfig,
+    pub storage: StorageConfig,
+    pub alerting: AlertingConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub retry: RetryConfig,
+    pub websocket: WebSocketConfig,
+    pub supervisor: SupervisorConfig,
+}
+
+//==========================================================================
+impl AtlasConfig {
+    //==========================================================================
+    /// Load `path`, the overlay for `$ATLAS_ENV` next to it, then `ATLAS_*`
+    /// overrides from the process environment.
+    pub fn load(path: impl AsRef<Path>) -> AtlasResult<Self> {
+        Self::from_loader(&Self::loader(path)?)
+    }
+
+    //==========================================================================
+    /// Like `load`, taking the path from `$ATLAS_CONFIG`. With no file set only
+    /// defaults and environment overrides apply.
+    pub fn from_env() -> AtlasResult<Self> {
+        match std::env::var(CONFIG_PATH_VAR) {
+            Ok(path) => Self::load(path),
+            Err(_) => Self::from_loader(&ConfigLoader::new().with_process_env()),
+        }
+    }
+
+    //==========================================================================
+    /// The standard layer stack used by `load`, for callers that also want to
+    /// extract their own sections from it.
+    pub fn loader(path: impl AsRef<Path>) -> AtlasResult<ConfigLoader> {
+        let path = path.as_ref();
+        let mut loader = ConfigLoader::new().with_file(path)?;
+        if let Ok(env) = std::env::var(ENV_NAME_VAR) {
+            loader = loader.with_environment(path, &env)?;
+        }
+        Ok(loader.with_process_env())
+    }
+
+    //==========================================================================
+    pub fn from_loader(loader: &ConfigLoader) -> AtlasResult<Self> {
+        let config: AtlasConfig = loader.extract()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    //==========================================================================
+    /// Check every section and report all problems in one error.
+    pub fn validate(&self) -> AtlasResult<()> {
+        let mut problems = Vec::new();
+        check_url(
+            &mut problems,
+            "solana.rpc_url",
+            &self.solana.rpc_url,
+            &["http", "https"],
+        );
+        check_url(
+            &mut problems,
+            "solana.ws_url",
+            &self.solana.ws_url,
+            &["ws", "wss"],
+        );
+        if !["processed", "confirmed", "finalized"].contains(&self.solana.commitment.as_str()) {
+            problems.push(ConfigProblem::new(
+                "solana.commitment",
+                format!("unknown commitment `{}`", self.solana.commitment),
+            ));
+        }
+        if self.solana.geyser.channel_size == 0 {
+            problems.push(ConfigProblem::new(
+                "solana.geyser.channel_size",
+                "must be greater than zero",
+            ));
+        }
+        if let Some(eth) = &self.ethereum {
+            check_url(
+                &mut problems,
+                "ethereum.https_url",
+                &eth.https_url,
+                &["http", "https"],
+            );
+            check_url(
+                &mut problems,
+                "ethereum.wss_url",
+                &eth.wss_url,
+                &["ws", "wss"],
+            );
+        }
+        check_url(
+            &mut problems,
+            "pyth.https_url",
+            &self.pyth.https_url,
+            &["http", "https"],
+        );
+        check_url(
+            &mut problems,
+            "pyth.wss_url",
+            &self.pyth.wss_url,
+            &["ws", "wss"],
+        );
+        if self.pyth.feed_batch_size == 0 {
+            problems.push(ConfigProblem::new(
+                "pyth.feed_batch_size",
+                "must be greater than zero",
+            ));
+        }
+        if self.storage.data_dir.as_os_str().is_empty() {
+            problems.push(ConfigProblem::new("storage.data_dir", "is required"));
+        }
+        if self.alerting.enabled {
+            match &self.alerting.telegram {
+                None => problems.push(ConfigProblem::new(
+                    "alerting.telegram",
+                    "is required when alerting is enabled",
+                )),
+                Some(telegram) => {
+                    if telegram.token.expose().is_empty() {
+                        problems.push(ConfigProblem::new("alerting.telegram.token", "is required"));
+                    }
+                    if telegram.chat_id.is_empty() {
+                        problems.push(ConfigProblem::new(
+                            "alerting.telegram.chat_id",
+                            "is required",
+                        ));
+                    }
+                    check_url(
+                        &mut problems,
+                        "alerting.telegram.base_url",
+                        &telegram.base_url,
+                        &["http", "https"],
+                    );
+                }
+            }
+        }
+        if self.alerting.enabled && self.alerting.max_per_minute == 0 {
+            problems.push(ConfigProblem::new(
+                "alerting.max_per_minute",
+                "must be greater than zero",
+            ));
+        }
+        let levels = std::iter::once(("logging.level".to_string(), &self.logging.level)).chain(
+            self.logging
+                .modules
+                .iter()
+                .map(|(m, l)| (format!("logging.modules.{}", m), l)),
+        );
+        for (field, level) in levels {
+            if log::LevelFilter::from_str(level).is_err() {
+                problems.push(ConfigProblem::new(
+                    &field,
+                    format!("unknown log level `{}`", level),
+                ));
+            }
+        }
+        if let Some(file) = &self.logging.file {
+            if file.max_size_mb == 0 {
+                problems.push(ConfigProblem::new(
+                    "logging.file.max_size_mb",
+                    "must be greater than zero",
+                ));
+            }
+        }
+        if self.metrics.enabled && self.metrics.listen.parse::<std::net::SocketAddr>().is_err() {
+            problems.push(ConfigProblem::new(
+                "metrics.listen",
+                format!("`{}` is not a socket address", self.metrics.listen),
+            ));
+        }
+        if self.health.enabled && self.health.listen.parse::<std::net::SocketAddr>().is_err() {
+            problems.push(ConfigProblem::new(
+                "health.listen",
+                format!("`{}` is not a socket address", self.health.listen),
+            ));
+        }
+        for (field, value) in [
+            ("health.message_stale_ms", self.health.message_stale_ms),
+            ("health.slot_stale_ms", self.health.slot_stale_ms),
+            ("health.price_stale_ms", self.health.price_stale_ms),
+        ] {
+            if value == 0 {
+                problems.push(ConfigProblem::new(field, "must be greater than zero"));
+            }
+        }
+        for (endpoint, limit) in &self.rate_limit.endpoints {
+            let field = |key: &str| format!("rate_limit.endpoints.{}.{}", endpoint, key);
+            let mut positive = vec![
+                (field("per_second"), limit.per_second),
+                (field("burst"), limit.burst),
+            ];
+            for (method, m) in &limit.methods {
+                let field = |key: &str| field(&format!("methods.{}.{}", method, key));
+                positive.extend(m.per_second.map(|v| (field("per_second"), v)));
+                positive.extend(m.burst.map(|v| (field("burst"), v)));
+                if !(m.cost >= 0.0 && m.cost.is_finite()) {
+                    problems.push(ConfigProblem::new(&field("cost"), "must not be negative"));
+                }
+            }
+            if !(limit.default_cost >= 0.0 && limit.default_cost.is_finite()) {
+                problems.push(ConfigProblem::new(
+                    &field("default_cost"),
+                    "must not be negative",
+                ));
+            }
+            for (field, value) in positive {
+                if !(value > 0.0 && value.is_finite()) {
+                    problems.push(ConfigProblem::new(&field, "must be greater than zero"));
+                }
+            }
+        }
+        if self.retry.max_attempts == 0 {
+            problems.push(ConfigProblem::new(
+                "retry.max_attempts",
+                "must be at least one",
+            ));
+        }
+        if self.retry.multiplier < 1.0 {
+            problems.push(ConfigProblem::new(
+                "retry.multiplier",
+                "must be at least 1.0",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.retry.jitter) {
+            problems.push(ConfigProblem::new(
+                "retry.jitter",
+                "must be between 0.0 and 1.0",
+            ));
+        }
+        if self.websocket.ping_interval_ms == 0 {
+            problems.push(ConfigProblem::new(
+                "websocket.ping_interval_ms",
+                "must be greater than zero",
+            ));
+        }
+        if self.websocket.idle_timeout_ms <= self.websocket.ping_interval_ms {
+            problems.push(ConfigProblem::new(
+                "websocket.idle_timeout_ms",
+                "must be longer than websocket.ping_interval_ms",
+            ));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(AtlasError::ConfigValidation(problems))
+        }
+    }
+}
+
+//==========================================================================
+fn check_url(problems: &mut Vec<ConfigProblem>, field: &str, url: &str, schemes: &[&str]) {
+    if url.is_empty() {
+        problems.push(ConfigProblem::new(field, "is required"));
+        return;
+    }
+    let scheme = url.split_once("://").map(|(s, _)| s);
+    if !scheme.is_some_and(|s| schemes.contains(&s)) {
+        problems.push(ConfigProblem::new(
+            field,
+            format!("`{}` must use one of {:?}", url, schemes),
+        ));
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn write_tmp(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atlas-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_layers_merge_in_order() {
+        let base = write_tmp(
+            "atlas.toml",
+            r#"
+            [solana]
+            rpc_url = "https://base.example"
+            ws_url = "wss://base.example"
+
+            [pyth]
+            feed_batch_size = 10
+            "#,
+        );
+        write_tmp(
+            "atlas.prod.toml",
+            r#"
+            [solana]
+            rpc_url = "https://prod.example"
+            "#,
+        );
+        let loader = ConfigLoader::new()
+            .with_file(&base)
+            .unwrap()
+            .with_environment(&base, "prod")
+            .unwrap()
+            .with_env_vars(vars(&[
+                ("ATLAS_PYTH__FEED_BATCH_SIZE", "25"),
+                ("ATLAS_SOLANA__GEYSER__CHANNEL_SIZE", "64"),
+                ("ATLAS_ENV", "prod"),
+                ("HOME", "/root"),
+            ]));
+        let config = AtlasConfig::from_loader(&loader).unwrap();
+        assert_eq!(config.solana.rpc_url, "https://prod.example");
+        assert_eq!(config.solana.ws_url, "wss://base.example");
+        assert_eq!(config.pyth.feed_batch_size, 25);
+        assert_eq!(config.solana.geyser.channel_size, 64);
+        assert_eq!(loader.sources().len(), 3);
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_json_layer() {
+        let path = write_tmp(
+            "geyser.json",
+            r#"{"libpath": "libatlas_sol.so", "solana": {"geyser": {"channel_size": 128}}}"#,
+        );
+        let config =
+            AtlasConfig::from_loader(&ConfigLoader::new().with_file(path).unwrap()).unwrap();
+        assert_eq!(config.solana.geyser.channel_size, 128);
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let loader = ConfigLoader::new().with_env_vars(vars(&[
+            ("ATLAS_SOLANA__RPC_URL", "ftp://nope"),
+            ("ATLAS_SOLANA__COMMITMENT", "eventually"),
+            ("ATLAS_ALERTING__ENABLED", "true"),
+        ]));
+        match AtlasConfig::from_loader(&loader) {
+            Err(AtlasError::ConfigValidation(problems)) => {
+                let fields: Vec<&str> = problems.iter().map(|p| p.field.as_str()).collect();
+                assert_eq!(
+                    fields,
+                    vec!["solana.rpc_url", "solana.commitment", "alerting.telegram"]
+                );
+            }
+            other => panic!("expected validation error, got {:?}", other),
+        }
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_extract_section() {
+        let loader =
+            ConfigLoader::new().with_env_vars(vars(&[("ATLAS_SOLANA__GEYSER__CHANNEL_SIZE", "7")]));
+        let geyser: GeyserConfig = loader.extract_section("solana.geyser").unwrap();
+        assert_eq!(geyser.channel_size, 7);
+        assert!(loader.extract_section::<GeyserConfig>("missing").is_err());
+
+        // A scalar in the middle of the path is an error, not a skip.
+        let loader =
+            ConfigLoader::new().with_env_vars(vars(&[("ATLAS_SOLANA__RPC_URL", "https://node")]));
+        match loader.extract_section::<GeyserConfig>("solana.rpc_url.geyser") {
+            Err(AtlasError::Config { field, .. }) => assert_eq!(field, "solana.rpc_url"),
+            other => panic!("expected config error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,522 @@
+use crate::error::{AtlasError, AtlasResult};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+//==========================================================================
+fn pow10(exp: u32) -> Option<i128> {
+    10i128.checked_pow(exp)
+}
+
+//==========================================================================
+/// Exact base-10 fixed-point number: `mantissa * 10^-scale`. Equality,
+/// ordering and hashing compare values, so `1.50 == 1.5`. Arithmetic is
+/// checked and returns `None` on overflow rather than losing precision.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+//==========================================================================
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    //==========================================================================
+    pub const fn new(mantissa: i128, scale: u32) -> Self {
+        Decimal { mantissa, scale }
+    }
+
+    //==========================================================================
+    /// A raw on-chain amount with its mint decimals, e.g. `(1_500_000, 6)` is 1.5.
+    pub fn from_raw(amount: u64, decimals: u8) -> Self {
+        Decimal::new(amount as i128, decimals as u32)
+    }
+
+    //==========================================================================
+    /// A Pyth style `price * 10^expo`.
+    pub fn from_price(price: i64, expo: i32) -> AtlasResult<Self> {
+        if expo <= 0 {
+            return Ok(Decimal::new(price as i128, expo.unsigned_abs()));
+        }
+        pow10(expo as u32)
+            .and_then(|factor| (price as i128).checked_mul(factor))
+            .map(|mantissa| Decimal::new(mantissa, 0))
+            .ok_or_else(|| AtlasError::decode("decimal", format!("{}e{} overflows", price, expo)))
+    }
+
+    //==========================================================================
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    //==========================================================================
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    //==========================================================================
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    //==========================================================================
+    pub fn is_negative(&self) -> bool {
+        self.mantissa < 0
+    }
+
+    //==========================================================================
+    pub fn abs(&self) -> Self {
+        Decimal::new(self.mantissa.abs(), self.scale)
+    }
+
+    //==========================================================================
+    /// Same value with trailing fractional zeros removed.
+    pub fn normalize(&self) -> Self {
+        let mut d = *self;
+        if d.mantissa == 0 {
+            return Decimal::ZERO;
+        }
+        while d.scale > 0 && d.mantissa % 10 == 0 {
+            d.mantissa /= 10;
+            d.scale -= 1;
+        }
+        d
+    }
+
+    //==========================================================================
+    /// Same value at `scale`, or `None` if that would drop digits or overflow.
+    pub fn with_scale(&self, scale: u32) -> Option<Self> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Some(*self),
+            Ordering::Greater => {
+                let mantissa = self.mantissa.checked_mul(pow10(scale - self.scale)?)?;
+                Some(Decimal::new(mantissa, scale))
+            }
+            Ordering::Less => {
+                let factor = pow10(self.scale - scale);
+                match factor {
+                    Some(f) if self.mantissa % f == 0 => {
+                        Some(Decimal::new(self.mantissa / f, scale))
+                    }
+                    // A factor beyond i128 only divides zero.
+                    None if self.mantissa == 0 => Some(Decimal::new(0, scale)),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    //==========================================================================
+    /// Drop digits beyond `scale`, rounding toward zero.
+    pub fn trunc(&self, scale: u32) -> Self {
+        if scale >= self.scale {
+            return *self;
+        }
+        match pow10(self.scale - scale) {
+            Some(f) => Decimal::new(self.mantissa / f, scale),
+            None => Decimal::new(0, scale),
+        }
+    }
+
+    //==========================================================================
+    fn aligned(&self, other: &Self) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        let a = self.with_scale(scale)?.mantissa;
+        let b = other.with_scale(scale)?.mantissa;
+        Some((a, b, scale))
+    }
+
+    //==========================================================================
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let (a, b, scale) = self.aligned(other)?;
+        Some(Decimal::new(a.checked_add(b)?, scale))
+    }
+
+    //==========================================================================
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        let (a, b, scale) = self.aligned(other)?;
+        Some(Decimal::new(a.checked_sub(b)?, scale))
+    }
+
+    //==========================================================================
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa)?;
+        let scale = self.scale.checked_add(other.scale)?;
+        Some(Decimal::new(mantissa, scale))
+    }
+
+    //==========================================================================
+    /// `self / other` to `scale` fractional digits, rounded toward zero.
+    pub fn checked_div(&self, other: &Self, scale: u32) -> Option<Self> {
+        if other.mantissa == 0 {
+            return None;
+        }
+        // (a / 10^sa) / (b / 10^sb) * 10^scale = a * 10^(scale + sb - sa) / b
+        let shift = scale as i64 + other.scale as i64 - self.scale as i64;
+        let (numerator, denominator) = if shift >= 0 {
+            (
+                self.mantissa.checked_mul(pow10(shift as u32)?)?,
+                other.mantissa,
+            )
+        } else {
+            (
+                self.mantissa,
+                other.mantissa.checked_mul(pow10((-shift) as u32)?)?,
+            )
+        };
+        Some(Decimal::new(numerator / denominator, scale))
+    }
+
+    //==========================================================================
+    /// The raw integer amount at `decimals`, if exact and in range.
+    pub fn to_raw(&self, decimals: u8) -> Option<u64> {
+        u64::try_from(self.with_scale(decimals as u32)?.mantissa).ok()
+    }
+
+    //==========================================================================
+    /// Lossy; for display and metrics only.
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+}
+
+//==========================================================================
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+//==========================================================================
+impl Eq for Decimal {}
+
+//==========================================================================
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+//==========================================================================
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b) = (self.normalize(), other.normalize());
+        match a.aligned(&b) {
+            Some((x, y, _)) => x.cmp(&y),
+            // Rescaling only overflows for the operand with the larger
+            // magnitude, so its sign decides.
+            None if a.scale < b.scale => a.mantissa.signum().cmp(&0),
+            None => 0.cmp(&b.mantissa.signum()),
+        }
+    }
+}
+
+//==========================================================================
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let d = self.normalize();
+        d.mantissa.hash(state);
+        d.scale.hash(state);
+    }
+}
+
+//==========================================================================
+impl std::ops::Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(-self.mantissa, self.scale)
+    }
+}
+
+//==========================================================================
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        if digits.len() > scale {
+            let (int, frac) = digits.split_at(digits.len() - scale);
+            write!(f, "{}{}.{}", sign, int, frac)
+        } else {
+            write!(
+                f,
+                "{}0.{}{}",
+                sign,
+                "0".repeat(scale - digits.len()),
+                digits
+            )
+        }
+    }
+}
+
+//==========================================================================
+impl FromStr for Decimal {
+    type Err = AtlasError;
+
+    /// Plain decimal notation: optional sign, digits, optional fraction.
+    fn from_str(s: &str) -> AtlasResult<Self> {
+        let invalid = || AtlasError::decode("decimal", format!("invalid decimal {:?}", s));
+        let (negative, body) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (int, frac) = body.split_once('.').unwrap_or((body, ""));
+        if int.is_empty() && frac.is_empty() {
+            return Err(invalid());
+        }
+        if !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let mut mantissa: i128 = 0;
+        for b in int.bytes().chain(frac.bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add((b - b'0') as i128))
+                .ok_or_else(invalid)?;
+        }
+        let mantissa = if negative { -mantissa } else { mantissa };
+        Ok(Decimal::new(mantissa, frac.len() as u32))
+    }
+}
+
+//==========================================================================
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+//==========================================================================
+/// Accepts strings and integers. Floats are refused: by the time a value
+/// is an `f64` it may already have drifted.
+struct DecimalVisitor;
+
+//==========================================================================
+impl Visitor<'_> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal string or an integer")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Decimal, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Decimal, E> {
+        Ok(Decimal::new(v as i128, 0))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Decimal, E> {
+        Ok(Decimal::new(v as i128, 0))
+    }
+}
+
+//==========================================================================
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+//==========================================================================
+/// A raw SPL token amount and its mint decimals. Serializes in the RPC's
+/// `UiTokenAmount` shape, `{"amount": "1500000", "decimals": 6}`; extra
+/// fields such as `uiAmount` are ignored when reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenAmount {
+    #[serde(with = "amount_string")]
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+//==========================================================================
+impl TokenAmount {
+    //==========================================================================
+    pub fn new(amount: u64, decimals: u8) -> Self {
+        TokenAmount { amount, decimals }
+    }
+
+    //==========================================================================
+    /// Parse a UI amount such as `"1.5"`; fails if it has more fractional
+    /// digits than `decimals` allows.
+    pub fn from_ui_amount_string(s: &str, decimals: u8) -> AtlasResult<Self> {
+        let amount = s.parse::<Decimal>()?.to_raw(decimals).ok_or_else(|| {
+            AtlasError::decode(
+                "token amount",
+                format!("{} is not representable with {} decimals", s, decimals),
+            )
+        })?;
+        Ok(TokenAmount::new(amount, decimals))
+    }
+
+    //==========================================================================
+    pub fn to_decimal(&self) -> Decimal {
+        Decimal::from_raw(self.amount, self.decimals)
+    }
+
+    //==========================================================================
+    /// Same rendering as the RPC's `uiAmountString`.
+    pub fn ui_amount_string(&self) -> String {
+        self.to_decimal().normalize().to_string()
+    }
+
+    //==========================================================================
+    /// `None` on overflow or when the decimals differ.
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        (self.decimals == other.decimals)
+            .then(|| self.amount.checked_add(other.amount))
+            .flatten()
+            .map(|amount| TokenAmount::new(amount, self.decimals))
+    }
+
+    //==========================================================================
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        (self.decimals == other.decimals)
+            .then(|| self.amount.checked_sub(other.amount))
+            .flatten()
+            .map(|amount| TokenAmount::new(amount, self.decimals))
+    }
+}
+
+//==========================================================================
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.ui_amount_string())
+    }
+}
+
+//==========================================================================
+mod amount_string {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(amount: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(amount)
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Str(String),
+        Num(u64),
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        match Raw::deserialize(deserializer)? {
+            Raw::Str(s) => s.parse().map_err(de::Error::custom),
+            Raw::Num(n) => Ok(n),
+        }
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(d("123.4500").to_string(), "123.4500");
+        assert_eq!(d("-0.000012").to_string(), "-0.000012");
+        assert_eq!(d(".5").to_string(), "0.5");
+        assert_eq!(d("+7").to_string(), "7");
+        assert_eq!(d("123.4500").normalize().to_string(), "123.45");
+        for bad in ["", ".", "1.2.3", "1e5", "abc", "--1"] {
+            assert!(bad.parse::<Decimal>().is_err(), "{}", bad);
+        }
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_exact_arithmetic() {
+        assert_eq!(d("0.1").checked_add(&d("0.2")).unwrap(), d("0.3"));
+        assert_eq!(d("1.5").checked_sub(&d("2.25")).unwrap(), d("-0.75"));
+        assert_eq!(d("1.5").checked_mul(&d("-0.2")).unwrap(), d("-0.3"));
+        assert_eq!(
+            d("1").checked_div(&d("3"), 4).unwrap().to_string(),
+            "0.3333"
+        );
+        assert_eq!(d("2.5").checked_div(&d("0.05"), 0).unwrap(), d("50"));
+        assert!(d("1").checked_div(&Decimal::ZERO, 2).is_none());
+        let big = Decimal::new(i128::MAX, 0);
+        assert!(big.checked_add(&d("1")).is_none());
+        assert!(big.checked_mul(&d("2")).is_none());
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_ordering_across_scales() {
+        assert_eq!(d("1.50"), d("1.5"));
+        assert!(d("1.05") < d("1.5"));
+        assert!(d("-2") < d("-1.999"));
+        let huge = Decimal::new(i128::MAX, 0);
+        let tiny = Decimal::new(1, 30);
+        assert!(huge > tiny);
+        assert!(-huge < tiny);
+        let mut set = std::collections::HashSet::new();
+        set.insert(d("2.00"));
+        assert!(set.contains(&d("2")));
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_scaling() {
+        assert_eq!(Decimal::from_raw(1_500_000, 6), d("1.5"));
+        assert_eq!(Decimal::from_price(6_512_345, -5).unwrap(), d("65.12345"));
+        assert_eq!(Decimal::from_price(12, 3).unwrap(), d("12000"));
+        assert!(Decimal::from_price(i64::MAX, 30).is_err());
+        assert_eq!(d("1.5").to_raw(6), Some(1_500_000));
+        assert_eq!(d("1.0000001").to_raw(6), None);
+        assert_eq!(d("-1").to_raw(6), None);
+        assert_eq!(d("1.0000001").trunc(6), d("1"));
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_serde() {
+        let v: Decimal = serde_json::from_str("\"0.000001\"").unwrap();
+        assert_eq!(v, d("0.000001"));
+        let v: Decimal = serde_json::from_str("42").unwrap();
+        assert_eq!(v, d("42"));
+        assert!(serde_json::from_str::<Decimal>("0.1").is_err());
+        assert_eq!(serde_json::to_string(&d("1.50")).unwrap(), "\"1.50\"");
+
+        let ui =
+            r#"{"amount": "2500000", "decimals": 6, "uiAmount": 2.5, "uiAmountString": "2.5"}"#;
+        let amount: TokenAmount = serde_json::from_str(ui).unwrap();
+        assert_eq!(amount, TokenAmount::new(2_500_000, 6));
+        assert_eq!(amount.ui_amount_string(), "2.5");
+        assert_eq!(
+            serde_json::to_string(&amount).unwrap(),
+            r#"{"amount":"2500000","decimals":6}"#
+        );
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_token_amount() {
+        let a = TokenAmount::from_ui_amount_string("1.000001", 6).unwrap();
+        assert_eq!(a.amount, 1_000_001);
+        assert!(TokenAmount::from_ui_amount_string("1.0000001", 6).is_err());
+        let b = TokenAmount::new(999_999, 6);
+        assert_eq!(a.checked_sub(&b).unwrap().to_string(), "0.000002");
+        assert!(a.checked_add(&TokenAmount::new(1, 9)).is_none());
+        assert_eq!(TokenAmount::new(0, 9).ui_amount_string(), "0");
+    }
+}
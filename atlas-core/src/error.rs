@@ -1,9 +1,15 @@
-use serde::de::Error as SerdeError;
-use serde::ser::Error as SerdeSerError;
-use std::{fs, io};
+use std::io;
 use thiserror::Error;
 use tokio::task::JoinError;
-use toml;
+
+//==========================================================================
+/// Whether an error is worth retrying (reconnect, resend) or should abort
+/// the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    Retryable,
+    Fatal,
+}
 
 //==========================================================================
 #[derive(Error, Debug)]
@@ -14,15 +20,274 @@ pub enum AtlasError {
     #[error("TOML deserialization error: {0}")]
     Toml(#[from] toml::de::Error),
 
-    #[error("TOML deserialization error: {0}")]
+    #[error("Logger error: {0}")]
     Log(#[from] log::SetLoggerError),
 
     #[error("Tokio task join error: {0}")]
     JoinError(JoinError),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("RPC error from {endpoint} calling {method}{}: {message}", fmt_code(.code))]
+    Rpc {
+        endpoint: String,
+        method: String,
+        code: Option<i64>,
+        message: String,
+    },
+
+    #[error("HTTP {status} from {endpoint}: {message}")]
+    Http {
+        endpoint: String,
+        status: u16,
+        message: String,
+    },
+
+    #[error("Transport error talking to {endpoint}: {message}")]
+    Transport { endpoint: String, message: String },
+
+    #[error("WebSocket error on {endpoint}: {message}")]
+    WebSocket { endpoint: String, message: String },
+
+    #[error("Timed out after {elapsed_ms}ms waiting on {endpoint} ({operation})")]
+    Timeout {
+        endpoint: String,
+        operation: String,
+        elapsed_ms: u64,
+    },
+
+    #[error("Circuit breaker open for {endpoint}")]
+    CircuitOpen { endpoint: String },
+
+    #[error("Protocol error from {endpoint}: {message}")]
+    Protocol { endpoint: String, message: String },
+
+    #[error("Failed to decode {what}{}{}: {message}", fmt_slot(.slot), fmt_signature(.signature))]
+    Decode {
+        what: String,
+        slot: Option<u64>,
+        signature: Option<String>,
+        message: String,
+    },
+
+    #[error("Invalid config `{field}`: {message}")]
+    Config { field: String, message: String },
+
+    #[error("Config validation failed: {}", fmt_problems(.0))]
+    ConfigValidation(Vec<ConfigProblem>),
+
+    #[error("Failed to load secret from {origin}: {message}")]
+    Secret { origin: String, message: String },
+
+    #[error("{0}")]
+    CustomError(String),
 }
 
 //==========================================================================
-impl AtlasError {}
+/// A single validation failure, reported together with its siblings through
+/// `AtlasError::ConfigValidation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub field: String,
+    pub message: String,
+}
+
+//==========================================================================
+impl ConfigProblem {
+    pub fn new(field: &str, message: impl ToString) -> Self {
+        ConfigProblem {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+//==========================================================================
+impl std::fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` {}", self.field, self.message)
+    }
+}
+
+//==========================================================================
+fn fmt_problems(problems: &[ConfigProblem]) -> String {
+    problems
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+//==========================================================================
+fn fmt_code(code: &Option<i64>) -> String {
+    code.map(|c| format!(" (code {})", c)).unwrap_or_default()
+}
+
+//==========================================================================
+fn fmt_slot(slot: &Option<u64>) -> String {
+    slot.map(|s| format!(" at slot {}", s)).unwrap_or_default()
+}
+
+//==========================================================================
+fn fmt_signature(signature: &Option<String>) -> String {
+    signature
+        .as_ref()
+        .map(|s| format!(" in tx {}", s))
+        .unwrap_or_default()
+}
+
+//==========================================================================
+// JSON-RPC error codes that indicate a transient node condition, e.g. the
+// node is behind or the block is not yet available.
+const RETRYABLE_RPC_CODES: &[i64] = &[
+    -32603, // internal error
+    -32004, // block not available
+    -32005, // node unhealthy / behind
+    -32014, // block status not yet available
+    -32016, // min context slot not reached
+];
+
+//==========================================================================
+/// JSON-RPC code embedded in an error string, as clients render it:
+/// `"code":-32005`, `code: -32005` or `(code -32005)`.
+fn parse_rpc_code(message: &str) -> Option<i64> {
+    let (_, rest) = message.split_once("code")?;
+    let rest = rest.trim_start_matches(|c: char| c == '"' || c == ':' || c.is_whitespace());
+    let end = rest
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+        .map_or(rest.len(), |(i, _)| i);
+    rest[..end].parse().ok()
+}
+
+//==========================================================================
+impl AtlasError {
+    //==========================================================================
+    /// An error the node answered with. Clients that only hand over a string
+    /// usually embed the JSON-RPC code in it; it is recovered so the error
+    /// classifies, and left `None` (fatal) otherwise.
+    pub fn rpc(endpoint: &str, method: &str, message: impl ToString) -> Self {
+        let message = message.to_string();
+        AtlasError::Rpc {
+            endpoint: endpoint.to_string(),
+            method: method.to_string(),
+            code: parse_rpc_code(&message),
+            message,
+        }
+    }
+
+    //==========================================================================
+    /// The request never got an answer: connection refused or reset, DNS,
+    /// TLS.
+    pub fn transport(endpoint: &str, message: impl ToString) -> Self {
+        AtlasError::Transport {
+            endpoint: endpoint.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    //==========================================================================
+    pub fn websocket(endpoint: &str, message: impl ToString) -> Self {
+        AtlasError::WebSocket {
+            endpoint: endpoint.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    //==========================================================================
+    pub fn protocol(endpoint: &str, message: impl ToString) -> Self {
+        AtlasError::Protocol {
+            endpoint: endpoint.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    //==========================================================================
+    pub fn decode(what: &str, message: impl ToString) -> Self {
+        AtlasError::Decode {
+            what: what.to_string(),
+            slot: None,
+            signature: None,
+            message: message.to_string(),
+        }
+    }
+
+    //==========================================================================
+    pub fn config(field: &str, message: impl ToString) -> Self {
+        AtlasError::Config {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    //==========================================================================
+    /// Attach a slot to a decode error; other variants are returned unchanged.
+    pub fn at_slot(mut self, at: u64) -> Self {
+        if let AtlasError::Decode { slot, .. } = &mut self {
+            *slot = Some(at);
+        }
+        self
+    }
+
+    //==========================================================================
+    /// Attach a transaction signature to a decode error; other variants are
+    /// returned unchanged.
+    pub fn in_tx(mut self, sig: impl ToString) -> Self {
+        if let AtlasError::Decode { signature, .. } = &mut self {
+            *signature = Some(sig.to_string());
+        }
+        self
+    }
+
+    //==========================================================================
+    pub fn class(&self) -> ErrorClass {
+        let retryable = match self {
+            AtlasError::Io(err) => matches!(
+                err.kind(),
+                io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof
+            ),
+            AtlasError::Rpc { code, .. } => code.is_some_and(|c| RETRYABLE_RPC_CODES.contains(&c)),
+            AtlasError::Http { status, .. } => *status == 408 || *status == 429 || *status >= 500,
+            AtlasError::Transport { .. }
+            | AtlasError::WebSocket { .. }
+            | AtlasError::Timeout { .. }
+            | AtlasError::CircuitOpen { .. } => true,
+            AtlasError::JoinError(err) => err.is_cancelled(),
+            AtlasError::Toml(_)
+            | AtlasError::Log(_)
+            | AtlasError::Json(_)
+            | AtlasError::Protocol { .. }
+            | AtlasError::Decode { .. }
+            | AtlasError::Config { .. }
+            | AtlasError::ConfigValidation(_)
+            | AtlasError::Secret { .. }
+            | AtlasError::CustomError(_) => false,
+        };
+        if retryable {
+            ErrorClass::Retryable
+        } else {
+            ErrorClass::Fatal
+        }
+    }
+
+    //==========================================================================
+    pub fn is_retryable(&self) -> bool {
+        self.class() == ErrorClass::Retryable
+    }
+
+    //==========================================================================
+    pub fn is_fatal(&self) -> bool {
+        self.class() == ErrorClass::Fatal
+    }
+}
 
 //==========================================================================
 impl From<JoinError> for AtlasError {
@@ -33,3 +298,115 @@ impl From<JoinError> for AtlasError {
 
 //==========================================================================
 pub type AtlasResult<T> = Result<T, AtlasError>;
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //==========================================================================
+    #[test]
+    fn test_classification() {
+        let err = AtlasError::websocket("wss://node", "connection reset");
+        assert!(err.is_retryable());
+
+        let err = AtlasError::Http {
+            endpoint: "https://hermes".to_string(),
+            status: 429,
+            message: "too many requests".to_string(),
+        };
+        assert!(err.is_retryable());
+
+        let err = AtlasError::Http {
+            endpoint: "https://hermes".to_string(),
+            status: 404,
+            message: "not found".to_string(),
+        };
+        assert!(err.is_fatal());
+
+        let err = AtlasError::Rpc {
+            endpoint: "https://node".to_string(),
+            method: "getBlock".to_string(),
+            code: Some(-32007),
+            message: "slot skipped".to_string(),
+        };
+        assert!(err.is_fatal());
+
+        let err = AtlasError::Rpc {
+            endpoint: "https://node".to_string(),
+            method: "getBlock".to_string(),
+            code: Some(-32004),
+            message: "block not available".to_string(),
+        };
+        assert!(err.is_retryable());
+
+        let err = AtlasError::Io(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(err.is_retryable());
+        let err = AtlasError::Io(io::Error::from(io::ErrorKind::NotFound));
+        assert!(err.is_fatal());
+
+        assert!(AtlasError::config("solana.rpc_url", "missing").is_fatal());
+        assert!(AtlasError::transport("https://node", "connection refused").is_retryable());
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_rpc_code_from_message() {
+        let err = AtlasError::rpc(
+            "https://node",
+            "getBlock",
+            r#"{"code":-32005,"message":"Node is behind by 42 slots"}"#,
+        );
+        assert!(matches!(
+            err,
+            AtlasError::Rpc {
+                code: Some(-32005),
+                ..
+            }
+        ));
+        assert!(err.is_retryable());
+
+        let err = AtlasError::rpc(
+            "https://node",
+            "getBlock",
+            "RPC response error -32602: code: -32602 invalid params",
+        );
+        assert!(matches!(
+            err,
+            AtlasError::Rpc {
+                code: Some(-32602),
+                ..
+            }
+        ));
+        assert!(err.is_fatal());
+
+        // Without a code there is no telling a transient failure from a bad
+        // request, so it is not retried.
+        let err = AtlasError::rpc("https://node", "getBlock", "method not found");
+        assert!(matches!(err, AtlasError::Rpc { code: None, .. }));
+        assert!(err.is_fatal());
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_context_in_message() {
+        let err = AtlasError::decode("transaction", "bad length")
+            .at_slot(42)
+            .in_tx("5abc");
+        assert_eq!(
+            err.to_string(),
+            "Failed to decode transaction at slot 42 in tx 5abc: bad length"
+        );
+
+        let err = AtlasError::Rpc {
+            endpoint: "https://node".to_string(),
+            method: "getSlot".to_string(),
+            code: Some(-32005),
+            message: "node is behind".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "RPC error from https://node calling getSlot (code -32005): node is behind"
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use crate::clock::{self, SharedClock};
+use crate::config::HealthConfig;
+use crate::error::AtlasResult;
+use crate::http::{self, HttpResponse};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::info;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy)]
+struct Thresholds {
+    message: Duration,
+    slot: Duration,
+    price: Duration,
+}
+
+//==========================================================================
+#[derive(Debug, Default)]
+struct ComponentState {
+    connection: ConnectionState,
+    /// When set, wins over `connection`; e.g. a `WsClient`'s live state.
+    connection_source: Option<watch::Receiver<ConnectionState>>,
+    last_message: Option<DateTime<Utc>>,
+    /// Slot and when it last advanced.
+    last_slot: Option<(u64, DateTime<Utc>)>,
+    /// Publish time of the latest price, per feed.
+    prices: BTreeMap<String, DateTime<Utc>>,
+}
+
+//==========================================================================
+/// Components register here and report as they go; `report` judges them
+/// against the configured staleness thresholds.
+#[derive(Debug, Clone)]
+pub struct HealthRegistry {
+    components: Arc<Mutex<BTreeMap<String, ComponentState>>>,
+    thresholds: Thresholds,
+    clock: SharedClock,
+}
+
+//==========================================================================
+impl HealthRegistry {
+    //==========================================================================
+    pub fn new(config: &HealthConfig) -> Self {
+        HealthRegistry {
+            components: Arc::default(),
+            thresholds: Thresholds {
+                message: Duration::from_millis(config.message_stale_ms),
+                slot: Duration::from_millis(config.slot_stale_ms),
+                price: Duration::from_millis(config.price_stale_ms),
+            },
+            clock: clock::system(),
+        }
+    }
+
+    //==========================================================================
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    //==========================================================================
+    /// Register `name`, or get another handle to it if already registered.
+    pub fn component(&self, name: &str) -> ComponentHealth {
+        self.components
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default();
+        ComponentHealth {
+            target: Some((self.clone(), name.to_string())),
+        }
+    }
+
+    //==========================================================================
+    pub fn report(&self) -> HealthReport {
+        let now = self.clock.now();
+        let components = self.components.lock().unwrap();
+        let components: BTreeMap<_, _> = components
+            .iter()
+            .map(|(name, state)| (name.clone(), self.judge(state, now)))
+            .collect();
+        HealthReport {
+            ready: components.values().all(|c| c.ready),
+            checked_at: timestamp(now),
+            components,
+        }
+    }
+
+    //==========================================================================
+    fn judge(&self, state: &ComponentState, now: DateTime<Utc>) -> ComponentReport {
+        let age = |at: DateTime<Utc>| (now - at).to_std().unwrap_or_default();
+        let mut problems = Vec::new();
+        let connection = match &state.connection_source {
+            Some(source) => *source.borrow(),
+            None => state.connection,
+        };
+        if connection != ConnectionState::Connected {
+            problems.push(format!("connection is {:?}", connection).to_lowercase());
+        }
+        let last_message_age = state.last_message.map(age);
+        match last_message_age {
+            None => problems.push("no messages received yet".to_string()),
+            Some(a) if a > self.thresholds.message => {
+                problems.push(format!("no message for {}ms", a.as_millis()))
+            }
+            Some(_) => {}
+        }
+        let last_slot_age = state.last_slot.map(|(_, at)| age(at));
+        if let (Some((slot, _)), Some(a)) = (state.last_slot, last_slot_age) {
+            if a > self.thresholds.slot {
+                problems.push(format!(
+                    "slot {} has not advanced for {}ms",
+                    slot,
+                    a.as_millis()
+                ));
+            }
+        }
+        let prices = state
+            .prices
+            .iter()
+            .map(|(feed, published)| {
+                let a = age(*published);
+                let stale = a > self.thresholds.price;
+                if stale {
+                    problems.push(format!("price {} is {}ms old", feed, a.as_millis()));
+                }
+                let report = FeedReport {
+                    publish_time: timestamp(*published),
+                    age_ms: a.as_millis() as u64,
+                    stale,
+                };
+                (feed.clone(), report)
+            })
+            .collect();
+        ComponentReport {
+            ready: problems.is_empty(),
+            connection,
+            last_message: state.last_message.map(timestamp),
+            last_message_age_ms: last_message_age.map(|a| a.as_millis() as u64),
+            last_slot: state.last_slot.map(|(slot, _)| slot),
+            last_slot_age_ms: last_slot_age.map(|a| a.as_millis() as u64),
+            prices,
+            problems,
+        }
+    }
+}
+
+//==========================================================================
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+//==========================================================================
+/// Handle a component reports through. The default handle is detached and
+/// ignores every update, so clients without a registry need no special case.
+#[derive(Debug, Clone, Default)]
+pub struct ComponentHealth {
+    target: Option<(HealthRegistry, String)>,
+}
+
+//==========================================================================
+impl ComponentHealth {
+    //==========================================================================
+    fn update(&self, f: impl FnOnce(&mut ComponentState, DateTime<Utc>)) {
+        if let Some((registry, name)) = &self.target {
+            let now = registry.clock.now();
+            let mut components = registry.components.lock().unwrap();
+            f(components.entry(name.clone()).or_default(), now);
+        }
+    }
+
+    //==========================================================================
+    pub fn set_connection(&self, state: ConnectionState) {
+        self.update(|c, _| c.connection = state);
+    }
+
+    //==========================================================================
+    /// Take the connection state from `source` from now on.
+    pub fn follow_connection(&self, source: watch::Receiver<ConnectionState>) {
+        self.update(|c, _| c.connection_source = Some(source));
+    }
+
+    //==========================================================================
+    pub fn message(&self) {
+        self.update(|c, now| c.last_message = Some(now));
+    }
+
+    //==========================================================================
+    /// Only a higher slot counts as progress.
+    pub fn slot(&self, slot: u64) {
+        self.update(|c, now| match c.last_slot {
+            Some((last, _)) if last >= slot => {}
+            _ => c.last_slot = Some((slot, now)),
+        });
+    }
+
+    //==========================================================================
+    /// `publish_time` in unix seconds, as Pyth reports it.
+    pub fn price(&self, feed: &str, publish_time: i64) {
+        let published = DateTime::from_timestamp(publish_time, 0).unwrap_or_default();
+        self.update(|c, _| {
+            c.prices.insert(feed.to_string(), published);
+        });
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub ready: bool,
+    pub checked_at: String,
+    pub components: BTreeMap<String, ComponentReport>,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentReport {
+    pub ready: bool,
+    pub connection: ConnectionState,
+    pub last_message: Option<String>,
+    pub last_message_age_ms: Option<u64>,
+    pub last_slot: Option<u64>,
+    pub last_slot_age_ms: Option<u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub prices: BTreeMap<String, FeedReport>,
+    pub problems: Vec<String>,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedReport {
+    pub publish_time: String,
+    pub age_ms: u64,
+    pub stale: bool,
+}
+
+//==========================================================================
+/// Serve `/healthz` and `/readyz` for `registry` on `listen` until the task
+/// is dropped.
+pub async fn serve(listen: &str, registry: HealthRegistry) -> AtlasResult<()> {
+    let listener = http::bind(listen).await?;
+    info!(endpoint = listen; "Health endpoint listening");
+    serve_listener(listener, registry).await
+}
+
+//==========================================================================
+/// `/healthz` answers 200 while the process is serving at all; `/readyz`
+/// answers 503 while any component is not ready. Both carry the full report.
+pub async fn serve_listener(
+    listener: tokio::net::TcpListener,
+    registry: HealthRegistry,
+) -> AtlasResult<()> {
+    http::serve(listener, move |path| {
+        let report = registry.report();
+        let status = match path {
+            "/healthz" => 200,
+            "/readyz" if report.ready => 200,
+            "/readyz" => 503,
+            _ => return HttpResponse::not_found(),
+        };
+        HttpResponse {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(&report).unwrap_or_default(),
+        }
+    })
+    .await
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, ManualClock};
+
+    fn registry() -> (HealthRegistry, ManualClock) {
+        let clock = ManualClock::new("2024-12-01T00:00:00Z".parse().unwrap());
+        let registry =
+            HealthRegistry::new(&HealthConfig::default()).with_clock(Arc::new(clock.clone()));
+        (registry, clock)
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_staleness_flips_readiness() {
+        let (registry, clock) = registry();
+        let blocks = registry.component("blocks");
+        assert!(!registry.report().ready);
+
+        blocks.set_connection(ConnectionState::Connected);
+        blocks.message();
+        blocks.slot(100);
+        assert!(registry.report().ready);
+
+        // Messages keep coming but the slot is stuck.
+        clock.advance(Duration::from_secs(20));
+        blocks.message();
+        blocks.slot(100);
+        clock.advance(Duration::from_secs(15));
+        blocks.message();
+        let report = registry.report();
+        assert!(!report.ready);
+        assert_eq!(report.components["blocks"].last_slot_age_ms, Some(35_000));
+        assert!(report.components["blocks"].problems[0].contains("slot 100"));
+
+        blocks.slot(101);
+        assert!(registry.report().ready);
+
+        let (tx, rx) = watch::channel(ConnectionState::Connected);
+        blocks.follow_connection(rx);
+        tx.send_replace(ConnectionState::Disconnected);
+        let report = registry.report();
+        assert!(!report.ready);
+        assert_eq!(
+            report.components["blocks"].connection,
+            ConnectionState::Disconnected
+        );
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_stale_price_feed() {
+        let (registry, clock) = registry();
+        let prices = registry.component("pyth");
+        prices.set_connection(ConnectionState::Connected);
+        prices.message();
+        let published = clock.now().timestamp();
+        prices.price("eth", published);
+        prices.price("sol", published);
+        clock.advance(Duration::from_secs(50));
+        prices.message();
+        prices.price("sol", clock.now().timestamp());
+        assert!(registry.report().ready);
+
+        clock.advance(Duration::from_secs(20));
+        prices.message();
+        let report = registry.report();
+        let pyth = &report.components["pyth"];
+        assert!(!report.ready);
+        assert!(pyth.prices["eth"].stale);
+        assert!(!pyth.prices["sol"].stale);
+        assert_eq!(pyth.prices["eth"].age_ms, 70_000);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_health_endpoints() {
+        let (registry, _) = registry();
+        let listener = http::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(listener, registry.clone()));
+        let component = registry.component("collector");
+
+        let (status, body) = http::get(addr, "/readyz").await;
+        assert_eq!(status, 503);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["components"]["collector"]["connection"], "connecting");
+        let (status, _) = http::get(addr, "/healthz").await;
+        assert_eq!(status, 200);
+
+        component.set_connection(ConnectionState::Connected);
+        component.message();
+        let (status, _) = http::get(addr, "/readyz").await;
+        assert_eq!(status, 200);
+        let (status, _) = http::get(addr, "/nope").await;
+        assert_eq!(status, 404);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::error::AtlasResult;
+use log::{debug, error};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+//==========================================================================
+/// Response from a handler served by `serve`. Only what the metrics and
+/// health endpoints need: a status, a content type and a body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+//==========================================================================
+impl HttpResponse {
+    //==========================================================================
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        HttpResponse {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    //==========================================================================
+    pub fn not_found() -> Self {
+        HttpResponse {
+            status: 404,
+            content_type: "text/plain",
+            body: "not found\n".to_string(),
+        }
+    }
+
+    //==========================================================================
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            503 => "Service Unavailable",
+            _ => "",
+        }
+    }
+}
+
+//==========================================================================
+pub async fn bind(addr: &str) -> AtlasResult<TcpListener> {
+    Ok(TcpListener::bind(addr).await?)
+}
+
+//==========================================================================
+/// Accept connections forever, answering each GET with `handler(path)`.
+/// Connections are closed after one response.
+pub async fn serve<F>(listener: TcpListener, handler: F) -> AtlasResult<()>
+where
+    F: Fn(&str) -> HttpResponse + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, peer, handler.as_ref()).await {
+                error!("HTTP connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+//==========================================================================
+async fn handle<F>(mut stream: TcpStream, peer: SocketAddr, handler: &F) -> AtlasResult<()>
+where
+    F: Fn(&str) -> HttpResponse,
+{
+    let mut reader = BufReader::new(&mut stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Drain headers; nothing we serve needs them.
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or("/");
+    let path = path.split('?').next().unwrap_or(path);
+    debug!("HTTP {} {} from {}", method, path, peer);
+    let response = if method == "GET" {
+        handler(path)
+    } else {
+        HttpResponse {
+            status: 405,
+            content_type: "text/plain",
+            body: "method not allowed\n".to_string(),
+        }
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+//==========================================================================
+/// Minimal client used by tests of the servers built on `serve`.
+#[cfg(test)]
+pub(crate) async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+    use tokio::io::AsyncReadExt;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).await.unwrap();
+    let status = raw
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+    let body = raw
+        .split_once("\r\n\r\n")
+        .map(|(_, b)| b)
+        .unwrap_or_default();
+    (status, body.to_string())
+}
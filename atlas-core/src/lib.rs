@@ -1,2 +1,16 @@
+pub mod alert;
+pub mod bus;
+pub mod clock;
+pub mod config;
+pub mod decimal;
 pub mod error;
+pub mod health;
+pub mod http;
+pub mod logging;
+pub mod metrics;
+pub mod ratelimit;
+pub mod retry;
+pub mod secret;
+pub mod supervisor;
 pub mod util;
+pub mod ws;
@@ -0,0 +1,370 @@
+use crate::clock::{self, Clock, SharedClock};
+use crate::config::{LogFileConfig, LogFormat, LoggingConfig};
+use crate::error::{AtlasError, AtlasResult};
+use chrono::Local;
+use log::kv::{self, VisitSource};
+use log::{LevelFilter, Record};
+use serde_json::{Map, Value};
+use std::fmt::Arguments;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+//==========================================================================
+/// Build the fern dispatch described by `config`. Installing it is left to
+/// the caller so the dispatch can be inspected in tests.
+pub fn build_dispatch(config: &LoggingConfig) -> AtlasResult<fern::Dispatch> {
+    build_dispatch_with_clock(config, clock::system())
+}
+
+//==========================================================================
+/// As `build_dispatch`, with log timestamps and file rotation taken from
+/// `clock`.
+pub fn build_dispatch_with_clock(
+    config: &LoggingConfig,
+    clock: SharedClock,
+) -> AtlasResult<fern::Dispatch> {
+    let mut root = fern::Dispatch::new().level(parse_level("logging.level", &config.level)?);
+    let mut modules: Vec<_> = config.modules.iter().collect();
+    modules.sort();
+    for (module, level) in modules {
+        let field = format!("logging.modules.{}", module);
+        root = root.level_for(module.clone(), parse_level(&field, level)?);
+    }
+    if config.console {
+        root = root.chain(
+            formatted(config.format, clock.clone())
+                .chain(Box::new(io::stderr()) as Box<dyn Write + Send>),
+        );
+    }
+    if let Some(file) = &config.file {
+        let writer = RotatingFileWriter::open(file)?.with_clock(clock.clone());
+        root = root.chain(
+            formatted(file.format, clock).chain(fern::Output::writer(Box::new(writer), "\n")),
+        );
+    }
+    Ok(root)
+}
+
+//==========================================================================
+fn formatted(format: LogFormat, clock: SharedClock) -> fern::Dispatch {
+    fern::Dispatch::new().format(move |out, message, record| match format {
+        LogFormat::Text => out.finish(format_args!(
+            "{}",
+            render_text(clock.as_ref(), message, record)
+        )),
+        LogFormat::Json => out.finish(format_args!(
+            "{}",
+            render_json(clock.as_ref(), message, record)
+        )),
+    })
+}
+
+//==========================================================================
+fn parse_level(field: &str, level: &str) -> AtlasResult<LevelFilter> {
+    LevelFilter::from_str(level)
+        .map_err(|_| AtlasError::config(field, format!("unknown log level `{}`", level)))
+}
+
+//==========================================================================
+/// Human readable line, with any structured fields appended as `key=value`.
+pub fn render_text(clock: &dyn Clock, message: &Arguments, record: &Record) -> String {
+    let mut line = format!(
+        "{} [{} {}:{}] {}: {}",
+        clock.now().with_timezone(&Local).format("[%H:%M:%S.%3f]"),
+        record.level(),
+        record.file().unwrap_or("<unknown>"),
+        record.line().unwrap_or(0),
+        record.target(),
+        message
+    );
+    for (key, value) in collect_fields(record) {
+        match value {
+            Value::String(s) => line.push_str(&format!(" {}={}", key, s)),
+            other => line.push_str(&format!(" {}={}", key, other)),
+        }
+    }
+    line
+}
+
+//==========================================================================
+/// One JSON object per line. Structured fields passed with
+/// `info!(slot = 1, endpoint = url; "...")` become top-level keys.
+pub fn render_json(clock: &dyn Clock, message: &Arguments, record: &Record) -> String {
+    let mut object = Map::new();
+    object.insert("ts".into(), Value::String(clock.now().to_rfc3339()));
+    object.insert("level".into(), Value::String(record.level().to_string()));
+    object.insert("target".into(), Value::String(record.target().to_string()));
+    if let Some(file) = record.file() {
+        object.insert("file".into(), Value::String(file.to_string()));
+    }
+    if let Some(line) = record.line() {
+        object.insert("line".into(), Value::from(line));
+    }
+    object.insert("msg".into(), Value::String(message.to_string()));
+    for (key, value) in collect_fields(record) {
+        object.entry(key).or_insert(value);
+    }
+    Value::Object(object).to_string()
+}
+
+//==========================================================================
+fn collect_fields(record: &Record) -> Vec<(String, Value)> {
+    struct Collector(Vec<(String, Value)>);
+
+    impl<'kvs> VisitSource<'kvs> for Collector {
+        fn visit_pair(
+            &mut self,
+            key: kv::Key<'kvs>,
+            value: kv::Value<'kvs>,
+        ) -> Result<(), kv::Error> {
+            let value = if let Some(v) = value.to_u64() {
+                Value::from(v)
+            } else if let Some(v) = value.to_i64() {
+                Value::from(v)
+            } else if let Some(v) = value.to_f64() {
+                Value::from(v)
+            } else if let Some(v) = value.to_bool() {
+                Value::from(v)
+            } else {
+                Value::String(value.to_string())
+            };
+            self.0.push((key.to_string(), value));
+            Ok(())
+        }
+    }
+
+    let mut collector = Collector(Vec::new());
+    let _ = record.key_values().visit(&mut collector);
+    collector.0
+}
+
+//==========================================================================
+/// Size-rotated log file. When the active file would exceed `max_size_mb` it
+/// is renamed to `<name>.<timestamp>` and a fresh one is opened; rotated files
+/// beyond `max_files` or older than `max_age_hours` are deleted.
+pub struct RotatingFileWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_age: Option<Duration>,
+    max_files: usize,
+    file: File,
+    written: u64,
+    /// Whether the last write ended a line. fern hands over a record and its
+    /// newline in separate writes, so rotating anywhere else would split a
+    /// record across two files.
+    at_line_start: bool,
+    clock: SharedClock,
+}
+
+//==========================================================================
+impl RotatingFileWriter {
+    //==========================================================================
+    pub fn open(config: &LogFileConfig) -> AtlasResult<Self> {
+        if let Some(dir) = config.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFileWriter {
+            path: config.path.clone(),
+            max_bytes: config.max_size_mb.saturating_mul(1024 * 1024),
+            max_age: config
+                .max_age_hours
+                .map(|h| Duration::from_secs(h.saturating_mul(3600))),
+            max_files: config.max_files,
+            file,
+            written,
+            at_line_start: true,
+            clock: clock::system(),
+        })
+    }
+
+    //==========================================================================
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    //==========================================================================
+    #[cfg(test)]
+    fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    //==========================================================================
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let stamp = self.clock.now().format("%Y%m%dT%H%M%S%.6f");
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", stamp));
+        let mut target = PathBuf::from(&rotated);
+        let mut n = 1;
+        while target.exists() {
+            target = PathBuf::from(format!("{}-{}", rotated.to_string_lossy(), n));
+            n += 1;
+        }
+        fs::rename(&self.path, &target)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        self.prune()
+    }
+
+    //==========================================================================
+    fn prune(&self) -> io::Result<()> {
+        let mut rotated = rotated_files(&self.path)?;
+        // Timestamps sort lexically, oldest first.
+        rotated.sort();
+        let now = SystemTime::from(self.clock.now());
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for (i, path) in rotated.iter().enumerate() {
+            let expired = self.max_age.is_some_and(|max_age| {
+                fs::metadata(path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .is_some_and(|age| age > max_age)
+            });
+            if i < excess || expired {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//==========================================================================
+fn rotated_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+    );
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(&prefix))
+        {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+//==========================================================================
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.at_line_start
+            && self.written > 0
+            && self.written + buf.len() as u64 > self.max_bytes
+        {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        if n > 0 {
+            self.at_line_start = buf[n - 1] == b'\n';
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    //==========================================================================
+    #[test]
+    fn test_json_fields() {
+        let fields: &[(&str, kv::Value)] = &[
+            ("slot", kv::Value::from(300_000_000u64)),
+            ("signature", kv::Value::from("5xyz")),
+            ("endpoint", kv::Value::from("wss://node")),
+            ("latency_ms", kv::Value::from(12.5f64)),
+        ];
+        let record = Record::builder()
+            .level(log::Level::Info)
+            .target("atlas_sol2::sol_block")
+            .key_values(&fields)
+            .build();
+        let clock = ManualClock::new("2024-12-01T08:30:00.250Z".parse().unwrap());
+        let line = render_json(&clock, &format_args!("received block"), &record);
+        let parsed: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed["ts"], "2024-12-01T08:30:00.250+00:00");
+        assert_eq!(parsed["msg"], "received block");
+        assert_eq!(parsed["level"], "INFO");
+        assert_eq!(parsed["slot"], 300_000_000u64);
+        assert_eq!(parsed["signature"], "5xyz");
+        assert_eq!(parsed["endpoint"], "wss://node");
+        assert_eq!(parsed["latency_ms"], 12.5);
+
+        let text = render_text(&clock, &format_args!("received block"), &record);
+        assert!(text.ends_with(
+            "received block slot=300000000 signature=5xyz endpoint=wss://node latency_ms=12.5"
+        ));
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_rotation_and_retention() {
+        let dir = std::env::temp_dir().join(format!("atlas-logging-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = LogFileConfig {
+            path: dir.join("atlas.log"),
+            max_files: 2,
+            ..LogFileConfig::default()
+        };
+        let mut writer = RotatingFileWriter::open(&config)
+            .unwrap()
+            .with_max_bytes(16);
+        // Records arrive as the message and its newline, like fern writes
+        // them; no file may end mid-line.
+        for i in 0..5 {
+            writer
+                .write_all(format!("line number {}", i).as_bytes())
+                .unwrap();
+            writer.write_all(b"\n").unwrap();
+        }
+        writer.flush().unwrap();
+        let rotated = rotated_files(&config.path).unwrap();
+        assert_eq!(rotated.len(), 2);
+        for path in rotated {
+            assert_eq!(fs::read_to_string(path).unwrap().lines().count(), 1);
+        }
+        let active = fs::read_to_string(&config.path).unwrap();
+        assert_eq!(active, "line number 4\n");
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_bad_level_rejected() {
+        let config = LoggingConfig {
+            level: "loud".to_string(),
+            ..LoggingConfig::default()
+        };
+        assert!(build_dispatch(&config).is_err());
+    }
+}
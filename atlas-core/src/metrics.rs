@@ -0,0 +1,384 @@
+use crate::error::AtlasResult;
+use crate::http::{self, HttpResponse};
+use log::info;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+type Labels = Vec<(String, String)>;
+
+//==========================================================================
+#[derive(Debug, Clone, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+//==========================================================================
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+//==========================================================================
+/// An `f64` gauge stored as raw bits so it can be shared without a lock.
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicU64>);
+
+//==========================================================================
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + delta).to_bits())
+            });
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+//==========================================================================
+#[derive(Debug)]
+struct HistogramInner {
+    bounds: Vec<f64>,
+    buckets: Vec<AtomicU64>,
+    sum: Gauge,
+    count: AtomicU64,
+}
+
+//==========================================================================
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramInner>);
+
+//==========================================================================
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Histogram(Arc::new(HistogramInner {
+            bounds: bounds.to_vec(),
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum: Gauge::default(),
+            count: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn observe(&self, value: f64) {
+        let inner = &self.0;
+        if let Some(i) = inner.bounds.iter().position(|b| value <= *b) {
+            inner.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        inner.sum.add(value);
+        inner.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.0.sum.get()
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+//==========================================================================
+impl Metric {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug)]
+struct Family {
+    help: String,
+    series: BTreeMap<Labels, Metric>,
+}
+
+//==========================================================================
+/// Named metric families, each with any number of labelled series. Handles
+/// returned by `counter`/`gauge`/`histogram` are cheap clones of the stored
+/// series, so hot paths should keep the handle rather than look it up again.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+//==========================================================================
+impl MetricsRegistry {
+    //==========================================================================
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //==========================================================================
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        match self.get_or_insert(name, help, labels, || Metric::Counter(Counter::default())) {
+            Metric::Counter(c) => c,
+            other => panic!("metric {} is a {}, not a counter", name, other.type_name()),
+        }
+    }
+
+    //==========================================================================
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        match self.get_or_insert(name, help, labels, || Metric::Gauge(Gauge::default())) {
+            Metric::Gauge(g) => g,
+            other => panic!("metric {} is a {}, not a gauge", name, other.type_name()),
+        }
+    }
+
+    //==========================================================================
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        buckets: &[f64],
+    ) -> Histogram {
+        match self.get_or_insert(name, help, labels, || {
+            Metric::Histogram(Histogram::new(buckets))
+        }) {
+            Metric::Histogram(h) => h,
+            other => panic!(
+                "metric {} is a {}, not a histogram",
+                name,
+                other.type_name()
+            ),
+        }
+    }
+
+    //==========================================================================
+    fn get_or_insert(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        make: impl FnOnce() -> Metric,
+    ) -> Metric {
+        let mut labels: Labels = labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        labels.sort();
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            series: BTreeMap::new(),
+        });
+        family.series.entry(labels).or_insert_with(make).clone()
+    }
+
+    //==========================================================================
+    /// Prometheus text exposition format, version 0.0.4.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let Some(first) = family.series.values().next() else {
+                continue;
+            };
+            let _ = writeln!(out, "# HELP {} {}", name, escape_help(&family.help));
+            let _ = writeln!(out, "# TYPE {} {}", name, first.type_name());
+            for (labels, metric) in family.series.iter() {
+                match metric {
+                    Metric::Counter(c) => {
+                        let _ = writeln!(out, "{}{} {}", name, fmt_labels(labels, None), c.get());
+                    }
+                    Metric::Gauge(g) => {
+                        let _ = writeln!(out, "{}{} {}", name, fmt_labels(labels, None), g.get());
+                    }
+                    Metric::Histogram(h) => render_histogram(&mut out, name, labels, h),
+                }
+            }
+        }
+        out
+    }
+}
+
+//==========================================================================
+fn render_histogram(out: &mut String, name: &str, labels: &Labels, h: &Histogram) {
+    let mut cumulative = 0;
+    for (bound, bucket) in h.0.bounds.iter().zip(h.0.buckets.iter()) {
+        cumulative += bucket.load(Ordering::Relaxed);
+        let le = bound.to_string();
+        let _ = writeln!(
+            out,
+            "{}_bucket{} {}",
+            name,
+            fmt_labels(labels, Some(&le)),
+            cumulative
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{}_bucket{} {}",
+        name,
+        fmt_labels(labels, Some("+Inf")),
+        h.count()
+    );
+    let _ = writeln!(out, "{}_sum{} {}", name, fmt_labels(labels, None), h.sum());
+    let _ = writeln!(
+        out,
+        "{}_count{} {}",
+        name,
+        fmt_labels(labels, None),
+        h.count()
+    );
+}
+
+//==========================================================================
+fn fmt_labels(labels: &Labels, le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+//==========================================================================
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+//==========================================================================
+fn escape_help(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+//==========================================================================
+/// Process-wide registry used by the collectors and clients.
+pub fn global() -> &'static MetricsRegistry {
+    static REGISTRY: OnceLock<MetricsRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(MetricsRegistry::new)
+}
+
+//==========================================================================
+/// Serve `/metrics` for `registry` on `listen` until the task is dropped.
+pub async fn serve(listen: &str, registry: &'static MetricsRegistry) -> AtlasResult<()> {
+    let listener = http::bind(listen).await?;
+    info!(endpoint = listen; "Metrics endpoint listening");
+    serve_listener(listener, registry).await
+}
+
+//==========================================================================
+pub async fn serve_listener(
+    listener: tokio::net::TcpListener,
+    registry: &'static MetricsRegistry,
+) -> AtlasResult<()> {
+    http::serve(listener, move |path| match path {
+        "/metrics" => HttpResponse::ok("text/plain; version=0.0.4", registry.render()),
+        _ => HttpResponse::not_found(),
+    })
+    .await
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //==========================================================================
+    #[test]
+    fn test_render() {
+        let registry = MetricsRegistry::new();
+        registry
+            .counter("atlas_events_total", "Events seen", &[("kind", "account")])
+            .inc_by(3);
+        registry
+            .counter("atlas_events_total", "Events seen", &[("kind", "slot")])
+            .inc();
+        registry
+            .gauge("atlas_channel_occupancy", "Queued messages", &[])
+            .set(7.0);
+        let latency = registry.histogram("atlas_latency_seconds", "Latency", &[], &[0.1, 1.0]);
+        latency.observe(0.05);
+        latency.observe(0.5);
+        latency.observe(3.0);
+
+        let text = registry.render();
+        let expected = "\
+# HELP atlas_channel_occupancy Queued messages
+# TYPE atlas_channel_occupancy gauge
+atlas_channel_occupancy 7
+# HELP atlas_events_total Events seen
+# TYPE atlas_events_total counter
+atlas_events_total{kind=\"account\"} 3
+atlas_events_total{kind=\"slot\"} 1
+# HELP atlas_latency_seconds Latency
+# TYPE atlas_latency_seconds histogram
+atlas_latency_seconds_bucket{le=\"0.1\"} 1
+atlas_latency_seconds_bucket{le=\"1\"} 2
+atlas_latency_seconds_bucket{le=\"+Inf\"} 3
+atlas_latency_seconds_sum 3.55
+atlas_latency_seconds_count 3
+";
+        assert_eq!(text, expected);
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_handles_share_series() {
+        let registry = MetricsRegistry::new();
+        let a = registry.counter("c", "", &[("x", "1")]);
+        let b = registry.counter("c", "", &[("x", "1")]);
+        a.inc();
+        b.inc();
+        assert_eq!(a.get(), 2);
+        assert_eq!(escape_label("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_metrics_endpoint() {
+        static REGISTRY: OnceLock<MetricsRegistry> = OnceLock::new();
+        let registry = REGISTRY.get_or_init(MetricsRegistry::new);
+        registry.counter("atlas_up", "Up", &[]).inc();
+        let listener = http::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(listener, registry));
+
+        let (status, body) = http::get(addr, "/metrics").await;
+        assert_eq!(status, 200);
+        assert!(body.contains("atlas_up 1"), "{}", body);
+        let (status, _) = http::get(addr, "/nope").await;
+        assert_eq!(status, 404);
+    }
+}
@@ -0,0 +1,384 @@
+use crate::clock::{self, SharedClock};
+use crate::config::{EndpointLimit, RateLimitConfig};
+use crate::metrics::{self, Counter, Gauge};
+use crate::util::AtlasUtil;
+use chrono::{DateTime, Utc};
+use log::debug;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+//==========================================================================
+/// What is left of an endpoint's budget, in cost units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    pub available: f64,
+    pub capacity: f64,
+    pub per_second: f64,
+}
+
+//==========================================================================
+#[derive(Debug)]
+struct Bucket {
+    per_second: f64,
+    capacity: f64,
+    /// Goes negative while callers are queued on a reservation.
+    available: f64,
+    updated: DateTime<Utc>,
+}
+
+//==========================================================================
+impl Bucket {
+    fn new(per_second: f64, capacity: f64, now: DateTime<Utc>) -> Self {
+        Bucket {
+            per_second,
+            capacity,
+            available: capacity,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: DateTime<Utc>) {
+        let elapsed = (now - self.updated).to_std().unwrap_or_default();
+        self.available =
+            (self.available + elapsed.as_secs_f64() * self.per_second).min(self.capacity);
+        self.updated = self.updated.max(now);
+    }
+
+    /// How long until `cost` is covered.
+    fn shortfall(&self, cost: f64) -> Duration {
+        if self.available >= cost {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((cost - self.available) / self.per_second)
+    }
+
+    fn refund(&mut self, cost: f64) {
+        self.available = (self.available + cost).min(self.capacity);
+    }
+
+    fn budget(&self) -> Budget {
+        Budget {
+            available: self.available.max(0.0),
+            capacity: self.capacity,
+            per_second: self.per_second,
+        }
+    }
+}
+
+//==========================================================================
+#[derive(Debug)]
+struct EndpointState {
+    bucket: Bucket,
+    methods: HashMap<String, Bucket>,
+    remaining: Gauge,
+    throttled: Counter,
+}
+
+//==========================================================================
+/// Token buckets keyed by endpoint, charged per method by weight. Clones share
+/// the same buckets, so every client and job handed one stays under the
+/// provider's limit together.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limits: Arc<HashMap<String, EndpointLimit>>,
+    state: Arc<Mutex<HashMap<String, EndpointState>>>,
+    clock: SharedClock,
+}
+
+//==========================================================================
+impl Default for RateLimiter {
+    /// Limits nothing.
+    fn default() -> Self {
+        RateLimiter::new(&RateLimitConfig::default())
+    }
+}
+
+//==========================================================================
+impl RateLimiter {
+    //==========================================================================
+    pub fn new(config: &RateLimitConfig) -> Self {
+        RateLimiter {
+            limits: Arc::new(config.endpoints.clone()),
+            state: Arc::default(),
+            clock: clock::system(),
+        }
+    }
+
+    //==========================================================================
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    //==========================================================================
+    /// Config key for `endpoint`: the endpoint itself or its host.
+    fn key<'a>(&self, endpoint: &'a str) -> Option<&'a str> {
+        if self.limits.contains_key(endpoint) {
+            return Some(endpoint);
+        }
+        let host = host(endpoint);
+        self.limits.contains_key(host).then_some(host)
+    }
+
+    //==========================================================================
+    /// Cost of `method` against `endpoint`'s budget; zero when not limited.
+    pub fn cost(&self, endpoint: &str, method: &str) -> f64 {
+        let Some(limit) = self.key(endpoint).map(|k| &self.limits[k]) else {
+            return 0.0;
+        };
+        limit
+            .methods
+            .get(method)
+            .map_or(limit.default_cost, |m| m.cost)
+    }
+
+    //==========================================================================
+    /// Run `f` on the endpoint's state, refilled to now. `None` if the
+    /// endpoint is not limited.
+    fn with_state<R>(&self, endpoint: &str, f: impl FnOnce(&mut EndpointState) -> R) -> Option<R> {
+        let key = self.key(endpoint)?;
+        let limit = &self.limits[key];
+        let now = self.clock.now();
+        let mut states = self.state.lock().unwrap();
+        let state = states.entry(key.to_string()).or_insert_with(|| {
+            let registry = metrics::global();
+            let label = AtlasUtil::redact_url(key);
+            let labels = [("endpoint", label.as_str())];
+            EndpointState {
+                bucket: Bucket::new(limit.per_second, limit.burst, now),
+                methods: limit
+                    .methods
+                    .iter()
+                    .filter_map(|(name, m)| {
+                        let per_second = m.per_second?;
+                        let bucket = Bucket::new(per_second, m.burst.unwrap_or(per_second), now);
+                        Some((name.clone(), bucket))
+                    })
+                    .collect(),
+                remaining: registry.gauge(
+                    "atlas_rate_limit_remaining",
+                    "Request budget left per endpoint, in cost units",
+                    &labels,
+                ),
+                throttled: registry.counter(
+                    "atlas_rate_limit_throttled_total",
+                    "Calls that had to wait for request budget",
+                    &labels,
+                ),
+            }
+        });
+        state.bucket.refill(now);
+        state.methods.values_mut().for_each(|b| b.refill(now));
+        let result = f(state);
+        state.remaining.set(state.bucket.budget().available);
+        Some(result)
+    }
+
+    //==========================================================================
+    /// Wait until `method` may be called on `endpoint`. Budget is reserved
+    /// up front, so waiters are served in the order they arrived; a waiter
+    /// dropped before its turn hands the reservation back.
+    pub async fn acquire(&self, endpoint: &str, method: &str) {
+        let cost = self.cost(endpoint, method);
+        let reserved = self.with_state(endpoint, |state| {
+            let mut wait = state.bucket.shortfall(cost);
+            state.bucket.available -= cost;
+            if let Some(bucket) = state.methods.get_mut(method) {
+                wait = wait.max(bucket.shortfall(cost));
+                bucket.available -= cost;
+            }
+            if !wait.is_zero() {
+                state.throttled.inc();
+            }
+            wait
+        });
+        match reserved {
+            Some(wait) if !wait.is_zero() => {
+                let reservation = Reservation {
+                    limiter: self,
+                    endpoint,
+                    method,
+                    cost,
+                };
+                let label = AtlasUtil::redact_url(endpoint);
+                debug!(endpoint = label.as_str(), method = method; "Rate limited for {:?}", wait);
+                self.clock.sleep(wait).await;
+                std::mem::forget(reservation);
+            }
+            _ => {}
+        }
+    }
+
+    //==========================================================================
+    /// Take the budget for one call only if it is available right now.
+    pub fn try_acquire(&self, endpoint: &str, method: &str) -> bool {
+        let cost = self.cost(endpoint, method);
+        self.with_state(endpoint, |state| {
+            let method_bucket = state.methods.get_mut(method);
+            let ready = state.bucket.available >= cost
+                && method_bucket.as_ref().is_none_or(|b| b.available >= cost);
+            if ready {
+                state.bucket.available -= cost;
+                if let Some(bucket) = method_bucket {
+                    bucket.available -= cost;
+                }
+            }
+            ready
+        })
+        .unwrap_or(true)
+    }
+
+    //==========================================================================
+    /// `None` when the endpoint is not limited.
+    pub fn remaining(&self, endpoint: &str) -> Option<Budget> {
+        self.with_state(endpoint, |state| state.bucket.budget())
+    }
+}
+
+//==========================================================================
+/// Budget `acquire` took ahead of its wait; refunded unless the wait ends.
+struct Reservation<'a> {
+    limiter: &'a RateLimiter,
+    endpoint: &'a str,
+    method: &'a str,
+    cost: f64,
+}
+
+//==========================================================================
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.limiter.with_state(self.endpoint, |state| {
+            state.bucket.refund(self.cost);
+            if let Some(bucket) = state.methods.get_mut(self.method) {
+                bucket.refund(self.cost);
+            }
+        });
+    }
+}
+
+//==========================================================================
+/// Host part of a URL, without scheme, credentials, port or path.
+fn host(endpoint: &str) -> &str {
+    let rest = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    let authority = rest.split(['/', '?']).next().unwrap_or(rest);
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+    authority.split(':').next().unwrap_or(authority)
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config::MethodLimit;
+
+    const RPC: &str = "https://solana-mainnet.example.com/secret-key";
+
+    fn limiter() -> (RateLimiter, ManualClock) {
+        let mut endpoint = EndpointLimit {
+            per_second: 10.0,
+            burst: 10.0,
+            ..EndpointLimit::default()
+        };
+        endpoint.methods.insert(
+            "getBlock".to_string(),
+            MethodLimit {
+                cost: 5.0,
+                ..MethodLimit::default()
+            },
+        );
+        endpoint.methods.insert(
+            "getSlot".to_string(),
+            MethodLimit {
+                cost: 1.0,
+                per_second: Some(1.0),
+                burst: Some(2.0),
+            },
+        );
+        let mut config = RateLimitConfig::default();
+        config
+            .endpoints
+            .insert("solana-mainnet.example.com".to_string(), endpoint);
+        let clock = ManualClock::new(Utc::now());
+        let limiter = RateLimiter::new(&config).with_clock(Arc::new(clock.clone()));
+        (limiter, clock)
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_weighted_costs_and_budget() {
+        let (limiter, clock) = limiter();
+        assert_eq!(host(RPC), "solana-mainnet.example.com");
+        assert_eq!(
+            host("wss://user:pw@hermes.example.com:443/ws"),
+            "hermes.example.com"
+        );
+        assert_eq!(limiter.cost(RPC, "getBlock"), 5.0);
+        assert_eq!(limiter.cost(RPC, "getBalance"), 1.0);
+        assert_eq!(
+            limiter.cost("https://elsewhere.example.com", "getBlock"),
+            0.0
+        );
+        assert!(limiter.remaining("https://elsewhere.example.com").is_none());
+
+        assert!(limiter.try_acquire(RPC, "getBlock"));
+        assert!(limiter.try_acquire(RPC, "getBlock"));
+        assert!(!limiter.try_acquire(RPC, "getBalance"));
+        assert_eq!(limiter.remaining(RPC).unwrap().available, 0.0);
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(limiter.remaining(RPC).unwrap().available, 5.0);
+        // getSlot has its own bucket of two on top of the endpoint's.
+        assert!(limiter.try_acquire(RPC, "getSlot"));
+        assert!(limiter.try_acquire(RPC, "getSlot"));
+        assert!(!limiter.try_acquire(RPC, "getSlot"));
+        assert!(limiter.try_acquire(RPC, "getBalance"));
+        assert!(limiter.try_acquire("https://elsewhere.example.com", "anything"));
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_acquire_waits_for_refill() {
+        let (limiter, clock) = limiter();
+        limiter.acquire(RPC, "getBlock").await;
+        limiter.acquire(RPC, "getBlock").await;
+
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire(RPC, "getBlock").await })
+        };
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_millis(400));
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        clock.advance(Duration::from_millis(100));
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(limiter.remaining(RPC).unwrap().available, 0.0);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_cancelled_acquire_returns_budget() {
+        let (limiter, clock) = limiter();
+        limiter.acquire(RPC, "getBlock").await;
+        limiter.acquire(RPC, "getBlock").await;
+
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire(RPC, "getBlock").await })
+        };
+        tokio::task::yield_now().await;
+        waiter.abort();
+        assert!(waiter.await.unwrap_err().is_cancelled());
+        assert_eq!(limiter.remaining(RPC).unwrap().available, 0.0);
+
+        clock.advance(Duration::from_millis(500));
+        assert!(limiter.try_acquire(RPC, "getBlock"));
+    }
+}
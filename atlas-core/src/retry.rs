@@ -0,0 +1,480 @@
+use crate::clock::{self, SharedClock};
+use crate::config::RetryConfig;
+use crate::error::{AtlasError, AtlasResult};
+use crate::ratelimit::RateLimiter;
+use crate::util::AtlasUtil;
+use chrono::{DateTime, Utc};
+use log::warn;
+use rand::Rng;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+//==========================================================================
+/// Exponential backoff with jitter, a cap on attempts and an optional
+/// deadline applied to each individual attempt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Fraction of each delay that is randomised, 0.0 (none) to 1.0 (full).
+    pub jitter: f64,
+    pub attempt_timeout: Option<Duration>,
+}
+
+//==========================================================================
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::from_config(&RetryConfig::default())
+    }
+}
+
+//==========================================================================
+impl RetryPolicy {
+    //==========================================================================
+    pub fn from_config(config: &RetryConfig) -> Self {
+        RetryPolicy {
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            multiplier: config.multiplier,
+            jitter: config.jitter.clamp(0.0, 1.0),
+            attempt_timeout: config.attempt_timeout_ms.map(Duration::from_millis),
+        }
+    }
+
+    //==========================================================================
+    /// A policy that makes a single attempt.
+    pub fn no_retry() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    //==========================================================================
+    /// Delay before retry number `attempt` (1-based), before jitter.
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let delay = self.initial_backoff.as_secs_f64() * factor;
+        Duration::from_secs_f64(delay.min(self.max_backoff.as_secs_f64()))
+    }
+
+    //==========================================================================
+    pub fn delay(&self, attempt: u32, rng: &mut impl Rng) -> Duration {
+        let base = self.base_delay(attempt).as_secs_f64();
+        let spread = base * self.jitter;
+        Duration::from_secs_f64(base - spread + rng.gen::<f64>() * spread)
+    }
+}
+
+//==========================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+//==========================================================================
+#[derive(Debug)]
+struct BreakerInner {
+    failures: u32,
+    opened_at: Option<DateTime<Utc>>,
+    probing: bool,
+}
+
+//==========================================================================
+/// Opens after `failure_threshold` consecutive retryable failures and rejects
+/// calls until `reset_timeout` has passed, then lets one probe through.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    clock: SharedClock,
+    inner: Mutex<BreakerInner>,
+}
+
+//==========================================================================
+impl CircuitBreaker {
+    //==========================================================================
+    pub fn new(failure_threshold: u32, reset_timeout: Duration) -> Self {
+        CircuitBreaker::with_clock(failure_threshold, reset_timeout, clock::system())
+    }
+
+    //==========================================================================
+    pub fn with_clock(failure_threshold: u32, reset_timeout: Duration, clock: SharedClock) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            reset_timeout,
+            clock,
+            inner: Mutex::new(BreakerInner {
+                failures: 0,
+                opened_at: None,
+                probing: false,
+            }),
+        }
+    }
+
+    //==========================================================================
+    pub fn state(&self) -> BreakerState {
+        let inner = self.inner.lock().unwrap();
+        match inner.opened_at {
+            None => BreakerState::Closed,
+            Some(at) if self.clock.since(at) >= self.reset_timeout => BreakerState::HalfOpen,
+            Some(_) => BreakerState::Open,
+        }
+    }
+
+    //==========================================================================
+    /// A permit to make one call, or `None` while the breaker is open. In
+    /// the half-open state only one probe is admitted until it reports back
+    /// or its permit is dropped unreported, e.g. when the call is cancelled.
+    pub fn try_acquire(&self) -> Option<BreakerPermit<'_>> {
+        let mut inner = self.inner.lock().unwrap();
+        let probe = match inner.opened_at {
+            None => false,
+            Some(at) if self.clock.since(at) >= self.reset_timeout && !inner.probing => {
+                inner.probing = true;
+                true
+            }
+            Some(_) => return None,
+        };
+        Some(BreakerPermit {
+            breaker: self,
+            probe,
+        })
+    }
+
+    //==========================================================================
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures = 0;
+        inner.opened_at = None;
+        inner.probing = false;
+    }
+
+    //==========================================================================
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures += 1;
+        if inner.probing || inner.failures >= self.failure_threshold {
+            inner.opened_at = Some(self.clock.now());
+        }
+        inner.probing = false;
+    }
+}
+
+//==========================================================================
+/// One call admitted by a `CircuitBreaker`; report how it went through
+/// `succeeded` or `failed`.
+#[derive(Debug)]
+#[must_use = "a probe permit holds the half-open breaker until it is used"]
+pub struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+}
+
+//==========================================================================
+impl BreakerPermit<'_> {
+    //==========================================================================
+    pub fn succeeded(mut self) {
+        self.probe = false;
+        self.breaker.record_success();
+    }
+
+    //==========================================================================
+    pub fn failed(mut self) {
+        self.probe = false;
+        self.breaker.record_failure();
+    }
+}
+
+//==========================================================================
+impl Drop for BreakerPermit<'_> {
+    /// An abandoned probe says nothing about the endpoint: the breaker stays
+    /// half-open and admits the next caller.
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.inner.lock().unwrap().probing = false;
+        }
+    }
+}
+
+//==========================================================================
+/// Retry policy plus a circuit breaker shared by every call to one endpoint.
+#[derive(Debug, Clone)]
+pub struct CallPolicy {
+    /// Redacted; `url` is only used to find the endpoint's rate limit.
+    endpoint: String,
+    url: String,
+    retry: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+    clock: SharedClock,
+    limiter: Option<RateLimiter>,
+}
+
+//==========================================================================
+impl CallPolicy {
+    //==========================================================================
+    pub fn new(endpoint: &str, retry: RetryPolicy, breaker: Arc<CircuitBreaker>) -> Self {
+        CallPolicy {
+            endpoint: AtlasUtil::redact_url(endpoint),
+            url: endpoint.to_string(),
+            retry,
+            breaker,
+            clock: clock::system(),
+            limiter: None,
+        }
+    }
+
+    //==========================================================================
+    pub fn from_config(endpoint: &str, config: &RetryConfig) -> Self {
+        CallPolicy::from_config_with_clock(endpoint, config, clock::system())
+    }
+
+    //==========================================================================
+    /// Backoff, attempt timeouts and the breaker's reset all run on `clock`.
+    pub fn from_config_with_clock(
+        endpoint: &str,
+        config: &RetryConfig,
+        clock: SharedClock,
+    ) -> Self {
+        let breaker = CircuitBreaker::with_clock(
+            config.breaker_failure_threshold,
+            Duration::from_millis(config.breaker_reset_ms),
+            clock.clone(),
+        );
+        CallPolicy::new(
+            endpoint,
+            RetryPolicy::from_config(config),
+            Arc::new(breaker),
+        )
+        .with_clock(clock)
+    }
+
+    //==========================================================================
+    /// Run backoff and attempt timeouts on `clock`. The breaker keeps its own.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    //==========================================================================
+    /// Take budget from `limiter` before every attempt, retries included,
+    /// charged as this endpoint and the call's operation name.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    //==========================================================================
+    /// The endpoint as errors and logs show it, without credentials.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    //==========================================================================
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    //==========================================================================
+    /// Run `op` until it succeeds, fails with a fatal error, or runs out of
+    /// attempts. Only retryable errors count against the breaker.
+    pub async fn call<T, F, Fut>(&self, operation: &str, mut op: F) -> AtlasResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = AtlasResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let Some(permit) = self.breaker.try_acquire() else {
+                return Err(AtlasError::CircuitOpen {
+                    endpoint: self.endpoint.clone(),
+                });
+            };
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire(&self.url, operation).await;
+            }
+            attempt += 1;
+            let result = match self.retry.attempt_timeout {
+                Some(limit) => tokio::select! {
+                    result = op() => result,
+                    _ = self.clock.sleep(limit) => Err(AtlasError::Timeout {
+                        endpoint: self.endpoint.clone(),
+                        operation: operation.to_string(),
+                        elapsed_ms: limit.as_millis() as u64,
+                    }),
+                },
+                None => op().await,
+            };
+            let err = match result {
+                Ok(value) => {
+                    permit.succeeded();
+                    return Ok(value);
+                }
+                Err(err) => err,
+            };
+            if err.is_fatal() {
+                // The endpoint answered; the request itself was bad.
+                permit.succeeded();
+                return Err(err);
+            }
+            permit.failed();
+            if attempt >= self.retry.max_attempts {
+                return Err(err);
+            }
+            let delay = self.retry.delay(attempt, &mut rand::thread_rng());
+            warn!(
+                endpoint = self.endpoint.as_str(),
+                attempt = attempt;
+                "{} failed, retrying in {:?}: {}", operation, delay, err
+            );
+            self.clock.sleep(delay).await;
+        }
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            multiplier: 2.0,
+            jitter: 0.5,
+            attempt_timeout: Some(Duration::from_millis(50)),
+        }
+    }
+
+    fn breaker(threshold: u32) -> Arc<CircuitBreaker> {
+        Arc::new(CircuitBreaker::new(threshold, Duration::from_secs(60)))
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_backoff_growth() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            multiplier: 2.0,
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.base_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.base_delay(2), Duration::from_millis(200));
+        assert_eq!(policy.base_delay(4), Duration::from_millis(800));
+        assert_eq!(policy.base_delay(5), Duration::from_millis(1000));
+
+        let jittered = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let d = jittered.delay(2, &mut rng);
+            assert!(d >= Duration::from_millis(100) && d <= Duration::from_millis(200));
+        }
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_retries_retryable_until_success() {
+        let policy = CallPolicy::new("wss://node", fast_policy(5), breaker(10));
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .call("getSlot", || async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(AtlasError::websocket("wss://node", "reset"))
+                } else {
+                    Ok(42)
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_fatal_is_not_retried() {
+        let policy = CallPolicy::new("https://node", fast_policy(5), breaker(10));
+        let calls = AtomicU32::new(0);
+        let result: AtlasResult<()> = policy
+            .call("getBlock", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(AtlasError::decode("block", "bad"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_attempt_timeout() {
+        let policy = CallPolicy::new("https://node", fast_policy(2), breaker(10));
+        let result: AtlasResult<()> = policy
+            .call("getSlot", || async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .await;
+        assert!(matches!(result, Err(AtlasError::Timeout { .. })));
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_breaker_reset_follows_clock() {
+        let clock = ManualClock::new(chrono::Utc::now());
+        let breaker = Arc::new(CircuitBreaker::with_clock(
+            1,
+            Duration::from_secs(30),
+            Arc::new(clock.clone()),
+        ));
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        clock.advance(Duration::from_secs(29));
+        assert_eq!(breaker.state(), BreakerState::Open);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_breaker_opens_and_probes() {
+        let breaker = Arc::new(CircuitBreaker::new(2, Duration::from_millis(20)));
+        let policy = CallPolicy::new("https://node", fast_policy(1), breaker.clone());
+        for _ in 0..2 {
+            let _: AtlasResult<()> = policy
+                .call("getSlot", || async {
+                    Err(AtlasError::websocket("https://node", "down"))
+                })
+                .await;
+        }
+        assert_eq!(breaker.state(), BreakerState::Open);
+        let result: AtlasResult<()> = policy.call("getSlot", || async { Ok(()) }).await;
+        assert!(matches!(result, Err(AtlasError::CircuitOpen { .. })));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+        // A probe that is cancelled before it reports frees the slot for the
+        // next one instead of leaving the breaker open for good.
+        let probe = policy.call("getSlot", std::future::pending::<AtlasResult<()>>);
+        assert!(tokio::time::timeout(Duration::from_millis(5), probe)
+            .await
+            .is_err());
+        policy.call("getSlot", || async { Ok(()) }).await.unwrap();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+}
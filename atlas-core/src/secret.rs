@@ -0,0 +1,302 @@
+use crate::error::{AtlasError, AtlasResult};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const SOLANA_KEYPAIR_LEN: usize = 64;
+
+//==========================================================================
+/// Where a secret lives. Secrets are never written inline in config files:
+///
+/// ```toml
+/// private_key = { file = "/etc/atlas/bot.key" }
+/// private_key = { env = "BOT_PRIVATE_KEY" }
+/// private_key = { eth_keystore = { path = "keystore.json", passphrase_env = "KEYSTORE_PASS" } }
+/// identity_key = { solana_keypair = "/etc/atlas/id.json" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretSource {
+    File(PathBuf),
+    Env(String),
+    EthKeystore {
+        path: PathBuf,
+        passphrase_env: String,
+    },
+    SolanaKeypair(PathBuf),
+}
+
+//==========================================================================
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::File(path) => write!(f, "file {}", path.display()),
+            SecretSource::Env(var) => write!(f, "env ${}", var),
+            SecretSource::EthKeystore { path, .. } => write!(f, "keystore {}", path.display()),
+            SecretSource::SolanaKeypair(path) => write!(f, "keypair {}", path.display()),
+        }
+    }
+}
+
+//==========================================================================
+/// Secret bytes that never show up in `Debug`/`Display` output and are wiped
+/// from memory on drop.
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "SecretSource")]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+//==========================================================================
+impl Secret {
+    //==========================================================================
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Secret(Zeroizing::new(bytes))
+    }
+
+    //==========================================================================
+    pub fn load(source: &SecretSource) -> AtlasResult<Self> {
+        Self::load_with_env(source, |var| std::env::var(var).ok())
+    }
+
+    //==========================================================================
+    /// Like `load`, with environment variables read through `env`.
+    pub fn load_with_env<F>(source: &SecretSource, env: F) -> AtlasResult<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let fail = |message: String| AtlasError::Secret {
+            origin: source.to_string(),
+            message,
+        };
+        match source {
+            SecretSource::File(path) => {
+                check_permissions(path).map_err(fail)?;
+                let text = Zeroizing::new(fs::read_to_string(path)?);
+                Ok(Secret::new(text.trim().as_bytes().to_vec()))
+            }
+            SecretSource::Env(var) => {
+                let value = Zeroizing::new(env(var).ok_or_else(|| fail("not set".to_string()))?);
+                Ok(Secret::new(value.trim().as_bytes().to_vec()))
+            }
+            SecretSource::EthKeystore {
+                path,
+                passphrase_env,
+            } => {
+                check_permissions(path).map_err(fail)?;
+                let passphrase = Zeroizing::new(
+                    env(passphrase_env)
+                        .ok_or_else(|| fail(format!("${} is not set", passphrase_env)))?,
+                );
+                let key = eth_keystore::decrypt_key(path, passphrase.as_bytes())
+                    .map_err(|e| fail(e.to_string()))?;
+                Ok(Secret::new(key))
+            }
+            SecretSource::SolanaKeypair(path) => {
+                check_permissions(path).map_err(fail)?;
+                let text = Zeroizing::new(fs::read_to_string(path)?);
+                Ok(Secret(parse_keypair(&text).map_err(fail)?))
+            }
+        }
+    }
+
+    //==========================================================================
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    //==========================================================================
+    pub fn expose_str(&self) -> AtlasResult<&str> {
+        std::str::from_utf8(&self.0)
+            .map_err(|e| AtlasError::decode("secret", format!("not valid UTF-8: {}", e)))
+    }
+}
+
+//==========================================================================
+impl TryFrom<SecretSource> for Secret {
+    type Error = AtlasError;
+
+    fn try_from(source: SecretSource) -> AtlasResult<Self> {
+        Secret::load(&source)
+    }
+}
+
+//==========================================================================
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([REDACTED])")
+    }
+}
+
+//==========================================================================
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+//==========================================================================
+/// A `solana-keygen` keypair file: a JSON array of 64 bytes. Parsed by hand
+/// into a buffer that never reallocates, so no copy of the key outlives it
+/// whether or not the file is valid.
+fn parse_keypair(text: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let body = text
+        .trim()
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .ok_or("expected a JSON array of bytes")?;
+    let mut bytes = Zeroizing::new(Vec::with_capacity(SOLANA_KEYPAIR_LEN));
+    for item in body.split(',').filter(|item| !item.trim().is_empty()) {
+        if bytes.len() == SOLANA_KEYPAIR_LEN {
+            return Err(format!("expected {} bytes, found more", SOLANA_KEYPAIR_LEN));
+        }
+        let index = bytes.len();
+        let byte = item
+            .trim()
+            .parse::<u8>()
+            .map_err(|_| format!("element {} is not a byte", index))?;
+        bytes.push(byte);
+    }
+    if bytes.len() != SOLANA_KEYPAIR_LEN {
+        return Err(format!(
+            "expected {} bytes, found {}",
+            SOLANA_KEYPAIR_LEN,
+            bytes.len()
+        ));
+    }
+    Ok(bytes)
+}
+
+//==========================================================================
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)
+        .map_err(|e| e.to_string())?
+        .permissions()
+        .mode();
+    if mode & 0o004 != 0 {
+        return Err(format!(
+            "{} is world-readable (mode {:o}); run `chmod 600` on it",
+            path.display(),
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+//==========================================================================
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_key(name: &str, contents: &str, mode: u32) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atlas-secret-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        path
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_redacted_output() {
+        let secret = Secret::new(b"hunter2".to_vec());
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+        assert_eq!(format!("{}", secret), "[REDACTED]");
+        assert_eq!(secret.expose_str().unwrap(), "hunter2");
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_load_from_file_and_env() {
+        let path = write_key("bot.key", "0xabc\n", 0o600);
+        let secret = Secret::load(&SecretSource::File(path)).unwrap();
+        assert_eq!(secret.expose_str().unwrap(), "0xabc");
+
+        let env = |var: &str| (var == "BOT_KEY").then(|| " from-env\n".to_string());
+        let secret = Secret::load_with_env(&SecretSource::Env("BOT_KEY".into()), env).unwrap();
+        assert_eq!(secret.expose(), b"from-env");
+        assert!(Secret::load_with_env(&SecretSource::Env("OTHER".into()), env).is_err());
+    }
+
+    //==========================================================================
+    #[cfg(unix)]
+    #[test]
+    fn test_world_readable_rejected() {
+        let path = write_key("open.key", "0xabc", 0o644);
+        let err = Secret::load(&SecretSource::File(path)).unwrap_err();
+        assert!(err.to_string().contains("world-readable"), "{}", err);
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_solana_keypair() {
+        let bytes: Vec<u8> = (0..64).collect();
+        let path = write_key("id.json", &serde_json::to_string(&bytes).unwrap(), 0o600);
+        let secret = Secret::load(&SecretSource::SolanaKeypair(path)).unwrap();
+        assert_eq!(secret.expose(), bytes.as_slice());
+
+        for (name, contents) in [
+            ("short.json", "[1, 2, 3]".to_string()),
+            ("long.json", serde_json::to_string(&vec![1u8; 65]).unwrap()),
+            ("bad.json", "[1, 256, 3]".to_string()),
+            ("object.json", r#"{"key": 1}"#.to_string()),
+        ] {
+            let path = write_key(name, &contents, 0o600);
+            assert!(
+                Secret::load(&SecretSource::SolanaKeypair(path)).is_err(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_eth_keystore() {
+        let dir = std::env::temp_dir().join(format!("atlas-keystore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = [7u8; 32];
+        eth_keystore::encrypt_key(&dir, &mut rand::thread_rng(), key, "pass", Some("ks.json"))
+            .unwrap();
+        let path = dir.join("ks.json");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        let source = SecretSource::EthKeystore {
+            path,
+            passphrase_env: "KEYSTORE_PASS".into(),
+        };
+        let env = |var: &str| (var == "KEYSTORE_PASS").then(|| "pass".to_string());
+        assert_eq!(Secret::load_with_env(&source, env).unwrap().expose(), &key);
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_deserialize_from_source() {
+        #[derive(Deserialize, Debug)]
+        struct Bot {
+            key: Secret,
+        }
+        let path = write_key("toml.key", "toml-secret", 0o600);
+        let bot: Bot = toml::from_str(&format!("key = {{ file = {:?} }}", path)).unwrap();
+        assert_eq!(bot.key.expose(), b"toml-secret");
+        assert!(!format!("{:?}", bot).contains("toml-secret"));
+
+        assert!(toml::from_str::<Bot>(r#"key = "inline""#).is_err());
+    }
+}
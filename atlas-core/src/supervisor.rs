@@ -0,0 +1,377 @@
+use crate::config::{RestartPolicy, RetryConfig, SupervisorConfig};
+use crate::error::AtlasResult;
+use crate::metrics::{self, Counter};
+use crate::retry::RetryPolicy;
+use log::{error, info, warn};
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+pub use tokio_util::sync::CancellationToken;
+
+//==========================================================================
+/// Shutdown order. Every task in a stage is stopped and awaited before the
+/// next stage is cancelled, so producers stop first, processors then drain
+/// what is already queued and sinks flush last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    Producer,
+    Processor,
+    Sink,
+}
+
+//==========================================================================
+impl Stage {
+    pub const ALL: [Stage; 3] = [Stage::Producer, Stage::Processor, Stage::Sink];
+}
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskExit {
+    Completed,
+    Cancelled,
+    /// Failed and the restart policy did not allow another attempt.
+    Failed {
+        restarts: u32,
+        error: String,
+    },
+    /// Ignored its cancellation token for longer than the shutdown timeout.
+    Aborted,
+}
+
+//==========================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskReport {
+    pub name: String,
+    pub stage: Stage,
+    pub exit: TaskExit,
+}
+
+//==========================================================================
+struct SupervisedTask {
+    name: String,
+    stage: Stage,
+    handle: JoinHandle<TaskExit>,
+}
+
+//==========================================================================
+/// Owns a set of long running tasks. Each task is a factory that is handed a
+/// cancellation token and re-invoked when the previous run fails, according
+/// to the configured restart policy.
+pub struct Supervisor {
+    shutdown: CancellationToken,
+    stages: [CancellationToken; 3],
+    tasks: Vec<SupervisedTask>,
+    restart: RestartPolicy,
+    max_restarts: u32,
+    backoff: RetryPolicy,
+    shutdown_timeout: Duration,
+}
+
+//==========================================================================
+impl Supervisor {
+    //==========================================================================
+    pub fn new(config: &SupervisorConfig, retry: &RetryConfig) -> Self {
+        Supervisor {
+            shutdown: CancellationToken::new(),
+            stages: Default::default(),
+            tasks: Vec::new(),
+            restart: config.restart,
+            max_restarts: config.max_restarts,
+            backoff: RetryPolicy::from_config(retry),
+            shutdown_timeout: Duration::from_millis(config.shutdown_timeout_ms),
+        }
+    }
+
+    //==========================================================================
+    /// Cancelling this token asks `run_until_shutdown` to stop everything.
+    /// It is not handed to tasks; they see their stage token instead.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    //==========================================================================
+    /// Spawn `task` on the current runtime under supervision.
+    pub fn spawn<F, Fut>(&mut self, name: &str, stage: Stage, task: F)
+    where
+        F: FnMut(CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = AtlasResult<()>> + Send + 'static,
+    {
+        let run = TaskRun {
+            name: name.to_string(),
+            token: self.stages[stage as usize].clone(),
+            restart: self.restart,
+            max_restarts: self.max_restarts,
+            backoff: self.backoff.clone(),
+            restarts: metrics::global().counter(
+                "atlas_supervisor_restarts_total",
+                "Supervised task restarts",
+                &[("task", name)],
+            ),
+            failures: metrics::global().counter(
+                "atlas_supervisor_failures_total",
+                "Supervised tasks that failed and were not restarted",
+                &[("task", name)],
+            ),
+        };
+        self.tasks.push(SupervisedTask {
+            name: name.to_string(),
+            stage,
+            handle: tokio::spawn(run.supervise(task)),
+        });
+    }
+
+    //==========================================================================
+    /// Wait for the shutdown token, then shut down in stage order.
+    pub async fn run_until_shutdown(self) -> Vec<TaskReport> {
+        self.shutdown.cancelled().await;
+        self.shutdown().await
+    }
+
+    //==========================================================================
+    /// Wait for SIGINT/SIGTERM or the shutdown token, then shut down in stage
+    /// order.
+    pub async fn run_until_signal(self) -> AtlasResult<Vec<TaskReport>> {
+        tokio::select! {
+            signal = shutdown_signal() => info!("Received {}, shutting down", signal?),
+            _ = self.shutdown.cancelled() => info!("Shutdown requested"),
+        }
+        Ok(self.shutdown().await)
+    }
+
+    //==========================================================================
+    pub async fn shutdown(mut self) -> Vec<TaskReport> {
+        self.shutdown.cancel();
+        let mut reports = Vec::with_capacity(self.tasks.len());
+        for stage in Stage::ALL {
+            self.stages[stage as usize].cancel();
+            let deadline = tokio::time::Instant::now() + self.shutdown_timeout;
+            let (current, rest): (Vec<_>, Vec<_>) =
+                self.tasks.drain(..).partition(|t| t.stage == stage);
+            self.tasks = rest;
+            for mut task in current {
+                let exit = match tokio::time::timeout_at(deadline, &mut task.handle).await {
+                    Ok(Ok(exit)) => exit,
+                    Ok(Err(e)) => TaskExit::Failed {
+                        restarts: 0,
+                        error: e.to_string(),
+                    },
+                    Err(_) => {
+                        task.handle.abort();
+                        warn!(task = task.name.as_str(); "Task did not stop in time, aborted");
+                        TaskExit::Aborted
+                    }
+                };
+                reports.push(TaskReport {
+                    name: task.name,
+                    stage,
+                    exit,
+                });
+            }
+        }
+        reports
+    }
+}
+
+//==========================================================================
+struct TaskRun {
+    name: String,
+    token: CancellationToken,
+    restart: RestartPolicy,
+    max_restarts: u32,
+    backoff: RetryPolicy,
+    restarts: Counter,
+    failures: Counter,
+}
+
+//==========================================================================
+/// Aborts the wrapped run when the supervising future is dropped or aborted.
+struct AbortOnDrop(JoinHandle<AtlasResult<()>>);
+
+//==========================================================================
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+//==========================================================================
+impl TaskRun {
+    //==========================================================================
+    async fn supervise<F, Fut>(self, mut task: F) -> TaskExit
+    where
+        F: FnMut(CancellationToken) -> Fut,
+        Fut: Future<Output = AtlasResult<()>> + Send + 'static,
+    {
+        let name = self.name.as_str();
+        let mut restarts = 0;
+        loop {
+            // Each run gets its own task so a panic is caught as a JoinError.
+            let mut run = AbortOnDrop(tokio::spawn(task(self.token.clone())));
+            let outcome = (&mut run.0).await;
+            if self.token.is_cancelled() {
+                return match outcome {
+                    Ok(Err(e)) => {
+                        warn!(task = name; "Task failed while stopping: {}", e);
+                        TaskExit::Failed {
+                            restarts,
+                            error: e.to_string(),
+                        }
+                    }
+                    _ => TaskExit::Cancelled,
+                };
+            }
+            let error = match outcome {
+                Ok(Ok(())) if self.restart != RestartPolicy::Always => {
+                    info!(task = name; "Task completed");
+                    return TaskExit::Completed;
+                }
+                Ok(Ok(())) => "exited".to_string(),
+                Ok(Err(e)) => e.to_string(),
+                Err(e) => e.to_string(),
+            };
+            if self.restart == RestartPolicy::Never || restarts >= self.max_restarts {
+                self.failures.inc();
+                error!(task = name, restarts = restarts; "Task failed, not restarting: {}", error);
+                return TaskExit::Failed { restarts, error };
+            }
+            restarts += 1;
+            self.restarts.inc();
+            let delay = self.backoff.delay(restarts, &mut rand::thread_rng());
+            warn!(
+                task = name,
+                restarts = restarts;
+                "Task stopped ({}), restarting in {:?}", error, delay
+            );
+            tokio::select! {
+                _ = self.token.cancelled() => return TaskExit::Cancelled,
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    }
+}
+
+//==========================================================================
+/// Resolves with the name of the first SIGINT or SIGTERM received.
+#[cfg(unix)]
+pub async fn shutdown_signal() -> AtlasResult<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => Ok(result.map(|_| "SIGINT")?),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+//==========================================================================
+#[cfg(not(unix))]
+pub async fn shutdown_signal() -> AtlasResult<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("SIGINT")
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AtlasError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    fn supervisor(restart: RestartPolicy, max_restarts: u32) -> Supervisor {
+        let config = SupervisorConfig {
+            restart,
+            max_restarts,
+            shutdown_timeout_ms: 200,
+        };
+        let retry = RetryConfig {
+            initial_backoff_ms: 1,
+            max_backoff_ms: 2,
+            ..RetryConfig::default()
+        };
+        Supervisor::new(&config, &retry)
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_restarts_failed_task() {
+        let mut sup = supervisor(RestartPolicy::OnFailure, 5);
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        sup.spawn("flaky", Stage::Processor, move |_| {
+            let counter = counter.clone();
+            async move {
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(AtlasError::CustomError("boom".to_string())),
+                    1 => panic!("crashed"),
+                    _ => Ok(()),
+                }
+            }
+        });
+        let handle = sup.tasks.pop().unwrap().handle;
+        assert_eq!(handle.await.unwrap(), TaskExit::Completed);
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_gives_up_after_max_restarts() {
+        let mut sup = supervisor(RestartPolicy::OnFailure, 2);
+        sup.spawn("broken", Stage::Processor, |_| async {
+            Err(AtlasError::CustomError("down".to_string()))
+        });
+        let handle = sup.tasks.pop().unwrap().handle;
+        assert!(matches!(
+            handle.await.unwrap(),
+            TaskExit::Failed { restarts: 2, .. }
+        ));
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_ordered_shutdown_drains() {
+        let mut sup = supervisor(RestartPolicy::Never, 0);
+        let (tx, mut rx) = mpsc::channel::<u32>(1024);
+        let sent = Arc::new(AtomicU32::new(0));
+        let received = Arc::new(AtomicU32::new(0));
+
+        let (producer_sent, mut tx) = (sent.clone(), Some(tx));
+        sup.spawn("producer", Stage::Producer, move |token| {
+            let (sent, tx) = (producer_sent.clone(), tx.take().unwrap());
+            async move {
+                while !token.is_cancelled() {
+                    tx.send(sent.fetch_add(1, Ordering::SeqCst)).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+                Ok(())
+            }
+        });
+        let sink_received = received.clone();
+        sup.spawn("sink", Stage::Sink, move |_| {
+            let received = sink_received.clone();
+            let mut rx = std::mem::replace(&mut rx, mpsc::channel(1).1);
+            async move {
+                // Ignores its token; stops once the producer hangs up.
+                while rx.recv().await.is_some() {
+                    received.fetch_add(1, Ordering::SeqCst);
+                }
+                Ok(())
+            }
+        });
+        sup.spawn("stuck", Stage::Sink, |_| std::future::pending());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let reports = sup.shutdown().await;
+        let names: Vec<_> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["producer", "sink", "stuck"]);
+        assert_eq!(reports[0].exit, TaskExit::Cancelled);
+        // The sink may notice the closed channel before its own stage is cancelled.
+        assert!(matches!(
+            reports[1].exit,
+            TaskExit::Completed | TaskExit::Cancelled
+        ));
+        assert_eq!(reports[2].exit, TaskExit::Aborted);
+        assert!(sent.load(Ordering::SeqCst) > 0);
+        assert_eq!(sent.load(Ordering::SeqCst), received.load(Ordering::SeqCst));
+    }
+}
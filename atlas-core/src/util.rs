@@ -1,8 +1,7 @@
+use crate::clock::{self, SharedClock};
+use crate::config::LoggingConfig;
 use crate::error::AtlasError;
-use env_logger::Builder;
-use log::{error, info, LevelFilter};
-use std::env;
-use std::io::Write;
+use crate::logging;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 //==========================================================================
@@ -14,29 +13,68 @@ static LOGGER_INITIALIZED: AtomicBool = AtomicBool::new(false);
 impl AtlasUtil {
     //==========================================================================
     pub fn setup_logger() -> Result<(), AtlasError> {
-        if LOGGER_INITIALIZED.load(Ordering::Relaxed) {
+        Self::setup_logger_with(&LoggingConfig::default())
+    }
+
+    //==========================================================================
+    pub fn setup_logger_with(config: &LoggingConfig) -> Result<(), AtlasError> {
+        Self::setup_logger_with_clock(config, clock::system())
+    }
+
+    //==========================================================================
+    /// Log timestamps come from `clock`, e.g. a replay clock so a replayed
+    /// session logs with its recorded times.
+    pub fn setup_logger_with_clock(
+        config: &LoggingConfig,
+        clock: SharedClock,
+    ) -> Result<(), AtlasError> {
+        if LOGGER_INITIALIZED.swap(true, Ordering::Relaxed) {
             return Ok(());
         }
-        LOGGER_INITIALIZED.store(true, Ordering::Relaxed);
-        let mut builder = Builder::new();
-        builder.filter(None, LevelFilter::Info);
-        builder.format(|buf, record| {
-            use chrono::Local;
-            let now = Local::now();
-            let file = record.file().unwrap_or("<unknown>");
-            let line = record.line().unwrap_or(0);
-            writeln!(
-                buf,
-                "{} [{} {}:{}] {}: {}",
-                now.format("[%H:%M:%S.%3f]"),
-                record.level(),
-                file,
-                line,
-                record.target(),
-                record.args()
-            )
-        });
-        builder.try_init()?;
+        let dispatch = logging::build_dispatch_with_clock(config, clock).inspect_err(|_| {
+            LOGGER_INITIALIZED.store(false, Ordering::Relaxed);
+        })?;
+        dispatch.apply()?;
         Ok(())
     }
+
+    //==========================================================================
+    /// `scheme://host:port` of `url`. Providers put API keys in the path,
+    /// query or userinfo, so this is the form endpoints take in logs, metric
+    /// labels, errors and alerts.
+    pub fn redact_url(url: &str) -> String {
+        let (scheme, rest) = match url.split_once("://") {
+            Some((scheme, rest)) => (Some(scheme), rest),
+            None => (None, url),
+        };
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+        let authority = authority.rsplit_once('@').map_or(authority, |(_, a)| a);
+        match scheme {
+            Some(scheme) => format!("{}://{}", scheme, authority),
+            None => authority.to_string(),
+        }
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //==========================================================================
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            AtlasUtil::redact_url("https://solana-mainnet.core.chainstack.com/0123abcd"),
+            "https://solana-mainnet.core.chainstack.com"
+        );
+        assert_eq!(
+            AtlasUtil::redact_url("wss://user:pw@node.example.com:8900/?api-key=abc"),
+            "wss://node.example.com:8900"
+        );
+        assert_eq!(
+            AtlasUtil::redact_url("node.example.com/key"),
+            "node.example.com"
+        );
+    }
 }
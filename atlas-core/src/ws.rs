@@ -0,0 +1,771 @@
+use crate::config::{RetryConfig, WebSocketConfig};
+use crate::error::{AtlasError, AtlasResult};
+use crate::health::ConnectionState;
+use crate::metrics::{self, Counter};
+use crate::retry::RetryPolicy;
+use crate::util::AtlasUtil;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+//==========================================================================
+/// What the engine should send to open (and optionally close) one
+/// subscription. `routes` are keys known up front that notifications for this
+/// subscription will carry, e.g. Pyth feed ids; protocols that hand out ids in
+/// the ack learn their routes from it instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscribeRequest {
+    pub method: String,
+    pub params: Value,
+    pub unsubscribe_method: Option<String>,
+    pub routes: Vec<String>,
+}
+
+//==========================================================================
+impl SubscribeRequest {
+    //==========================================================================
+    pub fn new(method: &str, params: Value) -> Self {
+        SubscribeRequest {
+            method: method.to_string(),
+            params,
+            unsubscribe_method: None,
+            routes: Vec::new(),
+        }
+    }
+
+    //==========================================================================
+    pub fn with_unsubscribe(mut self, method: &str) -> Self {
+        self.unsubscribe_method = Some(method.to_string());
+        self
+    }
+
+    //==========================================================================
+    pub fn with_routes(mut self, routes: Vec<String>) -> Self {
+        self.routes = routes;
+        self
+    }
+}
+
+//==========================================================================
+/// A decoded inbound frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inbound {
+    /// A subscribe request was accepted. `request_id` of `None` acks the
+    /// oldest outstanding request; `route` is the server's id for it, if any.
+    Ack {
+        request_id: Option<u64>,
+        route: Option<String>,
+    },
+    Rejected {
+        request_id: Option<u64>,
+        message: String,
+    },
+    Notification {
+        route: String,
+        payload: Value,
+    },
+    Ignored,
+}
+
+//==========================================================================
+/// Wire format of a subscription protocol. JSON-RPC pubsub is provided by
+/// `JsonRpcProtocol`; services with their own framing implement this trait.
+pub trait WsProtocol: Send + Sync + 'static {
+    fn subscribe_frame(&self, request_id: u64, request: &SubscribeRequest) -> Value;
+
+    fn unsubscribe_frame(
+        &self,
+        request_id: u64,
+        request: &SubscribeRequest,
+        routes: &[String],
+    ) -> Option<Value>;
+
+    fn classify(&self, frame: Value) -> Inbound;
+}
+
+//==========================================================================
+/// Solana style JSON-RPC 2.0 pubsub: the ack's `result` is the subscription
+/// id and notifications carry it in `params.subscription`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonRpcProtocol;
+
+//==========================================================================
+impl WsProtocol for JsonRpcProtocol {
+    fn subscribe_frame(&self, request_id: u64, request: &SubscribeRequest) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": request_id,
+            "method": request.method,
+            "params": request.params,
+        })
+    }
+
+    fn unsubscribe_frame(
+        &self,
+        request_id: u64,
+        request: &SubscribeRequest,
+        routes: &[String],
+    ) -> Option<Value> {
+        let method = request.unsubscribe_method.as_ref()?;
+        let id: Value = serde_json::from_str(routes.first()?).ok()?;
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": request_id,
+            "method": method,
+            "params": [id],
+        }))
+    }
+
+    fn classify(&self, mut frame: Value) -> Inbound {
+        if let Some(params) = frame.get_mut("params") {
+            if let Some(route) = params.get("subscription") {
+                let route = route.to_string();
+                let payload = params
+                    .get_mut("result")
+                    .map(Value::take)
+                    .unwrap_or_default();
+                return Inbound::Notification { route, payload };
+            }
+        }
+        let request_id = frame.get("id").and_then(Value::as_u64);
+        if let Some(error) = frame.get("error") {
+            return Inbound::Rejected {
+                request_id,
+                message: error.to_string(),
+            };
+        }
+        match frame.get("result") {
+            Some(result) if request_id.is_some() => Inbound::Ack {
+                request_id,
+                route: Some(result.to_string()),
+            },
+            _ => Inbound::Ignored,
+        }
+    }
+}
+
+//==========================================================================
+/// A notification and when its frame was read off the socket, before any
+/// decoding, so consumers can measure their own processing latency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Received<T> {
+    pub value: T,
+    pub received_at: Instant,
+}
+
+type Delivery = AtlasResult<Received<Value>>;
+
+//==========================================================================
+enum Command {
+    Subscribe {
+        local_id: u64,
+        request: SubscribeRequest,
+        sender: mpsc::Sender<Delivery>,
+    },
+    Unsubscribe {
+        local_id: u64,
+    },
+}
+
+//==========================================================================
+/// Handle to a background task that owns one websocket connection. The task
+/// reconnects with backoff whenever the socket drops, goes idle, or a
+/// subscription ack does not arrive in time, and resubscribes everything
+/// still active. It exits once every handle and subscription is dropped.
+#[derive(Clone)]
+pub struct WsClient {
+    endpoint: String,
+    commands: mpsc::UnboundedSender<Command>,
+    next_id: Arc<AtomicU64>,
+    buffer: usize,
+    state: watch::Receiver<ConnectionState>,
+}
+
+//==========================================================================
+impl WsClient {
+    //==========================================================================
+    /// Start the connection task on the current tokio runtime.
+    pub fn spawn(
+        endpoint: &str,
+        protocol: Arc<dyn WsProtocol>,
+        config: &WebSocketConfig,
+        retry: &RetryConfig,
+    ) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let (state_tx, state) = watch::channel(ConnectionState::Connecting);
+        let label = AtlasUtil::redact_url(endpoint);
+        let engine = Engine {
+            url: endpoint.to_string(),
+            endpoint: label.clone(),
+            protocol,
+            ping_interval: Duration::from_millis(config.ping_interval_ms),
+            idle_timeout: Duration::from_millis(config.idle_timeout_ms),
+            ack_timeout: Duration::from_millis(config.ack_timeout_ms),
+            backoff: RetryPolicy::from_config(retry),
+            commands: receiver,
+            subscriptions: HashMap::new(),
+            next_request_id: 1,
+            state: state_tx,
+            reconnects: metrics::global().counter(
+                "atlas_ws_reconnects_total",
+                "Websocket reconnect attempts",
+                &[("endpoint", &label)],
+            ),
+            drops: metrics::global().counter(
+                "atlas_ws_dropped_notifications_total",
+                "Notifications dropped because a subscriber fell behind",
+                &[("endpoint", &label)],
+            ),
+        };
+        tokio::spawn(engine.run());
+        WsClient {
+            endpoint: label,
+            commands,
+            next_id: Arc::new(AtomicU64::new(1)),
+            buffer: config.subscriber_buffer.max(1),
+            state,
+        }
+    }
+
+    //==========================================================================
+    /// The endpoint without its path, query or credentials; safe to log.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    //==========================================================================
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    //==========================================================================
+    /// Follows the connection task through connects and reconnects.
+    pub fn watch_connection(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    //==========================================================================
+    /// Register a subscription. It is sent now if connected, otherwise as soon
+    /// as the connection is (re)established.
+    pub fn subscribe<T: DeserializeOwned>(
+        &self,
+        request: SubscribeRequest,
+    ) -> AtlasResult<Subscription<T>> {
+        let local_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(self.buffer);
+        self.commands
+            .send(Command::Subscribe {
+                local_id,
+                request,
+                sender,
+            })
+            .map_err(|_| AtlasError::websocket(&self.endpoint, "connection task stopped"))?;
+        Ok(Subscription {
+            local_id,
+            receiver,
+            commands: self.commands.clone(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+//==========================================================================
+/// Typed stream of notifications for one subscription. Payloads that do not
+/// deserialize into `T` surface as `Err` items. A subscription the server
+/// rejects yields the rejection and then ends; it is not retried.
+/// Dropping it unsubscribes.
+pub struct Subscription<T> {
+    local_id: u64,
+    receiver: mpsc::Receiver<Delivery>,
+    commands: mpsc::UnboundedSender<Command>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+//==========================================================================
+impl<T: DeserializeOwned> Stream for Subscription<T> {
+    type Item = AtlasResult<Received<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx).map(|item| {
+            item.map(|result| {
+                let received = result?;
+                Ok(Received {
+                    value: serde_json::from_value(received.value)?,
+                    received_at: received.received_at,
+                })
+            })
+        })
+    }
+}
+
+//==========================================================================
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Unsubscribe {
+            local_id: self.local_id,
+        });
+    }
+}
+
+//==========================================================================
+struct ActiveSubscription {
+    request: SubscribeRequest,
+    sender: mpsc::Sender<Delivery>,
+    learned_routes: Vec<String>,
+    pending: Option<(u64, Instant)>,
+}
+
+//==========================================================================
+impl ActiveSubscription {
+    fn matches(&self, route: &str) -> bool {
+        self.learned_routes.iter().any(|r| r == route)
+            || self.request.routes.iter().any(|r| r == route)
+    }
+}
+
+//==========================================================================
+enum SessionEnd {
+    Shutdown,
+    Disconnected(String),
+}
+
+//==========================================================================
+struct Engine {
+    url: String,
+    /// `url` redacted, for logs and metrics.
+    endpoint: String,
+    protocol: Arc<dyn WsProtocol>,
+    ping_interval: Duration,
+    idle_timeout: Duration,
+    ack_timeout: Duration,
+    backoff: RetryPolicy,
+    commands: mpsc::UnboundedReceiver<Command>,
+    subscriptions: HashMap<u64, ActiveSubscription>,
+    next_request_id: u64,
+    state: watch::Sender<ConnectionState>,
+    reconnects: Counter,
+    drops: Counter,
+}
+
+//==========================================================================
+impl Engine {
+    //==========================================================================
+    async fn run(mut self) {
+        let mut attempt: u32 = 0;
+        loop {
+            match connect_async(self.url.as_str()).await {
+                Ok((socket, _)) => {
+                    info!(endpoint = self.endpoint.as_str(); "WebSocket connected");
+                    attempt = 0;
+                    self.state.send_replace(ConnectionState::Connected);
+                    let end = self.session(socket).await;
+                    self.state.send_replace(ConnectionState::Disconnected);
+                    match end {
+                        SessionEnd::Shutdown => {
+                            info!(endpoint = self.endpoint.as_str(); "WebSocket client shut down");
+                            return;
+                        }
+                        SessionEnd::Disconnected(reason) => {
+                            warn!(endpoint = self.endpoint.as_str(); "WebSocket disconnected: {}", reason)
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.state.send_replace(ConnectionState::Disconnected);
+                    warn!(endpoint = self.endpoint.as_str(); "WebSocket connect failed: {}", e)
+                }
+            }
+            if self.commands.is_closed() && self.subscriptions.is_empty() {
+                return;
+            }
+            attempt = attempt.saturating_add(1);
+            self.reconnects.inc();
+            let delay = self.backoff.delay(attempt, &mut rand::thread_rng());
+            debug!(endpoint = self.endpoint.as_str(); "Reconnecting in {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    //==========================================================================
+    async fn session<S>(&mut self, socket: S) -> SessionEnd
+    where
+        S: Stream<Item = Result<Message, WsError>> + Sink<Message, Error = WsError> + Unpin,
+    {
+        let (mut write, mut read) = socket.split();
+        let ids: Vec<u64> = self.subscriptions.keys().copied().collect();
+        for local_id in ids {
+            if let Err(e) = self.send_subscribe(&mut write, local_id).await {
+                return SessionEnd::Disconnected(e.to_string());
+            }
+        }
+        let mut last_seen = Instant::now();
+        let mut ping = tokio::time::interval_at(last_seen + self.ping_interval, self.ping_interval);
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => {
+                    let Some(command) = command else {
+                        let _ = write.close().await;
+                        return SessionEnd::Shutdown;
+                    };
+                    if let Err(e) = self.handle_command(&mut write, command).await {
+                        return SessionEnd::Disconnected(e.to_string());
+                    }
+                }
+                message = read.next() => {
+                    let message = match message {
+                        None => return SessionEnd::Disconnected("stream ended".to_string()),
+                        Some(Err(e)) => return SessionEnd::Disconnected(e.to_string()),
+                        Some(Ok(message)) => message,
+                    };
+                    last_seen = Instant::now();
+                    match message {
+                        Message::Text(text) => self.handle_text(&text, last_seen),
+                        Message::Binary(bytes) => match String::from_utf8(bytes) {
+                            Ok(text) => self.handle_text(&text, last_seen),
+                            Err(e) => warn!(endpoint = self.endpoint.as_str(); "Non UTF-8 frame: {}", e),
+                        },
+                        Message::Ping(payload) => {
+                            if let Err(e) = write.send(Message::Pong(payload)).await {
+                                return SessionEnd::Disconnected(e.to_string());
+                            }
+                        }
+                        Message::Close(frame) => {
+                            return SessionEnd::Disconnected(format!("closed by server: {:?}", frame));
+                        }
+                        Message::Pong(_) | Message::Frame(_) => {}
+                    }
+                }
+                _ = ping.tick() => {
+                    if last_seen.elapsed() > self.idle_timeout {
+                        return SessionEnd::Disconnected(format!(
+                            "no traffic for {:?}",
+                            last_seen.elapsed()
+                        ));
+                    }
+                    let overdue = self.subscriptions.values().any(|s| {
+                        s.pending.is_some_and(|(_, sent)| sent.elapsed() > self.ack_timeout)
+                    });
+                    if overdue {
+                        return SessionEnd::Disconnected("subscription ack timed out".to_string());
+                    }
+                    if let Err(e) = write.send(Message::Ping(Vec::new())).await {
+                        return SessionEnd::Disconnected(e.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    //==========================================================================
+    async fn handle_command<W>(&mut self, write: &mut W, command: Command) -> Result<(), WsError>
+    where
+        W: Sink<Message, Error = WsError> + Unpin,
+    {
+        match command {
+            Command::Subscribe {
+                local_id,
+                request,
+                sender,
+            } => {
+                self.subscriptions.insert(
+                    local_id,
+                    ActiveSubscription {
+                        request,
+                        sender,
+                        learned_routes: Vec::new(),
+                        pending: None,
+                    },
+                );
+                self.send_subscribe(write, local_id).await
+            }
+            Command::Unsubscribe { local_id } => {
+                let Some(sub) = self.subscriptions.remove(&local_id) else {
+                    return Ok(());
+                };
+                let request_id = self.next_request_id();
+                let frame =
+                    self.protocol
+                        .unsubscribe_frame(request_id, &sub.request, &sub.learned_routes);
+                match frame {
+                    Some(frame) => write.send(Message::Text(frame.to_string())).await,
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    //==========================================================================
+    async fn send_subscribe<W>(&mut self, write: &mut W, local_id: u64) -> Result<(), WsError>
+    where
+        W: Sink<Message, Error = WsError> + Unpin,
+    {
+        let request_id = self.next_request_id();
+        let Some(sub) = self.subscriptions.get_mut(&local_id) else {
+            return Ok(());
+        };
+        sub.learned_routes.clear();
+        sub.pending = Some((request_id, Instant::now()));
+        let frame = self.protocol.subscribe_frame(request_id, &sub.request);
+        write.send(Message::Text(frame.to_string())).await
+    }
+
+    //==========================================================================
+    fn next_request_id(&mut self) -> u64 {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        id
+    }
+
+    //==========================================================================
+    fn handle_text(&mut self, text: &str, received_at: Instant) {
+        let frame: Value = match serde_json::from_str(text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!(endpoint = self.endpoint.as_str(); "Unparseable frame: {}", e);
+                return;
+            }
+        };
+        match self.protocol.classify(frame) {
+            Inbound::Ack { request_id, route } => {
+                if let Some((_, sub)) = self.pending_for(request_id) {
+                    sub.pending = None;
+                    sub.learned_routes.extend(route);
+                }
+            }
+            Inbound::Rejected {
+                request_id,
+                message,
+            } => {
+                // Resending a rejected request on every reconnect would only
+                // be rejected again: the subscription is dropped instead.
+                let rejected = self.pending_for(request_id).map(|(local_id, _)| local_id);
+                match rejected.and_then(|local_id| self.subscriptions.remove(&local_id)) {
+                    Some(sub) => {
+                        let err = AtlasError::protocol(
+                            &self.endpoint,
+                            format!("subscription rejected: {}", message),
+                        );
+                        tokio::spawn(async move {
+                            let _ = sub.sender.send(Err(err)).await;
+                        });
+                    }
+                    None => {
+                        warn!(endpoint = self.endpoint.as_str(); "Request rejected: {}", message)
+                    }
+                }
+            }
+            Inbound::Notification { route, payload } => {
+                let mut closed = Vec::new();
+                let mut delivered = false;
+                for (local_id, sub) in self.subscriptions.iter() {
+                    if !sub.matches(&route) {
+                        continue;
+                    }
+                    delivered = true;
+                    let received = Received {
+                        value: payload.clone(),
+                        received_at,
+                    };
+                    match sub.sender.try_send(Ok(received)) {
+                        Ok(()) => {}
+                        Err(mpsc::error::TrySendError::Full(_)) => self.drops.inc(),
+                        Err(mpsc::error::TrySendError::Closed(_)) => closed.push(*local_id),
+                    }
+                }
+                for local_id in closed {
+                    self.subscriptions.remove(&local_id);
+                }
+                if !delivered {
+                    debug!(endpoint = self.endpoint.as_str(); "No subscriber for route {}", route);
+                }
+            }
+            Inbound::Ignored => {}
+        }
+    }
+
+    //==========================================================================
+    /// The subscription waiting on `request_id`, or the oldest waiting one for
+    /// protocols whose acks carry no id.
+    fn pending_for(&mut self, request_id: Option<u64>) -> Option<(u64, &mut ActiveSubscription)> {
+        let mut pending = self
+            .subscriptions
+            .iter_mut()
+            .filter(|(_, s)| s.pending.is_some());
+        let (local_id, sub) = match request_id {
+            Some(id) => pending.find(|(_, s)| s.pending.is_some_and(|(pending, _)| pending == id)),
+            None => pending.min_by_key(|(_, s)| s.pending.map(|(id, _)| id)),
+        }?;
+        Some((*local_id, sub))
+    }
+}
+
+//==========================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Tick {
+        n: u64,
+    }
+
+    fn fast_config() -> (WebSocketConfig, RetryConfig) {
+        let ws = WebSocketConfig {
+            ping_interval_ms: 50,
+            idle_timeout_ms: 5_000,
+            ack_timeout_ms: 1_000,
+            subscriber_buffer: 16,
+        };
+        let retry = RetryConfig {
+            initial_backoff_ms: 10,
+            max_backoff_ms: 20,
+            ..RetryConfig::default()
+        };
+        (ws, retry)
+    }
+
+    //==========================================================================
+    #[test]
+    fn test_json_rpc_classify() {
+        let p = JsonRpcProtocol;
+        assert_eq!(
+            p.classify(json!({"jsonrpc": "2.0", "result": 23, "id": 4})),
+            Inbound::Ack {
+                request_id: Some(4),
+                route: Some("23".to_string())
+            }
+        );
+        assert_eq!(
+            p.classify(json!({
+                "jsonrpc": "2.0",
+                "method": "slotNotification",
+                "params": {"result": {"slot": 9}, "subscription": 23}
+            })),
+            Inbound::Notification {
+                route: "23".to_string(),
+                payload: json!({"slot": 9})
+            }
+        );
+        assert!(matches!(
+            p.classify(json!({"jsonrpc": "2.0", "error": {"code": -32601}, "id": 5})),
+            Inbound::Rejected {
+                request_id: Some(5),
+                ..
+            }
+        ));
+        let request =
+            SubscribeRequest::new("slotSubscribe", json!([])).with_unsubscribe("slotUnsubscribe");
+        assert_eq!(
+            p.unsubscribe_frame(7, &request, &["23".to_string()]),
+            Some(json!({"jsonrpc": "2.0", "id": 7, "method": "slotUnsubscribe", "params": [23]}))
+        );
+    }
+
+    //==========================================================================
+    /// Server that acks every subscription, sends `per_connection` ticks and
+    /// then drops the connection, for `connections` connections.
+    async fn flaky_server(listener: TcpListener, connections: u64, per_connection: u64) {
+        for conn in 0..connections {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(stream).await.unwrap();
+            let request = loop {
+                match ws.next().await.unwrap().unwrap() {
+                    Message::Text(text) => break serde_json::from_str::<Value>(&text).unwrap(),
+                    _ => continue,
+                }
+            };
+            assert_eq!(request["method"], "tickSubscribe");
+            let sub_id = 100 + conn;
+            let ack = json!({"jsonrpc": "2.0", "result": sub_id, "id": request["id"]});
+            ws.send(Message::Text(ack.to_string())).await.unwrap();
+            for i in 0..per_connection {
+                let n = conn * per_connection + i;
+                let note = json!({
+                    "jsonrpc": "2.0",
+                    "method": "tickNotification",
+                    "params": {"result": {"n": n}, "subscription": sub_id}
+                });
+                ws.send(Message::Text(note.to_string())).await.unwrap();
+            }
+            drop(ws);
+        }
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_reconnects_and_resubscribes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(flaky_server(listener, 3, 2));
+
+        let (ws, retry) = fast_config();
+        let client = WsClient::spawn(&endpoint, Arc::new(JsonRpcProtocol), &ws, &retry);
+        let mut ticks = client
+            .subscribe::<Tick>(SubscribeRequest::new("tickSubscribe", json!([])))
+            .unwrap();
+        let mut seen = Vec::new();
+        while seen.len() < 6 {
+            let tick = tokio::time::timeout(Duration::from_secs(5), ticks.next())
+                .await
+                .expect("stream stalled")
+                .unwrap()
+                .unwrap();
+            assert!(tick.received_at <= Instant::now());
+            seen.push(tick.value.n);
+        }
+        assert_eq!(seen, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    //==========================================================================
+    #[tokio::test]
+    async fn test_rejection_surfaces_as_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(stream).await.unwrap();
+            if let Some(Ok(Message::Text(text))) = ws.next().await {
+                let request: Value = serde_json::from_str(&text).unwrap();
+                let reply = json!({
+                    "jsonrpc": "2.0",
+                    "error": {"code": -32601, "message": "Method not found"},
+                    "id": request["id"]
+                });
+                ws.send(Message::Text(reply.to_string())).await.unwrap();
+            }
+            let _ = ws.next().await;
+        });
+
+        let (ws, retry) = fast_config();
+        let client = WsClient::spawn(&endpoint, Arc::new(JsonRpcProtocol), &ws, &retry);
+        let mut sub = client
+            .subscribe::<Tick>(SubscribeRequest::new("bogusSubscribe", json!([])))
+            .unwrap();
+        let item = tokio::time::timeout(Duration::from_secs(5), sub.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(item, Err(AtlasError::Protocol { .. })));
+        // Dropped rather than resent on the next connection.
+        let end = tokio::time::timeout(Duration::from_secs(5), sub.next())
+            .await
+            .unwrap();
+        assert!(end.is_none());
+        assert_eq!(client.connection_state(), ConnectionState::Connected);
+    }
+}
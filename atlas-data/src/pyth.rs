@@ -1,7 +1,18 @@
+use atlas_core::alert::{Alert, Alerter};
+use atlas_core::bus::{ChainEvent, EventBus, PriceUpdate};
+use atlas_core::clock::{self, Clock, SharedClock};
+use atlas_core::config::{AtlasConfig, RetryConfig, WebSocketConfig};
+use atlas_core::decimal::Decimal;
 use atlas_core::error::{AtlasError, AtlasResult};
+use atlas_core::health::{ComponentHealth, HealthRegistry};
+use atlas_core::metrics;
+use atlas_core::ratelimit::RateLimiter;
+use atlas_core::retry::CallPolicy;
+use atlas_core::supervisor::CancellationToken;
+use atlas_core::ws::{Inbound, SubscribeRequest, WsClient, WsProtocol};
 use dashmap::DashMap;
-use futures_util::{SinkExt, StreamExt};
-use log::info;
+use futures_util::StreamExt;
+use log::{info, warn};
 use reqwest::Client;
 use reqwest::Error;
 use serde::de::{self, Visitor};
@@ -10,7 +21,8 @@ use serde_json::json;
 use serde_json::Value;
 use std::fmt;
 use std::str::FromStr;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, WebSocketStream};
+use std::sync::Arc;
+use std::time::Duration;
 
 fn deserialize_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
@@ -26,7 +38,7 @@ where
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Price {
     #[serde(deserialize_with = "deserialize_from_string")]
-    pub conf: i32,
+    pub conf: u64,
     pub expo: i32,
     #[serde(deserialize_with = "deserialize_from_string")]
     pub price: i64,
@@ -35,22 +47,42 @@ pub struct Price {
 
 impl Price {
     pub fn new(conf: String, expo: i32, price: String, publish_time: i64) -> AtlasResult<Self> {
-        let conf_res = conf.parse::<i32>();
-        if conf_res.is_err() {
-            return Err(AtlasError::CustomError("Invalid conf".to_string()));
-        }
-        let price_res = price.parse::<i64>();
-        if price_res.is_err() {
-            return Err(AtlasError::CustomError("Invalid price".to_string()));
-        }
+        let conf = conf
+            .parse::<u64>()
+            .map_err(|e| AtlasError::decode("pyth price conf", e))?;
+        let price = price
+            .parse::<i64>()
+            .map_err(|e| AtlasError::decode("pyth price", e))?;
         Ok(Price {
-            conf: conf_res.unwrap(),
+            conf,
             expo,
-            price: price_res.unwrap(),
+            price,
             publish_time,
         })
     }
 
+    /// `price * 10^expo`, exactly.
+    pub fn value(&self) -> AtlasResult<Decimal> {
+        Decimal::from_price(self.price, self.expo)
+    }
+
+    /// `conf * 10^expo`, exactly.
+    pub fn confidence(&self) -> AtlasResult<Decimal> {
+        let conf =
+            i64::try_from(self.conf).map_err(|e| AtlasError::decode("pyth price conf", e))?;
+        Decimal::from_price(conf, self.expo)
+    }
+
+    /// Time since `publish_time` on `clock`; zero if published in the future.
+    pub fn age(&self, clock: &dyn Clock) -> Duration {
+        let published = chrono::DateTime::from_timestamp(self.publish_time, 0).unwrap_or_default();
+        clock.since(published)
+    }
+
+    pub fn is_stale(&self, clock: &dyn Clock, max_age: Duration) -> bool {
+        self.age(clock) > max_age
+    }
+
     pub fn to_dict(&self) -> serde_json::Value {
         serde_json::json!({
             "conf": self.conf,
@@ -62,14 +94,14 @@ impl Price {
 
     pub fn from_dict(price_dict: &PriceDict) -> AtlasResult<Self> {
         if price_dict.conf.is_empty() || price_dict.price.is_empty() {
-            return Err(AtlasError::CustomError("Empty conf or price".to_string()));
+            return Err(AtlasError::decode("pyth price", "empty conf or price"));
         }
-        Ok(Price {
-            conf: price_dict.conf.parse().unwrap(),
-            expo: price_dict.expo,
-            price: price_dict.price.parse().unwrap(),
-            publish_time: price_dict.publish_time,
-        })
+        Price::new(
+            price_dict.conf.clone(),
+            price_dict.expo,
+            price_dict.price.clone(),
+            price_dict.publish_time,
+        )
     }
 
     pub fn from_json_value(value: &Value) -> AtlasResult<Self> {
@@ -102,15 +134,104 @@ impl PriceDict {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct PriceFeed {
-    id: String,
-    price: Price,
-    ema_price: Price,
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PriceFeed {
+    pub id: String,
+    pub price: Price,
+    pub ema_price: Price,
+}
+
+/// A feed as listed by Hermes, e.g. `Crypto.SOL/USD`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeedMetadata {
+    pub id: String,
+    pub symbol: String,
+    pub asset_type: String,
+}
+
+impl FeedMetadata {
+    /// `symbol` matches either the full Hermes symbol or the part after the
+    /// asset type, ignoring case: `Crypto.SOL/USD` or `sol/usd`.
+    pub fn matches(&self, symbol: &str) -> bool {
+        let short = self
+            .symbol
+            .split_once('.')
+            .map_or(self.symbol.as_str(), |(_, s)| s);
+        self.symbol.eq_ignore_ascii_case(symbol) || short.eq_ignore_ascii_case(symbol)
+    }
+}
+
+/// `endpoint` is the redacted one; reqwest's own message would name the full
+/// URL, so it is dropped from `err`.
+fn http_error(endpoint: &str, err: reqwest::Error) -> AtlasError {
+    let err = err.without_url();
+    if let Some(status) = err.status() {
+        AtlasError::Http {
+            endpoint: endpoint.to_string(),
+            status: status.as_u16(),
+            message: err.to_string(),
+        }
+    } else if err.is_decode() {
+        AtlasError::decode("hermes response", err)
+    } else {
+        AtlasError::transport(endpoint, err)
+    }
 }
 
-const HERMES_ENDPOINT_HTTPS: &str = "https://hermes.pyth.network";
-const HERMES_ENDPOINT_WSS: &str = "wss://hermes.pyth.network/ws";
+/// Hermes speaks its own framing rather than JSON-RPC: acks carry no request
+/// id and updates are keyed by feed id.
+struct HermesProtocol;
+
+impl HermesProtocol {
+    /// Hermes reports feed ids lowercase and without the `0x` prefix.
+    fn route(feed_id: &str) -> String {
+        feed_id.trim_start_matches("0x").to_lowercase()
+    }
+}
+
+impl WsProtocol for HermesProtocol {
+    fn subscribe_frame(&self, _request_id: u64, request: &SubscribeRequest) -> Value {
+        let mut frame = request.params.clone();
+        frame["type"] = json!(request.method);
+        frame
+    }
+
+    fn unsubscribe_frame(
+        &self,
+        _request_id: u64,
+        request: &SubscribeRequest,
+        _routes: &[String],
+    ) -> Option<Value> {
+        Some(json!({
+            "type": request.unsubscribe_method.as_deref()?,
+            "ids": request.params["ids"],
+        }))
+    }
+
+    fn classify(&self, mut frame: Value) -> Inbound {
+        match frame["type"].as_str() {
+            Some("response") if frame["status"] == "success" => Inbound::Ack {
+                request_id: None,
+                route: None,
+            },
+            Some("response") => Inbound::Rejected {
+                request_id: None,
+                message: frame["error"].to_string(),
+            },
+            Some("price_update") => {
+                let feed = frame["price_feed"].take();
+                match feed["id"].as_str() {
+                    Some(id) => Inbound::Notification {
+                        route: Self::route(id),
+                        payload: feed,
+                    },
+                    None => Inbound::Ignored,
+                }
+            }
+            _ => Inbound::Ignored,
+        }
+    }
+}
 
 pub struct HermesClient {
     feed_ids: Vec<String>,
@@ -119,25 +240,71 @@ pub struct HermesClient {
     endpoint: String,
     ws_endpoint: String,
     feed_batch_size: usize,
+    http: Client,
+    policy: CallPolicy,
+    websocket: WebSocketConfig,
+    retry: RetryConfig,
+    bus: Option<EventBus>,
+    clock: SharedClock,
+    health: ComponentHealth,
+    alerter: Alerter,
+    stale_after: Duration,
 }
 
 impl HermesClient {
-    pub fn new(
-        feed_ids: Vec<String>,
-        endpoint: Option<String>,
-        ws_endpoint: Option<String>,
-        feed_batch_size: Option<usize>,
-    ) -> Self {
+    pub fn new(config: &AtlasConfig) -> Self {
+        let pyth = &config.pyth;
         HermesClient {
-            feed_ids: feed_ids.clone(),
-            pending_feed_ids: feed_ids,
+            feed_ids: Vec::new(),
+            pending_feed_ids: pyth.feed_ids.clone(),
             prices_dict: DashMap::new(),
-            endpoint: endpoint.unwrap_or_else(|| HERMES_ENDPOINT_HTTPS.to_string()),
-            ws_endpoint: ws_endpoint.unwrap_or_else(|| HERMES_ENDPOINT_WSS.to_string()),
-            feed_batch_size: feed_batch_size.unwrap_or(100), // default to 100
+            endpoint: pyth.https_url.clone(),
+            ws_endpoint: pyth.wss_url.clone(),
+            feed_batch_size: pyth.feed_batch_size,
+            http: Client::new(),
+            policy: CallPolicy::from_config(&pyth.https_url, &config.retry),
+            websocket: config.websocket.clone(),
+            retry: config.retry.clone(),
+            bus: None,
+            clock: clock::system(),
+            health: ComponentHealth::default(),
+            alerter: Alerter::default(),
+            stale_after: Duration::from_millis(config.health.price_stale_ms),
         }
     }
 
+    /// Publish every streamed price update to `bus` as well.
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    /// Report stream connection, messages and per-feed prices as `pyth`.
+    pub fn with_health(mut self, registry: &HealthRegistry) -> Self {
+        self.health = registry.component("pyth");
+        self
+    }
+
+    /// Alert on stream disconnects and on prices older than
+    /// `health.price_stale_ms` when they arrive.
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        self.alerter = alerter;
+        self
+    }
+
+    /// Wait for budget from `limiter` before each Hermes HTTP request.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.policy = self.policy.with_rate_limiter(limiter);
+        self
+    }
+
+    /// Measure price staleness and run HTTP backoff on `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.policy = self.policy.with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
     fn extract_price_feed_v1(data: &serde_json::Value) -> AtlasResult<PriceFeed> {
         let price = Price::from_json_value(&data["price"])?;
         let ema_price = Price::from_json_value(&data["ema_price"])?;
@@ -174,16 +341,18 @@ impl HermesClient {
         feed_ids: Vec<String>,
         version: u8,
     ) -> AtlasResult<Vec<PriceFeed>> {
-        let url = match version {
-            1 => format!("{}/api/latest_price_feeds", self.endpoint),
-            2 => format!("{}/v2/updates/price/latest", self.endpoint),
+        // The request path is also the operation rate limits are keyed on.
+        let path = match version {
+            1 => "/api/latest_price_feeds",
+            2 => "/v2/updates/price/latest",
             _ => {
-                return Err(AtlasError::CustomError(format!(
-                    "Unsupported version {}",
-                    version
-                )))
+                return Err(AtlasError::config(
+                    "pyth.version",
+                    format!("unsupported version {}", version),
+                ))
             }
         };
+        let url = format!("{}{}", self.endpoint, path);
 
         let params = match version {
             1 => vec![
@@ -195,98 +364,209 @@ impl HermesClient {
                 ("encoding", "base64".to_string()),
                 ("parsed", "true".to_string()),
             ],
-            _ => return Err(AtlasError::CustomError("Unsupported version".to_string())),
+            _ => unreachable!(),
         };
 
-        let client = Client::new();
-        let response = client
-            .get(&url)
-            .query(&params)
-            .send()
-            .await
-            .map_err(|e| AtlasError::CustomError(e.to_string()))?;
-        let data: Value = response
-            .json()
-            .await
-            .map_err(|e| AtlasError::CustomError(e.to_string()))?;
-        if version == 1 {
+        let data: Value = self
+            .policy
+            .call(path, || async {
+                let response = self
+                    .http
+                    .get(&url)
+                    .query(&params)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| http_error(self.policy.endpoint(), e))?;
+                response
+                    .json()
+                    .await
+                    .map_err(|e| http_error(self.policy.endpoint(), e))
+            })
+            .await?;
+        let results = if version == 1 {
             let mut results = Vec::new();
             if let Some(arr) = data.as_array() {
                 for res in arr {
-                    results.push(Self::extract_price_feed_v1(&data)?);
+                    results.push(Self::extract_price_feed_v1(res)?);
                 }
             }
-            Ok(results)
+            results
         } else if version == 2 {
-            Self::extract_price_feed_v2(&data)
+            Self::extract_price_feed_v2(&data)?
         } else {
-            Err(AtlasError::CustomError(format!(
-                "Unsupported version {}",
-                version
-            )))
+            unreachable!()
+        };
+        results.iter().for_each(|feed| self.record_update(feed));
+        Ok(results)
+    }
+
+    fn record_update(&self, feed: &PriceFeed) {
+        let registry = metrics::global();
+        let labels = [("feed", feed.id.as_str())];
+        registry
+            .counter(
+                "atlas_pyth_updates_total",
+                "Pyth price updates received, by feed",
+                &labels,
+            )
+            .inc();
+        registry
+            .gauge(
+                "atlas_pyth_price_staleness_seconds",
+                "Age of the latest price by publish time when it was received",
+                &labels,
+            )
+            .set(feed.price.age(self.clock.as_ref()).as_secs_f64());
+        self.health.price(&feed.id, feed.price.publish_time);
+        let age = feed.price.age(self.clock.as_ref());
+        if age > self.stale_after {
+            self.alerter.fire(Alert::stale_price("pyth", &feed.id, age));
+        }
+    }
+
+    /// Feeds whose symbol or description contains `query`.
+    pub async fn search_feeds(&self, query: &str) -> AtlasResult<Vec<FeedMetadata>> {
+        let path = "/v2/price_feeds";
+        let url = format!("{}{}", self.endpoint, path);
+        let data: Value = self
+            .policy
+            .call(path, || async {
+                let response = self
+                    .http
+                    .get(&url)
+                    .query(&[("query", query)])
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| http_error(self.policy.endpoint(), e))?;
+                response
+                    .json()
+                    .await
+                    .map_err(|e| http_error(self.policy.endpoint(), e))
+            })
+            .await?;
+        let feeds = data
+            .as_array()
+            .ok_or_else(|| AtlasError::decode("hermes price feeds", "expected an array"))?;
+        Ok(feeds
+            .iter()
+            .map(|feed| FeedMetadata {
+                id: feed["id"].as_str().unwrap_or_default().to_string(),
+                symbol: feed["attributes"]["symbol"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                asset_type: feed["attributes"]["asset_type"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+            .collect())
+    }
+
+    /// Feed id for `symbol`, e.g. `SOL/USD`. Fails unless exactly one feed
+    /// matches.
+    pub async fn resolve_symbol(&self, symbol: &str) -> AtlasResult<String> {
+        let base = symbol.split('/').next().unwrap_or(symbol);
+        let found: Vec<_> = self
+            .search_feeds(base)
+            .await?
+            .into_iter()
+            .filter(|f| f.matches(symbol))
+            .collect();
+        match found.as_slice() {
+            [feed] => Ok(feed.id.clone()),
+            [] => Err(AtlasError::config(
+                "pyth symbol",
+                format!("no feed matches `{}`", symbol),
+            )),
+            many => Err(AtlasError::config(
+                "pyth symbol",
+                format!(
+                    "`{}` is ambiguous: {}",
+                    symbol,
+                    many.iter()
+                        .map(|f| f.symbol.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )),
         }
     }
 
-    async fn add_feed_ids(&mut self, feed_ids: Vec<String>) {
+    pub async fn add_feed_ids(&mut self, feed_ids: Vec<String>) {
         self.pending_feed_ids.extend(feed_ids);
     }
 
-    async fn ws_pyth_prices(&mut self, version: u32) -> Result<(), Box<dyn std::error::Error>> {
+    /// Stream price updates until `shutdown` is cancelled.
+    pub async fn ws_pyth_prices(
+        &mut self,
+        version: u32,
+        shutdown: &CancellationToken,
+    ) -> AtlasResult<()> {
         if version != 1 {
-            return Err("Unsupported version".into());
+            return Err(AtlasError::config(
+                "pyth.version",
+                format!("unsupported websocket version {}", version),
+            ));
         }
-        let (mut ws_stream, _) = connect_async(&self.ws_endpoint).await?;
-        loop {
-            // Add new price feed ids to the ws subscription
-            if !self.pending_feed_ids.is_empty() {
-                let json_subscribe = json!({
-                    "ids": self.pending_feed_ids,
-                    "type": "subscribe",
-                    "verbose": true,
-                    "binary": true,
-                });
-                ws_stream
-                    .send(Message::Text(json_subscribe.to_string()))
-                    .await?;
-                self.feed_ids.extend(self.pending_feed_ids.clone());
-                self.pending_feed_ids.clear();
-            }
+        let client = WsClient::spawn(
+            &self.ws_endpoint,
+            Arc::new(HermesProtocol),
+            &self.websocket,
+            &self.retry,
+        );
+        self.health.follow_connection(client.watch_connection());
+        self.alerter
+            .watch_connection("pyth", client.endpoint(), client.watch_connection());
+        let ids = std::mem::take(&mut self.pending_feed_ids);
+        let request = SubscribeRequest::new(
+            "subscribe",
+            json!({"ids": ids, "verbose": true, "binary": true}),
+        )
+        .with_unsubscribe("unsubscribe")
+        .with_routes(ids.iter().map(|id| HermesProtocol::route(id)).collect());
+        let mut updates = client.subscribe::<PriceFeed>(request)?;
+        self.feed_ids.extend(ids);
 
-            // Wait for a message from the websocket
-            let msg = ws_stream.next().await.unwrap()?;
-            match msg {
-                Message::Text(text) => {
-                    let msg: Value = serde_json::from_str(&text)?;
-                    if msg.get("type") == Some(&Value::String("response".to_string())) {
-                        if msg.get("status") != Some(&Value::String("success".to_string())) {
-                            return Err("Error subscribing to websocket".into());
-                        }
-                    }
-                    if msg.get("type") == Some(&Value::String("price_update".to_string())) {
-                        if let Some(price_feed) = msg.get("price_feed") {
-                            if let Some(feed_id) = price_feed.get("id").and_then(|id| id.as_str()) {
-                                let new_feed: PriceFeed =
-                                    serde_json::from_value(price_feed.clone())?;
-                                info!("Received price feed: {:?}", new_feed);
-                                self.prices_dict.insert(feed_id.to_string(), new_feed);
-                            }
-                        }
-                    }
-                }
-                Message::Close(_) => {
-                    println!("WebSocket closed");
-                    break;
+        loop {
+            let update = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                update = updates.next() => update,
+            };
+            let Some(update) = update else {
+                break;
+            };
+            self.health.message();
+            let new_feed = match update {
+                Ok(received) => received.value,
+                Err(e @ AtlasError::Protocol { .. }) => return Err(e),
+                Err(e) => {
+                    warn!(endpoint = client.endpoint(); "Dropping price update: {}", e);
+                    continue;
                 }
-                _ => continue,
+            };
+            info!("Received price feed: {:?}", new_feed);
+            self.record_update(&new_feed);
+            if let Some(bus) = &self.bus {
+                bus.publish(ChainEvent::Price(PriceUpdate {
+                    feed_id: new_feed.id.clone(),
+                    price: new_feed.price.price,
+                    conf: new_feed.price.conf,
+                    expo: new_feed.price.expo,
+                    publish_time: new_feed.price.publish_time,
+                }));
             }
+            self.prices_dict.insert(new_feed.id.clone(), new_feed);
         }
-
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use atlas_core::clock::ManualClock;
     use atlas_core::util::AtlasUtil;
 
     const ID: &'static str = "0x63f341689d98a12ef60a5cff1d7f85c70a9e17bf1575f0e7c0b2512d48b1c8b3";
@@ -295,7 +575,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_feed_ids() {
         AtlasUtil::setup_logger().unwrap();
-        let client = HermesClient::new(vec![], None, None, None);
+        let client = HermesClient::new(&AtlasConfig::default());
         let id = "0x63f341689d98a12ef60a5cff1d7f85c70a9e17bf1575f0e7c0b2512d48b1c8b3";
         let feed_ids = client
             .get_pyth_prices_latest(vec![id.to_string()], 2)
@@ -303,11 +583,77 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_price_scaling() {
+        let dict = PriceDict {
+            conf: "3000000000".to_string(),
+            expo: -8,
+            price: "6512345678901".to_string(),
+            publish_time: 0,
+        };
+        let price = dict.to_price().unwrap();
+        assert_eq!(price.conf, 3_000_000_000);
+        assert_eq!(price.value().unwrap().to_string(), "65123.45678901");
+        assert_eq!(price.confidence().unwrap().to_string(), "30.00000000");
+    }
+
+    #[test]
+    fn test_price_staleness() {
+        let price = Price::new("1".into(), -8, "100".into(), 1_733_011_200).unwrap();
+        let clock = ManualClock::new(chrono::DateTime::from_timestamp(1_733_011_200, 0).unwrap());
+        assert_eq!(price.age(&clock), Duration::ZERO);
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(price.age(&clock), Duration::from_secs(45));
+        assert!(!price.is_stale(&clock, Duration::from_secs(60)));
+        clock.advance(Duration::from_secs(30));
+        assert!(price.is_stale(&clock, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_feed_symbol_match() {
+        let feed = FeedMetadata {
+            id: ID[2..].to_string(),
+            symbol: "Crypto.SOL/USD".to_string(),
+            asset_type: "Crypto".to_string(),
+        };
+        assert!(feed.matches("Crypto.SOL/USD"));
+        assert!(feed.matches("sol/usd"));
+        assert!(!feed.matches("SOL"));
+        assert!(!feed.matches("Crypto.SOL/USDC"));
+    }
+
+    #[test]
+    fn test_hermes_protocol() {
+        let protocol = HermesProtocol;
+        assert_eq!(
+            protocol.classify(json!({"type": "response", "status": "success"})),
+            Inbound::Ack {
+                request_id: None,
+                route: None
+            }
+        );
+        assert!(matches!(
+            protocol.classify(json!({"type": "response", "status": "error", "error": "bad id"})),
+            Inbound::Rejected { .. }
+        ));
+        let update = json!({"type": "price_update", "price_feed": {"id": &ID[2..]}});
+        assert_eq!(
+            protocol.classify(update),
+            Inbound::Notification {
+                route: HermesProtocol::route(ID),
+                payload: json!({"id": &ID[2..]})
+            }
+        );
+    }
+
     #[tokio::test]
     async fn test_stream_prices() {
         AtlasUtil::setup_logger().unwrap();
-        let mut client = HermesClient::new(vec![], None, None, None);
+        let mut client = HermesClient::new(&AtlasConfig::default());
         client.add_feed_ids(vec![ID.to_string()]).await;
-        client.ws_pyth_prices(1).await.unwrap();
+        client
+            .ws_pyth_prices(1, &CancellationToken::new())
+            .await
+            .unwrap();
     }
 }
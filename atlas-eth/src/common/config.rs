@@ -1,6 +1,7 @@
+use atlas_core::config::{AlertingConfig, AtlasConfig, ConfigLoader, TelegramAlertConfig};
 use atlas_core::error::AtlasError;
+use atlas_core::secret::{Secret, SecretSource};
 use serde::{Deserialize, Serialize};
-use std::fs;
 
 //==========================================================================
 #[derive(Deserialize, Debug)]
@@ -13,14 +14,14 @@ pub struct ServerConfig {
 #[derive(Deserialize, Debug)]
 pub struct BotConfig {
     address: String,
-    private_key: String,
-    identity_key: String,
+    private_key: Secret,
+    identity_key: Secret,
 }
 
 //==========================================================================
 #[derive(Deserialize, Debug)]
 pub struct TelegramConfig {
-    token: String,
+    token: Secret,
     chat_id: String,
 }
 
@@ -47,11 +48,71 @@ pub struct AtlasEnv {
     environment: Environment,
 }
 
+//==========================================================================
+/// `[bot]` as written, before its keys are loaded.
+#[derive(Deserialize)]
+struct BotSources {
+    address: String,
+    private_key: SecretSource,
+    identity_key: SecretSource,
+}
+
+//==========================================================================
+#[derive(Deserialize)]
+struct TelegramSources {
+    token: SecretSource,
+    chat_id: String,
+}
+
 //==========================================================================
 impl AtlasEnv {
     //==========================================================================
     pub fn new(config_path: &str) -> Result<Self, AtlasError> {
-        Ok(toml::from_str(&(fs::read_to_string(config_path)?))?)
+        Self::from_loader(&AtlasConfig::loader(config_path)?)
+    }
+
+    //==========================================================================
+    pub fn from_loader(loader: &ConfigLoader) -> Result<Self, AtlasError> {
+        Self::from_loader_with_env(loader, |var| std::env::var(var).ok())
+    }
+
+    //==========================================================================
+    /// Like `from_loader`, with secrets' environment variables read through
+    /// `env`.
+    pub fn from_loader_with_env<F>(loader: &ConfigLoader, env: F) -> Result<Self, AtlasError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let bot: BotSources = loader.extract_section("bot")?;
+        let telegram: TelegramSources = loader.extract_section("telegram")?;
+        Ok(AtlasEnv {
+            server: loader.extract_section("server")?,
+            bot: BotConfig {
+                address: bot.address,
+                private_key: Secret::load_with_env(&bot.private_key, &env)?,
+                identity_key: Secret::load_with_env(&bot.identity_key, &env)?,
+            },
+            telegram: TelegramConfig {
+                token: Secret::load_with_env(&telegram.token, &env)?,
+                chat_id: telegram.chat_id,
+            },
+            settings: loader.extract_section("settings")?,
+            environment: loader.extract_section("environment")?,
+        })
+    }
+
+    //==========================================================================
+    /// Alerting through `[telegram]`, switched on by `settings.use_alert`.
+    pub fn alerting(&self) -> AlertingConfig {
+        AlertingConfig {
+            enabled: self.settings.use_alert,
+            telegram: Some(TelegramAlertConfig {
+                token: self.telegram.token.clone(),
+                chat_id: self.telegram.chat_id.clone(),
+                ..TelegramAlertConfig::default()
+            }),
+            ..AlertingConfig::default()
+        }
     }
 }
 
@@ -62,14 +123,38 @@ mod tests {
 
     use super::*;
     use crate::test::DEV_CONFIG;
+    use std::collections::HashMap;
 
     //==========================================================================
     #[test]
     fn test_env() {
-        let env = AtlasEnv::new(DEV_CONFIG);
+        let vars = HashMap::from([
+            ("DEV_BOT_PRIVATE_KEY", "0xprivate"),
+            ("DEV_BOT_IDENTITY_KEY", "0xidentity"),
+            ("DEV_TELEGRAM_TOKEN", "123:telegram"),
+        ]);
+        let env = |var: &str| vars.get(var).map(|v| v.to_string());
+        let loader = ConfigLoader::new().with_file(DEV_CONFIG).unwrap();
+        let env = AtlasEnv::from_loader_with_env(&loader, env);
         if !env.is_ok() {
             assert!(false, "{}", format!("{:?}", env.err()));
         }
+        let env = env.unwrap();
+        assert_eq!(env.bot.private_key.expose_str().unwrap(), "0xprivate");
+        assert_eq!(env.telegram.token.expose_str().unwrap(), "123:telegram");
+        let debug = format!("{:?}", env);
+        assert!(!debug.contains("0xprivate") && !debug.contains("0xidentity"));
+        assert!(!debug.contains("123:telegram"));
+        let alerting = env.alerting();
+        assert!(!alerting.enabled);
+        assert_eq!(alerting.telegram.unwrap().chat_id, "...");
+
+        let missing = AtlasEnv::from_loader_with_env(&loader, |_| None).unwrap_err();
+        assert!(
+            missing.to_string().contains("DEV_BOT_PRIVATE_KEY"),
+            "{}",
+            missing
+        );
     }
 
     //==========================================================================
@@ -0,0 +1,121 @@
+use alloy::consensus::BlockHeader;
+use alloy::eips::BlockNumberOrTag;
+use alloy::providers::{Provider, ProviderBuilder, RootProvider};
+use alloy::rpc::types::BlockTransactionsKind;
+use alloy::transports::http::{Client, Http};
+use alloy::transports::{RpcError, TransportError};
+use atlas_core::config::AtlasConfig;
+use atlas_core::error::{AtlasError, AtlasResult};
+use atlas_core::ratelimit::RateLimiter;
+use atlas_core::retry::CallPolicy;
+use atlas_core::util::AtlasUtil;
+
+//==========================================================================
+/// Gas figures of a block needed to project the next base fee.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockGas {
+    pub number: u64,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub base_fee_per_gas: u64,
+}
+
+//==========================================================================
+/// HTTP provider whose calls all go through the configured retry policy and
+/// circuit breaker.
+pub struct EthProvider {
+    provider: RootProvider<Http<Client>>,
+    endpoint: String,
+    policy: CallPolicy,
+}
+
+//==========================================================================
+impl EthProvider {
+    //==========================================================================
+    pub fn new(config: &AtlasConfig) -> AtlasResult<Self> {
+        let eth = config
+            .ethereum
+            .as_ref()
+            .ok_or_else(|| AtlasError::config("ethereum", "section is missing"))?;
+        let url = eth
+            .https_url
+            .parse()
+            .map_err(|e| AtlasError::config("ethereum.https_url", e))?;
+        Ok(EthProvider {
+            provider: ProviderBuilder::new().on_http(url),
+            endpoint: AtlasUtil::redact_url(&eth.https_url),
+            policy: CallPolicy::from_config(&eth.https_url, &config.retry),
+        })
+    }
+
+    //==========================================================================
+    /// Wait for budget from `limiter` before each call to the node.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.policy = self.policy.with_rate_limiter(limiter);
+        self
+    }
+
+    //==========================================================================
+    pub async fn get_block_number(&self) -> AtlasResult<u64> {
+        self.policy
+            .call("eth_blockNumber", || async {
+                self.provider
+                    .get_block_number()
+                    .await
+                    .map_err(|e| provider_error(&self.endpoint, "eth_blockNumber", e))
+            })
+            .await
+    }
+
+    //==========================================================================
+    pub async fn get_gas_price(&self) -> AtlasResult<u128> {
+        self.policy
+            .call("eth_gasPrice", || async {
+                self.provider
+                    .get_gas_price()
+                    .await
+                    .map_err(|e| provider_error(&self.endpoint, "eth_gasPrice", e))
+            })
+            .await
+    }
+
+    //==========================================================================
+    pub async fn get_block_gas(&self, number: BlockNumberOrTag) -> AtlasResult<BlockGas> {
+        let method = "eth_getBlockByNumber";
+        let block = self
+            .policy
+            .call(method, || async {
+                self.provider
+                    .get_block_by_number(number, BlockTransactionsKind::Hashes)
+                    .await
+                    .map_err(|e| provider_error(&self.endpoint, method, e))
+            })
+            .await?
+            .ok_or_else(|| {
+                AtlasError::protocol(&self.endpoint, format!("block {} not found", number))
+            })?;
+        let header = &block.header;
+        Ok(BlockGas {
+            number: header.number(),
+            gas_used: header.gas_used(),
+            gas_limit: header.gas_limit(),
+            base_fee_per_gas: header.base_fee_per_gas().unwrap_or_default(),
+        })
+    }
+}
+
+//==========================================================================
+fn provider_error(endpoint: &str, method: &str, err: TransportError) -> AtlasError {
+    match err {
+        RpcError::ErrorResp(payload) => AtlasError::Rpc {
+            endpoint: endpoint.to_string(),
+            method: method.to_string(),
+            code: Some(payload.code),
+            message: payload.message.to_string(),
+        },
+        RpcError::SerError(e) => AtlasError::decode(method, e),
+        RpcError::DeserError { err, .. } => AtlasError::decode(method, err),
+        RpcError::Transport(e) => AtlasError::transport(endpoint, e),
+        other => AtlasError::rpc(endpoint, method, other),
+    }
+}
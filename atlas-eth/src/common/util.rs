@@ -2,7 +2,7 @@ use alloy_primitives::U256;
 use rand::Rng;
 
 //==============================================================================
-struct AtlasEthUtil {}
+pub struct AtlasEthUtil {}
 
 impl AtlasEthUtil {
     //==========================================================================
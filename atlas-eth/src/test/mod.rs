@@ -1,2 +1 @@
-pub const DEV_CONFIG: &'static str =
-    "C:\\Users\\natha\\OneDrive\\Desktop\\Rust\\Atlas\\atlas-eth\\src\\test\\dev.toml";
+pub const DEV_CONFIG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/test/dev.toml");
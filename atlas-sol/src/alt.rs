@@ -0,0 +1,544 @@
+use crate::transaction::ADDRESS_LOOKUP_PROGRAM_ID;
+use atlas_core::error::{AtlasError, AtlasResult};
+use atlas_core::metrics::{self, Counter};
+use atlas_core::util::AtlasUtil;
+use log::{debug, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::clock::Slot;
+use solana_sdk::message::v0::{LoadedAddresses, MessageAddressTableLookup};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Fixed size of the table header; addresses start right after it.
+const META_SIZE: usize = 56;
+const LOOKUP_TABLE_DISCRIMINATOR: u32 = 1;
+
+pub type AccountsFuture<'a> =
+    Pin<Box<dyn Future<Output = AtlasResult<Vec<Option<Account>>>> + Send + 'a>>;
+
+//=======================================================================
+/// Where `AltResolver` fetches the tables it has not cached.
+pub trait TableSource: Send + Sync {
+    /// Names the source in logs; never carries credentials.
+    fn name(&self) -> String;
+
+    /// One entry per key, `None` for accounts that do not exist.
+    fn fetch_accounts<'a>(&'a self, keys: &'a [Pubkey]) -> AccountsFuture<'a>;
+}
+
+//=======================================================================
+impl TableSource for RpcClient {
+    fn name(&self) -> String {
+        AtlasUtil::redact_url(&self.url())
+    }
+
+    fn fetch_accounts<'a>(&'a self, keys: &'a [Pubkey]) -> AccountsFuture<'a> {
+        Box::pin(async move {
+            self.get_multiple_accounts(keys).await.map_err(|e| {
+                let url = self.url();
+                let endpoint = AtlasUtil::redact_url(&url);
+                AtlasError::transport(&endpoint, e.to_string().replace(&url, &endpoint))
+            })
+        })
+    }
+}
+
+//=======================================================================
+/// An address lookup table account as stored by the ALT program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupTable {
+    pub address: Pubkey,
+    pub deactivation_slot: Slot,
+    pub last_extended_slot: Slot,
+    /// Addresses from here on were added in `last_extended_slot`.
+    pub last_extended_slot_start_index: u8,
+    pub authority: Option<Pubkey>,
+    pub addresses: Vec<Pubkey>,
+}
+
+//=======================================================================
+impl LookupTable {
+    //=======================================================================
+    pub fn decode(address: Pubkey, data: &[u8]) -> AtlasResult<Self> {
+        let err = |msg: String| {
+            AtlasError::decode("address lookup table", format!("{}: {}", address, msg))
+        };
+        if data.len() < META_SIZE {
+            return Err(err(format!(
+                "{} bytes is shorter than the header",
+                data.len()
+            )));
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+        let discriminator = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        if discriminator != LOOKUP_TABLE_DISCRIMINATOR {
+            return Err(err(format!(
+                "not initialized (discriminator {})",
+                discriminator
+            )));
+        }
+        let authority = match data[21] {
+            0 => None,
+            1 => Some(Pubkey::try_from(&data[22..54]).map_err(|e| err(e.to_string()))?),
+            tag => return Err(err(format!("bad authority tag {}", tag))),
+        };
+        let addresses = &data[META_SIZE..];
+        if !addresses.len().is_multiple_of(32) {
+            return Err(err(format!(
+                "{} address bytes is not a multiple of 32",
+                addresses.len()
+            )));
+        }
+        Ok(LookupTable {
+            address,
+            deactivation_slot: u64_at(4),
+            last_extended_slot: u64_at(12),
+            last_extended_slot_start_index: data[20],
+            authority,
+            addresses: addresses
+                .chunks_exact(32)
+                .map(|key| Pubkey::try_from(key).unwrap())
+                .collect(),
+        })
+    }
+
+    //=======================================================================
+    /// How many addresses a transaction landing in `slot` may use. Addresses
+    /// appended in a slot only become usable in the next one.
+    pub fn active_len(&self, slot: Slot) -> usize {
+        if slot > self.last_extended_slot {
+            self.addresses.len()
+        } else {
+            (self.last_extended_slot_start_index as usize).min(self.addresses.len())
+        }
+    }
+
+    //=======================================================================
+    /// The addresses `lookup` picks, or `None` if any index is not active
+    /// in `slot`.
+    fn select(&self, lookup: &MessageAddressTableLookup, slot: Slot) -> Option<LoadedAddresses> {
+        let active = &self.addresses[..self.active_len(slot)];
+        let pick = |indexes: &[u8]| -> Option<Vec<Pubkey>> {
+            indexes
+                .iter()
+                .map(|&i| active.get(i as usize).copied())
+                .collect()
+        };
+        Some(LoadedAddresses {
+            writable: pick(&lookup.writable_indexes)?,
+            readonly: pick(&lookup.readonly_indexes)?,
+        })
+    }
+}
+
+//=======================================================================
+struct ResolverMetrics {
+    cache: Counter,
+    rpc: Counter,
+    meta: Counter,
+}
+
+//=======================================================================
+impl ResolverMetrics {
+    fn new() -> Self {
+        let registry = metrics::global();
+        let counter = |source: &str| {
+            registry.counter(
+                "atlas_alt_lookups_total",
+                "Address table lookups resolved, by where the table came from",
+                &[("source", source)],
+            )
+        };
+        ResolverMetrics {
+            cache: counter("cache"),
+            rpc: counter("rpc"),
+            meta: counter("meta"),
+        }
+    }
+}
+
+//=======================================================================
+/// Resolves v0 address table lookups into account keys. Tables are cached
+/// by address along with the slot they were last extended in; a cached
+/// table that cannot serve a lookup is fetched again, since tables only
+/// ever grow. Clones share the cache.
+#[derive(Clone)]
+pub struct AltResolver {
+    tables: Arc<Mutex<HashMap<Pubkey, LookupTable>>>,
+    metrics: Arc<ResolverMetrics>,
+}
+
+//=======================================================================
+impl Default for AltResolver {
+    fn default() -> Self {
+        AltResolver {
+            tables: Arc::default(),
+            metrics: Arc::new(ResolverMetrics::new()),
+        }
+    }
+}
+
+//=======================================================================
+impl AltResolver {
+    //=======================================================================
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //=======================================================================
+    /// Cache `table`, unless a copy extended later is already cached.
+    pub fn insert(&self, table: LookupTable) {
+        let mut tables = self.tables.lock().unwrap();
+        match tables.get(&table.address) {
+            Some(cached)
+                if (cached.last_extended_slot, cached.addresses.len())
+                    > (table.last_extended_slot, table.addresses.len()) => {}
+            _ => {
+                tables.insert(table.address, table);
+            }
+        }
+    }
+
+    //=======================================================================
+    pub fn get(&self, address: &Pubkey) -> Option<LookupTable> {
+        self.tables.lock().unwrap().get(address).cloned()
+    }
+
+    //=======================================================================
+    /// Keys loaded by `lookups` for a transaction in `slot`, writable ones
+    /// first in lookup order, as the runtime loads them. Tables come from
+    /// the cache, then RPC; if that fails, `fallback` (the transaction
+    /// meta's `loaded_addresses`) is used when its shape matches.
+    pub async fn resolve<S: TableSource + ?Sized>(
+        &self,
+        connection: &S,
+        lookups: &[MessageAddressTableLookup],
+        slot: Slot,
+        fallback: Option<&LoadedAddresses>,
+    ) -> AtlasResult<LoadedAddresses> {
+        if lookups.is_empty() {
+            return Ok(LoadedAddresses::default());
+        }
+        let fetched = self.fetch_missing(connection, lookups, slot).await;
+        match self.resolve_cached(lookups, slot) {
+            Ok(loaded) => {
+                self.metrics.cache.inc_by((lookups.len() - fetched) as u64);
+                self.metrics.rpc.inc_by(fetched as u64);
+                Ok(loaded)
+            }
+            Err(e) => match fallback {
+                Some(loaded) if matches_shape(lookups, loaded) => {
+                    debug!("Using meta loaded addresses: {}", e);
+                    self.metrics.meta.inc_by(lookups.len() as u64);
+                    Ok(loaded.clone())
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    //=======================================================================
+    /// Fetch every table the cache cannot serve `lookups` from; returns how
+    /// many were fetched. Failures are left for `resolve_cached` to report.
+    async fn fetch_missing<S: TableSource + ?Sized>(
+        &self,
+        connection: &S,
+        lookups: &[MessageAddressTableLookup],
+        slot: Slot,
+    ) -> usize {
+        let missing: Vec<Pubkey> = {
+            let tables = self.tables.lock().unwrap();
+            lookups
+                .iter()
+                .filter(|lookup| {
+                    tables
+                        .get(&lookup.account_key)
+                        .and_then(|table| table.select(lookup, slot))
+                        .is_none()
+                })
+                .map(|lookup| lookup.account_key)
+                .collect()
+        };
+        let mut missing = missing;
+        missing.sort();
+        missing.dedup();
+        if missing.is_empty() {
+            return 0;
+        }
+        let accounts = match connection.fetch_accounts(&missing).await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                let endpoint = connection.name();
+                warn!(endpoint = endpoint.as_str(); "Fetching lookup tables failed: {}", e);
+                return 0;
+            }
+        };
+        let mut fetched = 0;
+        for (address, account) in missing.iter().zip(accounts) {
+            let Some(account) = account else {
+                warn!("Lookup table {} not found", address);
+                continue;
+            };
+            if account.owner.to_string() != ADDRESS_LOOKUP_PROGRAM_ID {
+                warn!("Account {} is not a lookup table", address);
+                continue;
+            }
+            match LookupTable::decode(*address, &account.data) {
+                Ok(table) => {
+                    self.insert(table);
+                    fetched += 1;
+                }
+                Err(e) => warn!("{}", e),
+            }
+        }
+        fetched
+    }
+
+    //=======================================================================
+    fn resolve_cached(
+        &self,
+        lookups: &[MessageAddressTableLookup],
+        slot: Slot,
+    ) -> AtlasResult<LoadedAddresses> {
+        let tables = self.tables.lock().unwrap();
+        let mut loaded = LoadedAddresses::default();
+        let mut readonly = Vec::new();
+        for lookup in lookups {
+            let table = tables.get(&lookup.account_key).ok_or_else(|| {
+                AtlasError::decode(
+                    "address lookup table",
+                    format!("table {} is unavailable", lookup.account_key),
+                )
+            })?;
+            let selected = table.select(lookup, slot).ok_or_else(|| {
+                AtlasError::decode(
+                    "address lookup table",
+                    format!(
+                        "table {} has {} addresses active at slot {}, lookup needs more",
+                        lookup.account_key,
+                        table.active_len(slot),
+                        slot
+                    ),
+                )
+            })?;
+            loaded.writable.extend(selected.writable);
+            readonly.extend(selected.readonly);
+        }
+        loaded.readonly = readonly;
+        Ok(loaded)
+    }
+}
+
+//=======================================================================
+fn matches_shape(lookups: &[MessageAddressTableLookup], loaded: &LoadedAddresses) -> bool {
+    let writable: usize = lookups.iter().map(|l| l.writable_indexes.len()).sum();
+    let readonly: usize = lookups.iter().map(|l| l.readonly_indexes.len()).sum();
+    loaded.writable.len() == writable && loaded.readonly.len() == readonly
+}
+
+//=======================================================================
+/// `loaded_addresses` from RPC transaction meta, which lists base58 keys.
+pub fn parse_loaded_addresses(
+    writable: &[String],
+    readonly: &[String],
+) -> AtlasResult<LoadedAddresses> {
+    let parse = |keys: &[String]| {
+        keys.iter()
+            .map(|key| {
+                key.parse::<Pubkey>()
+                    .map_err(|e| AtlasError::decode("loaded address", format!("{}: {}", key, e)))
+            })
+            .collect::<AtlasResult<Vec<_>>>()
+    };
+    Ok(LoadedAddresses {
+        writable: parse(writable)?,
+        readonly: parse(readonly)?,
+    })
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves accounts from memory and counts fetches. `fail` makes every
+    /// fetch fail, like an unreachable node.
+    #[derive(Default)]
+    struct StubSource {
+        accounts: HashMap<Pubkey, Account>,
+        fail: bool,
+        fetches: AtomicUsize,
+    }
+
+    impl TableSource for StubSource {
+        fn name(&self) -> String {
+            "stub".to_string()
+        }
+
+        fn fetch_accounts<'a>(&'a self, keys: &'a [Pubkey]) -> AccountsFuture<'a> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            let result = if self.fail {
+                Err(AtlasError::transport("stub", "connection refused"))
+            } else {
+                Ok(keys.iter().map(|k| self.accounts.get(k).cloned()).collect())
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    fn account(owner: &str, data: Vec<u8>) -> Account {
+        Account {
+            lamports: 1,
+            data,
+            owner: owner.parse().unwrap(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn encode(table: &LookupTable) -> Vec<u8> {
+        let mut data = vec![0; META_SIZE];
+        data[0..4].copy_from_slice(&LOOKUP_TABLE_DISCRIMINATOR.to_le_bytes());
+        data[4..12].copy_from_slice(&table.deactivation_slot.to_le_bytes());
+        data[12..20].copy_from_slice(&table.last_extended_slot.to_le_bytes());
+        data[20] = table.last_extended_slot_start_index;
+        if let Some(authority) = table.authority {
+            data[21] = 1;
+            data[22..54].copy_from_slice(authority.as_ref());
+        }
+        table
+            .addresses
+            .iter()
+            .for_each(|key| data.extend_from_slice(key.as_ref()));
+        data
+    }
+
+    //=======================================================================
+    #[tokio::test]
+    async fn test_resolve_lookups() {
+        let table = LookupTable {
+            address: Pubkey::new_unique(),
+            deactivation_slot: u64::MAX,
+            last_extended_slot: 100,
+            last_extended_slot_start_index: 2,
+            authority: Some(Pubkey::new_unique()),
+            addresses: (0..4).map(|_| Pubkey::new_unique()).collect(),
+        };
+        let decoded = LookupTable::decode(table.address, &encode(&table)).unwrap();
+        assert_eq!(decoded, table);
+        assert!(LookupTable::decode(table.address, &encode(&table)[..70]).is_err());
+        assert_eq!(table.active_len(100), 2);
+        assert_eq!(table.active_len(101), 4);
+
+        let resolver = AltResolver::new();
+        resolver.insert(table.clone());
+        let lookup = MessageAddressTableLookup {
+            account_key: table.address,
+            writable_indexes: vec![3],
+            readonly_indexes: vec![0, 1],
+        };
+        let connection = StubSource {
+            fail: true,
+            ..StubSource::default()
+        };
+        let loaded = resolver
+            .resolve(&connection, std::slice::from_ref(&lookup), 101, None)
+            .await
+            .unwrap();
+        assert_eq!(connection.fetches.load(Ordering::Relaxed), 0);
+        assert_eq!(loaded.writable, vec![table.addresses[3]]);
+        assert_eq!(
+            loaded.readonly,
+            vec![table.addresses[0], table.addresses[1]]
+        );
+
+        // Index 3 was added in slot 100 and is not usable in it; refetching
+        // does not help when the node is down.
+        assert!(resolver
+            .resolve(&connection, std::slice::from_ref(&lookup), 100, None)
+            .await
+            .is_err());
+        assert_eq!(connection.fetches.load(Ordering::Relaxed), 1);
+        let meta = parse_loaded_addresses(
+            &[table.addresses[3].to_string()],
+            &[
+                table.addresses[0].to_string(),
+                table.addresses[1].to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            resolver
+                .resolve(&connection, std::slice::from_ref(&lookup), 100, Some(&meta))
+                .await
+                .unwrap(),
+            loaded
+        );
+        let wrong_shape = LoadedAddresses {
+            writable: vec![],
+            readonly: meta.readonly.clone(),
+        };
+        assert!(resolver
+            .resolve(&connection, &[lookup], 100, Some(&wrong_shape))
+            .await
+            .is_err());
+    }
+
+    //=======================================================================
+    #[tokio::test]
+    async fn test_fetch_uncached_tables() {
+        let table = LookupTable {
+            address: Pubkey::new_unique(),
+            deactivation_slot: u64::MAX,
+            last_extended_slot: 50,
+            last_extended_slot_start_index: 0,
+            authority: None,
+            addresses: (0..3).map(|_| Pubkey::new_unique()).collect(),
+        };
+        let impostor = Pubkey::new_unique();
+        let mut connection = StubSource::default();
+        connection.accounts.insert(
+            table.address,
+            account(ADDRESS_LOOKUP_PROGRAM_ID, encode(&table)),
+        );
+        connection.accounts.insert(
+            impostor,
+            account(crate::transaction::SYS_PROGRAM_ID, encode(&table)),
+        );
+        let lookup = |account_key| MessageAddressTableLookup {
+            account_key,
+            writable_indexes: vec![2],
+            readonly_indexes: vec![0],
+        };
+
+        let resolver = AltResolver::new();
+        let loaded = resolver
+            .resolve(&connection, &[lookup(table.address)], 60, None)
+            .await
+            .unwrap();
+        assert_eq!(loaded.writable, vec![table.addresses[2]]);
+        assert_eq!(loaded.readonly, vec![table.addresses[0]]);
+        assert_eq!(resolver.get(&table.address), Some(table.clone()));
+
+        // Cached now; the source is not asked again.
+        resolver
+            .resolve(&connection, &[lookup(table.address)], 60, None)
+            .await
+            .unwrap();
+        assert_eq!(connection.fetches.load(Ordering::Relaxed), 1);
+
+        // Accounts not owned by the lookup table program are not tables.
+        for missing in [impostor, Pubkey::new_unique()] {
+            assert!(resolver
+                .resolve(&connection, &[lookup(missing)], 60, None)
+                .await
+                .is_err());
+            assert!(resolver.get(&missing).is_none());
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use crate::programs::precompile::Precompile;
+use crate::transaction::{decode_confirmed_transaction, DecodedMessage, NATIVE_MINT_ID};
+use atlas_core::error::{AtlasError, AtlasResult};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_transaction_status_client_types::{
+    EncodedTransactionWithStatusMeta, UiTransactionStatusMeta, UiTransactionTokenBalance,
+};
+use std::collections::HashMap;
+
+/// Base fee charged per signature, precompile signatures included.
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+const WHAT: &str = "lamport balance";
+
+//=======================================================================
+/// The transaction fee split into its base and priority parts. The whole
+/// fee is charged to the payer, whether the transaction succeeds or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    pub payer: Pubkey,
+    pub signatures: u64,
+    pub base: u64,
+    pub priority: u64,
+}
+
+//=======================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LamportDelta {
+    pub account: Pubkey,
+    pub pre: u64,
+    pub post: u64,
+}
+
+//=======================================================================
+/// Rent deposited when an account was created (positive), or refunded
+/// when it was closed (negative). For a token account this is whatever
+/// moved beyond its wrapped SOL and `owner` is the token owner; any other
+/// account counts towards the fee payer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RentChange {
+    pub account: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: i128,
+}
+
+//=======================================================================
+/// SOL held by one owner across its own account and its token accounts,
+/// wrapped SOL included. `fee` is what the owner paid as fee payer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolDelta {
+    pub owner: Pubkey,
+    pub pre: u64,
+    pub post: u64,
+    pub fee: u64,
+}
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolBalances {
+    pub signature: String,
+    pub fee: FeeBreakdown,
+    /// Every account whose lamports changed, in account order.
+    pub accounts: Vec<LamportDelta>,
+    pub rent: Vec<RentChange>,
+    /// Owners whose SOL changed once fee and rent are netted out.
+    pub owners: Vec<SolDelta>,
+}
+
+//=======================================================================
+impl FeeBreakdown {
+    //=======================================================================
+    pub fn total(&self) -> u64 {
+        self.base + self.priority
+    }
+}
+
+//=======================================================================
+impl LamportDelta {
+    //=======================================================================
+    pub fn diff(&self) -> i128 {
+        self.post as i128 - self.pre as i128
+    }
+}
+
+//=======================================================================
+impl SolDelta {
+    //=======================================================================
+    /// Change in the owner's SOL, leaving out the fee it paid.
+    pub fn net(&self) -> i128 {
+        self.post as i128 - self.pre as i128 + self.fee as i128
+    }
+}
+
+//=======================================================================
+impl SolBalances {
+    //=======================================================================
+    /// Lamport deltas of a transaction from a block or `getTransaction`.
+    /// Failed transactions are included: they still pay their fee.
+    pub fn from_encoded(tx: &EncodedTransactionWithStatusMeta) -> AtlasResult<Self> {
+        let (signature, message, meta) = decode_confirmed_transaction(tx)?;
+        Self::from_meta(signature.clone(), &message, meta).map_err(|e| e.in_tx(signature))
+    }
+
+    //=======================================================================
+    /// Token accounts count towards their owner, so rent moving into or out
+    /// of them nets out against the owner's own account, and wrapped SOL
+    /// is counted as SOL. Other accounts created or closed in the
+    /// transaction with exactly a rent-exempt balance count towards the
+    /// fee payer, who funds nearly all account creation, so their rent
+    /// nets out the same way. When someone else pays for an account's
+    /// rent, the payer shows the outflow and the owner the inflow.
+    pub fn from_meta(
+        signature: String,
+        message: &DecodedMessage,
+        meta: &UiTransactionStatusMeta,
+    ) -> AtlasResult<Self> {
+        let keys = &message.account_keys;
+        if meta.pre_balances.len() != keys.len() || meta.post_balances.len() != keys.len() {
+            return Err(AtlasError::decode(
+                WHAT,
+                format!(
+                    "{} keys but {} pre and {} post balances",
+                    keys.len(),
+                    meta.pre_balances.len(),
+                    meta.post_balances.len()
+                ),
+            ));
+        }
+        let payer = keys
+            .first()
+            .map(|meta| meta.pubkey)
+            .ok_or_else(|| AtlasError::decode(WHAT, "message has no fee payer"))?;
+        let fee = fee_breakdown(payer, message, meta.fee);
+        let tokens = token_accounts(meta)?;
+
+        let mut accounts = Vec::new();
+        let mut rent = Vec::new();
+        let mut owners: Vec<SolDelta> = Vec::new();
+        for (index, key) in keys.iter().enumerate() {
+            let delta = LamportDelta {
+                account: key.pubkey,
+                pre: meta.pre_balances[index],
+                post: meta.post_balances[index],
+            };
+            let owner = match tokens.get(&(index as u8)) {
+                Some(token) => {
+                    let lamports = delta.diff() - token.wrapped;
+                    if lamports != 0 {
+                        rent.push(RentChange {
+                            account: key.pubkey,
+                            owner: token.owner,
+                            lamports,
+                        });
+                    }
+                    token.owner
+                }
+                None if key.pubkey != payer && is_rent(&delta) => {
+                    rent.push(RentChange {
+                        account: key.pubkey,
+                        owner: payer,
+                        lamports: delta.diff(),
+                    });
+                    payer
+                }
+                None => key.pubkey,
+            };
+            let entry = match owners.iter().position(|o| o.owner == owner) {
+                Some(i) => &mut owners[i],
+                None => {
+                    owners.push(SolDelta {
+                        owner,
+                        pre: 0,
+                        post: 0,
+                        fee: 0,
+                    });
+                    owners.last_mut().unwrap()
+                }
+            };
+            entry.pre += delta.pre;
+            entry.post += delta.post;
+            if delta.pre != delta.post {
+                accounts.push(delta);
+            }
+        }
+        if let Some(entry) = owners.iter_mut().find(|o| o.owner == payer) {
+            entry.fee = meta.fee;
+        }
+        owners.retain(|o| o.net() != 0);
+        Ok(SolBalances {
+            signature,
+            fee,
+            accounts,
+            rent,
+            owners,
+        })
+    }
+
+    //=======================================================================
+    /// Net SOL change of `owner`; zero when it is not involved.
+    pub fn owner(&self, owner: &Pubkey) -> i128 {
+        self.owners
+            .iter()
+            .find(|o| o.owner == *owner)
+            .map_or(0, SolDelta::net)
+    }
+}
+
+//=======================================================================
+/// Base fee from the signatures the message carries and verifies through
+/// precompiles; whatever else was charged is the priority fee.
+pub(crate) fn fee_breakdown(payer: Pubkey, message: &DecodedMessage, fee: u64) -> FeeBreakdown {
+    let signers = message.account_keys.iter().filter(|k| k.is_signer).count() as u64;
+    let verified: u64 = message
+        .instructions
+        .iter()
+        .filter(|ix| Precompile::from_program_id(&ix.program_id.to_string()).is_some())
+        .map(|ix| ix.data.first().copied().unwrap_or(0) as u64)
+        .sum();
+    let signatures = signers + verified;
+    let base = (signatures * LAMPORTS_PER_SIGNATURE).min(fee);
+    FeeBreakdown {
+        payer,
+        signatures,
+        base,
+        priority: fee - base,
+    }
+}
+
+//=======================================================================
+/// Whether the account went from nothing to exactly a rent-exempt
+/// balance, or back. Those are multiples of 6,960 lamports from 890,880
+/// up, so a transfer that creates a wallet is only mistaken for rent when
+/// it happens to land on one.
+fn is_rent(delta: &LamportDelta) -> bool {
+    let rent = Rent::default();
+    let base = rent.minimum_balance(0);
+    let per_byte = rent.minimum_balance(1) - base;
+    let exempt = |lamports: u64| lamports >= base && (lamports - base).is_multiple_of(per_byte);
+    match (delta.pre, delta.post) {
+        (0, post) => exempt(post),
+        (pre, 0) => exempt(pre),
+        _ => false,
+    }
+}
+
+//=======================================================================
+struct TokenAccount {
+    owner: Pubkey,
+    /// Change in wrapped SOL, zero for other mints.
+    wrapped: i128,
+}
+
+//=======================================================================
+/// Token accounts by account index, from the meta's token balances.
+fn token_accounts(meta: &UiTransactionStatusMeta) -> AtlasResult<HashMap<u8, TokenAccount>> {
+    let balances = |list: &Option<&Vec<UiTransactionTokenBalance>>, sign: i128| {
+        list.iter()
+            .flat_map(|v| v.iter())
+            .map(|balance| {
+                let owner = Option::<&String>::from(balance.owner.as_ref())
+                    .ok_or_else(|| {
+                        AtlasError::decode(
+                            WHAT,
+                            format!("token account #{} has no owner", balance.account_index),
+                        )
+                    })?
+                    .parse::<Pubkey>()
+                    .map_err(|e| AtlasError::decode(WHAT, e))?;
+                let wrapped = if balance.mint == NATIVE_MINT_ID {
+                    let amount: u64 = balance
+                        .ui_token_amount
+                        .amount
+                        .parse()
+                        .map_err(|e| AtlasError::decode(WHAT, e))?;
+                    sign * amount as i128
+                } else {
+                    0
+                };
+                Ok((balance.account_index, owner, wrapped))
+            })
+            .collect::<AtlasResult<Vec<_>>>()
+    };
+    let pre = Option::<&Vec<_>>::from(meta.pre_token_balances.as_ref());
+    let post = Option::<&Vec<_>>::from(meta.post_token_balances.as_ref());
+    let mut accounts: HashMap<u8, TokenAccount> = HashMap::new();
+    for (index, owner, wrapped) in balances(&pre, -1)?.into_iter().chain(balances(&post, 1)?) {
+        accounts
+            .entry(index)
+            .and_modify(|account| account.wrapped += wrapped)
+            .or_insert(TokenAccount { owner, wrapped });
+    }
+    Ok(accounts)
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //=======================================================================
+    fn balances(fixture: &str) -> SolBalances {
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_str(fixture).unwrap();
+        SolBalances::from_encoded(&tx).unwrap()
+    }
+
+    //=======================================================================
+    fn key(s: &str) -> Pubkey {
+        s.parse().unwrap()
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_native_buy_with_account_creation() {
+        let sol = balances(include_str!("../test/fixtures/native_buy.json"));
+        let user = key("7xMg4Hx2yPYjUqNHRE1QhD5rE9Eh6CWnp8rBYtRfdaPf");
+        let curve = key("HaBq2gUmrmEhEA4HPAwxdQSQ4cK8DDugaXs5qb1mnTuA");
+        let fee_recipient = key("CebN5WGQ4jvEPvsVU4EoHEpgzq1VV7AbicfhtW4xC9iM");
+        assert_eq!(
+            sol.fee,
+            FeeBreakdown {
+                payer: user,
+                signatures: 1,
+                base: 5_000,
+                priority: 20_000,
+            }
+        );
+
+        // The user's new token account holds rent, which nets out against
+        // what the user paid for it.
+        assert_eq!(
+            sol.rent,
+            vec![RentChange {
+                account: key("2cXaXsrM7Bpyw3mYgWBwqHKaR6EaRLfJbxM8s5JJkDhN"),
+                owner: user,
+                lamports: 2_039_280,
+            }]
+        );
+        assert_eq!(sol.owner(&user), -1_010_000_000);
+        assert_eq!(sol.owner(&curve), 1_000_000_000);
+        assert_eq!(sol.owner(&fee_recipient), 10_000_000);
+        assert_eq!(sol.owners.iter().map(SolDelta::net).sum::<i128>(), 0);
+        assert_eq!(sol.accounts.len(), 4);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_unwrap_is_not_a_movement() {
+        let sol = balances(include_str!("../test/fixtures/wsol_unwrap.json"));
+        let user = key("7xMg4Hx2yPYjUqNHRE1QhD5rE9Eh6CWnp8rBYtRfdaPf");
+        assert_eq!(sol.fee.total(), 5_000);
+        assert!(sol.owners.is_empty());
+        assert_eq!(sol.owner(&user), 0);
+        assert_eq!(sol.rent[0].lamports, -2_039_280);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_rent_of_other_accounts() {
+        let payer = Pubkey::new_unique();
+        let pda = Pubkey::new_unique();
+        let closed = Pubkey::new_unique();
+        let wallet = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        // The payer funds a new 82 byte PDA, gets a 32 byte account's rent
+        // back and sends 1 SOL to a wallet that did not exist before.
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(serde_json::json!({
+            "transaction": {
+                "signatures": [
+                    "2iNXgtNRYoeNkjga4pTieugcAKaqBzzusiad4TQS6uXLQYukRHoSwgYcUs3E5r5FFooTyBfb6rTCd7b25kAVEK5p"
+                ],
+                "message": {
+                    "header": {
+                        "numRequiredSignatures": 1,
+                        "numReadonlySignedAccounts": 0,
+                        "numReadonlyUnsignedAccounts": 1
+                    },
+                    "accountKeys": [
+                        payer.to_string(),
+                        pda.to_string(),
+                        closed.to_string(),
+                        wallet.to_string(),
+                        program.to_string()
+                    ],
+                    "recentBlockhash": "2pKndU4shsRd9eWeGJa4hhJ1HWhkGXuCPc5oSJ3HfCRT",
+                    "instructions": []
+                }
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5_000,
+                "preBalances": [2_000_000_000u64, 0, 1_113_600, 0, 1_141_440],
+                "postBalances": [999_647_000u64, 1_461_600, 0, 1_000_000_000, 1_141_440],
+                "innerInstructions": [],
+                "logMessages": [],
+                "preTokenBalances": [],
+                "postTokenBalances": [],
+                "rewards": []
+            },
+            "version": "legacy"
+        }))
+        .unwrap();
+        let sol = SolBalances::from_encoded(&tx).unwrap();
+
+        assert_eq!(
+            sol.rent,
+            vec![
+                RentChange {
+                    account: pda,
+                    owner: payer,
+                    lamports: 1_461_600,
+                },
+                RentChange {
+                    account: closed,
+                    owner: payer,
+                    lamports: -1_113_600,
+                },
+            ]
+        );
+        assert_eq!(sol.owner(&payer), -1_000_000_000);
+        assert_eq!(sol.owner(&wallet), 1_000_000_000);
+        assert_eq!(sol.owner(&pda), 0);
+        assert_eq!(sol.owners.len(), 2);
+        assert_eq!(sol.accounts.len(), 4);
+    }
+}
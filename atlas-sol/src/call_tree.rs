@@ -0,0 +1,483 @@
+use crate::transaction::{decode_confirmed_transaction, DecodedInstruction, DecodedMessage};
+use atlas_core::error::{AtlasError, AtlasResult};
+use base64::Engine;
+use solana_sdk::bs58;
+use solana_sdk::instruction::AccountMeta;
+use solana_transaction_status_client_types::{
+    EncodedTransactionWithStatusMeta, UiInnerInstructions, UiInstruction, UiParsedInstruction,
+    UiTransactionStatusMeta,
+};
+
+const WHAT: &str = "inner instruction";
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallStatus {
+    Succeeded,
+    Failed(String),
+    /// The logs do not say: they were truncated, or the call never ran
+    /// because an earlier one failed.
+    Unknown,
+}
+
+//=======================================================================
+/// One program invocation and the invocations it made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallNode {
+    pub instruction: DecodedInstruction,
+    /// 1 for top-level instructions, one more for each level of CPI.
+    pub stack_height: u32,
+    /// Only programs that log it; builtins don't.
+    pub units_consumed: Option<u64>,
+    pub return_data: Option<Vec<u8>>,
+    pub status: CallStatus,
+    pub children: Vec<CallNode>,
+}
+
+//=======================================================================
+/// Every invocation a transaction made, rooted at its top-level
+/// instructions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallTree {
+    pub signature: String,
+    pub roots: Vec<CallNode>,
+}
+
+//=======================================================================
+/// Depth-first walk over call nodes, parents before their children.
+pub struct Calls<'a> {
+    stack: Vec<&'a CallNode>,
+}
+
+//=======================================================================
+impl<'a> Iterator for Calls<'a> {
+    type Item = &'a CallNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children.iter().rev());
+        Some(node)
+    }
+}
+
+//=======================================================================
+impl CallNode {
+    //=======================================================================
+    /// This node and everything it invoked, in execution order.
+    pub fn iter(&self) -> Calls<'_> {
+        Calls { stack: vec![self] }
+    }
+}
+
+//=======================================================================
+impl CallTree {
+    //=======================================================================
+    pub fn from_encoded(tx: &EncodedTransactionWithStatusMeta) -> AtlasResult<Self> {
+        let (signature, message, meta) = decode_confirmed_transaction(tx)?;
+        Self::build(signature.clone(), &message, meta).map_err(|e| e.in_tx(signature))
+    }
+
+    //=======================================================================
+    /// Nest `meta.inner_instructions` under the top-level instructions by
+    /// stack height, and attach what the logs say about each invocation.
+    /// Nodes that predate stack heights take theirs from the log's invoke
+    /// depth, or are assumed to be direct CPIs when the logs are missing.
+    pub fn build(
+        signature: String,
+        message: &DecodedMessage,
+        meta: &UiTransactionStatusMeta,
+    ) -> AtlasResult<Self> {
+        let inner = Option::<&Vec<UiInnerInstructions>>::from(meta.inner_instructions.as_ref());
+        let mut calls = Vec::new();
+        for (index, instruction) in message.instructions.iter().enumerate() {
+            calls.push((Some(1), instruction.clone()));
+            let invoked = inner
+                .iter()
+                .flat_map(|sets| sets.iter())
+                .filter(|set| set.index as usize == index)
+                .flat_map(|set| set.instructions.iter());
+            for ui in invoked {
+                calls.push(inner_instruction(message, ui)?);
+            }
+        }
+
+        let logs = Option::<&Vec<String>>::from(meta.log_messages.as_ref());
+        let mut frames = parse_logs(logs.map_or(&[][..], |l| l.as_slice()))?.into_iter();
+        let mut next_frame = frames.next();
+
+        let mut roots = Vec::new();
+        let mut open: Vec<CallNode> = Vec::new();
+        for (height, instruction) in calls {
+            // Frames deeper than this call belong to invocations the inner
+            // instructions left out; skip them to get back in step.
+            if let Some(height) = height {
+                while next_frame
+                    .as_ref()
+                    .is_some_and(|frame| frame.depth > height)
+                {
+                    next_frame = frames.next();
+                }
+            }
+            let matches = next_frame.as_ref().is_some_and(|frame| {
+                frame.program_id == instruction.program_id.to_string()
+                    && height.is_none_or(|h| h == frame.depth)
+            });
+            let frame = if matches {
+                std::mem::replace(&mut next_frame, frames.next())
+            } else {
+                None
+            };
+            let height = height
+                .or(frame.as_ref().map(|f| f.depth))
+                .unwrap_or(2)
+                .clamp(1, open.len() as u32 + 1);
+            while open.len() as u32 >= height {
+                close(&mut open, &mut roots);
+            }
+            let (units_consumed, return_data, status) = match frame {
+                Some(frame) => (frame.units_consumed, frame.return_data, frame.status),
+                None => (None, None, CallStatus::Unknown),
+            };
+            open.push(CallNode {
+                instruction,
+                stack_height: height,
+                units_consumed,
+                return_data,
+                status,
+                children: Vec::new(),
+            });
+        }
+        while !open.is_empty() {
+            close(&mut open, &mut roots);
+        }
+        Ok(CallTree { signature, roots })
+    }
+
+    //=======================================================================
+    /// Every node, in execution order.
+    pub fn iter(&self) -> Calls<'_> {
+        Calls {
+            stack: self.roots.iter().rev().collect(),
+        }
+    }
+}
+
+//=======================================================================
+fn close(open: &mut Vec<CallNode>, roots: &mut Vec<CallNode>) {
+    if let Some(node) = open.pop() {
+        match open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+}
+
+//=======================================================================
+/// An inner instruction with its stack height, when the node reported one.
+/// Accounts take their flags from the message.
+fn inner_instruction(
+    message: &DecodedMessage,
+    ui: &UiInstruction,
+) -> AtlasResult<(Option<u32>, DecodedInstruction)> {
+    let by_index = |index: u8| -> AtlasResult<AccountMeta> {
+        message
+            .account_keys
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| {
+                AtlasError::decode(WHAT, format!("account index {} out of range", index))
+            })
+    };
+    let by_key = |key: &String| -> AtlasResult<AccountMeta> {
+        message
+            .account_keys
+            .iter()
+            .find(|meta| meta.pubkey.to_string() == *key)
+            .cloned()
+            .ok_or_else(|| AtlasError::decode(WHAT, format!("{} is not in the message", key)))
+    };
+    let data = |data: &str| {
+        bs58::decode(data)
+            .into_vec()
+            .map_err(|e| AtlasError::decode(WHAT, e))
+    };
+    match ui {
+        UiInstruction::Compiled(ix) => Ok((
+            ix.stack_height,
+            DecodedInstruction {
+                program_id: by_index(ix.program_id_index)?.pubkey,
+                data: data(&ix.data)?,
+                keys: ix
+                    .accounts
+                    .iter()
+                    .map(|&i| by_index(i))
+                    .collect::<AtlasResult<_>>()?,
+            },
+        )),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => Ok((
+            ix.stack_height,
+            DecodedInstruction {
+                program_id: by_key(&ix.program_id)?.pubkey,
+                data: data(&ix.data)?,
+                keys: ix.accounts.iter().map(by_key).collect::<AtlasResult<_>>()?,
+            },
+        )),
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) => Err(AtlasError::decode(
+            WHAT,
+            format!(
+                "{} instruction came back parsed, request json or base64",
+                ix.program
+            ),
+        )),
+    }
+}
+
+//=======================================================================
+/// What the logs say about one invocation.
+#[derive(Debug)]
+struct Frame {
+    program_id: String,
+    depth: u32,
+    units_consumed: Option<u64>,
+    return_data: Option<Vec<u8>>,
+    status: CallStatus,
+}
+
+//=======================================================================
+/// Invocations in the order they started, from the runtime's
+/// `Program <id> invoke [n]`, `consumed`, `return`, `success` and `failed`
+/// lines. Stops at `Log truncated`. Lines about any program but the
+/// innermost running one are ignored, and so is what programs log
+/// themselves, which can look like anything.
+fn parse_logs(logs: &[String]) -> AtlasResult<Vec<Frame>> {
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    for line in logs {
+        if line == "Log truncated" {
+            break;
+        }
+        let Some(rest) = line.strip_prefix("Program ") else {
+            continue;
+        };
+        if rest.starts_with("log: ") || rest.starts_with("data: ") {
+            continue;
+        }
+        if let Some(ret) = rest.strip_prefix("return: ") {
+            let (program_id, encoded) = ret.split_once(' ').unwrap_or((ret, ""));
+            if let Some(top) = running(&frames, &open, program_id) {
+                // A garbled line costs this call its return data only.
+                frames[top].return_data = base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .ok();
+            }
+            continue;
+        }
+        let Some((program_id, event)) = rest.split_once(' ') else {
+            continue;
+        };
+        let top = running(&frames, &open, program_id);
+        if let Some(depth) = event
+            .strip_prefix("invoke [")
+            .and_then(|d| d.strip_suffix(']'))
+        {
+            open.push(frames.len());
+            frames.push(Frame {
+                program_id: program_id.to_string(),
+                depth: depth
+                    .parse()
+                    .map_err(|e| AtlasError::decode("program log", e))?,
+                units_consumed: None,
+                return_data: None,
+                status: CallStatus::Unknown,
+            });
+        } else if let Some(units) = event.strip_prefix("consumed ") {
+            if let (Some(top), Some(units)) = (top, units.split(' ').next()) {
+                frames[top].units_consumed = units.parse().ok();
+            }
+        } else if event == "success" {
+            if let Some(top) = top {
+                open.pop();
+                frames[top].status = CallStatus::Succeeded;
+            }
+        } else if let Some(error) = event.strip_prefix("failed: ") {
+            if let Some(top) = top {
+                open.pop();
+                frames[top].status = CallStatus::Failed(error.to_string());
+            }
+        }
+    }
+    Ok(frames)
+}
+
+//=======================================================================
+/// The innermost open frame, if `program_id` is what it runs.
+fn running(frames: &[Frame], open: &[usize], program_id: &str) -> Option<usize> {
+    open.last()
+        .copied()
+        .filter(|&top| frames[top].program_id == program_id)
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{COMPUTE_BUDGET_PROGRAM_ID, TOKEN_PROGRAM_ID};
+    use solana_sdk::pubkey::Pubkey;
+
+    //=======================================================================
+    fn fixture() -> serde_json::Value {
+        serde_json::from_str(include_str!("../test/fixtures/routed_swap.json")).unwrap()
+    }
+
+    //=======================================================================
+    fn call_tree(value: serde_json::Value) -> CallTree {
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(value).unwrap();
+        CallTree::from_encoded(&tx).unwrap()
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_routed_swap() {
+        let router: Pubkey = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4"
+            .parse()
+            .unwrap();
+        let amm: Pubkey = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc"
+            .parse()
+            .unwrap();
+        let token: Pubkey = TOKEN_PROGRAM_ID.parse().unwrap();
+
+        let tree = call_tree(fixture());
+        assert_eq!(tree.roots.len(), 2);
+        assert_eq!(
+            tree.roots[0].instruction.program_id.to_string(),
+            COMPUTE_BUDGET_PROGRAM_ID
+        );
+        assert_eq!(tree.roots[0].status, CallStatus::Succeeded);
+
+        let route = &tree.roots[1];
+        assert_eq!(route.instruction.program_id, router);
+        assert_eq!(route.units_consumed, Some(61_000));
+        assert_eq!(route.return_data, Some(1_234_567u64.to_le_bytes().to_vec()));
+        assert_eq!(route.children.len(), 1);
+        let swap = &route.children[0];
+        assert_eq!((swap.instruction.program_id, swap.stack_height), (amm, 2));
+        assert_eq!(swap.units_consumed, Some(41_000));
+
+        let transfers = &swap.children;
+        assert_eq!(transfers.len(), 2);
+        assert!(transfers
+            .iter()
+            .all(|t| t.instruction.program_id == token && t.stack_height == 3));
+        assert_eq!(transfers[1].units_consumed, Some(4_736));
+        // Inner instructions decode like top-level ones.
+        assert!(transfers[0].instruction.token().unwrap().is_some());
+
+        let order: Vec<_> = tree.iter().map(|n| n.stack_height).collect();
+        assert_eq!(order, vec![1, 1, 2, 3, 3]);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_heights_from_logs_and_failures() {
+        // Older nodes leave out stack heights; the logs' invoke depths stand
+        // in for them.
+        let mut value = fixture();
+        for ix in value["meta"]["innerInstructions"][0]["instructions"]
+            .as_array_mut()
+            .unwrap()
+        {
+            ix["stackHeight"] = serde_json::Value::Null;
+        }
+        assert_eq!(call_tree(value.clone()), call_tree(fixture()));
+
+        // The second transfer fails; everything above it fails with it.
+        let logs = value["meta"]["logMessages"].as_array_mut().unwrap();
+        let at = logs
+            .iter()
+            .position(|l| l.as_str().unwrap().contains("consumed 4736"))
+            .unwrap();
+        logs.truncate(at);
+        for line in [
+            format!("Program {} failed: insufficient funds", TOKEN_PROGRAM_ID),
+            "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc failed: insufficient funds"
+                .to_string(),
+            "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 failed: insufficient funds"
+                .to_string(),
+        ] {
+            logs.push(line.into());
+        }
+        let tree = call_tree(value);
+        let statuses: Vec<_> = tree.iter().map(|n| n.status.clone()).collect();
+        let failed = CallStatus::Failed("insufficient funds".to_string());
+        assert_eq!(
+            statuses,
+            vec![
+                CallStatus::Succeeded,
+                failed.clone(),
+                failed.clone(),
+                CallStatus::Succeeded,
+                failed
+            ]
+        );
+        assert_eq!(tree.roots[1].return_data, None);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_program_output_and_bad_return_data() {
+        // Programs log whatever they like; none of it is a runtime event.
+        let mut value = fixture();
+        let logs = value["meta"]["logMessages"].as_array_mut().unwrap();
+        let at = logs
+            .iter()
+            .position(|l| l.as_str().unwrap() == "Program log: Instruction: Swap")
+            .unwrap();
+        for line in [
+            "Program log: success",
+            "Program log: failed: not really",
+            "Program data: consumed 1 of 2 compute units",
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success",
+        ] {
+            logs.insert(at + 1, line.into());
+        }
+        assert_eq!(call_tree(value), call_tree(fixture()));
+
+        // A bad return line loses that return data, not the tree.
+        let mut value = fixture();
+        for line in value["meta"]["logMessages"].as_array_mut().unwrap() {
+            if line.as_str().unwrap().starts_with("Program return: ") {
+                *line = "Program return: JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 %%%".into();
+            }
+        }
+        let mut expected = call_tree(fixture());
+        expected.roots[1].return_data = None;
+        assert_eq!(call_tree(value), expected);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_resync_past_missing_inner_instructions() {
+        // The route's CPIs are missing from the inner instructions, but
+        // their frames are in the logs. The instruction after it still
+        // gets its own frame.
+        let mut value = fixture();
+        value["meta"]["innerInstructions"] = serde_json::json!([]);
+        value["transaction"]["message"]["instructions"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!({
+                "programIdIndex": 6,
+                "accounts": [],
+                "data": "3Sy41WEwNLnT",
+            }));
+        let logs = value["meta"]["logMessages"].as_array_mut().unwrap();
+        logs.push(format!("Program {} invoke [1]", COMPUTE_BUDGET_PROGRAM_ID).into());
+        logs.push(format!("Program {} success", COMPUTE_BUDGET_PROGRAM_ID).into());
+
+        let tree = call_tree(value);
+        assert_eq!(tree.roots.len(), 3);
+        assert!(tree.roots[1].children.is_empty());
+        assert_eq!(tree.roots[1].units_consumed, Some(61_000));
+        assert_eq!(tree.roots[2].status, CallStatus::Succeeded);
+    }
+}
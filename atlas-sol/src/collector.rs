@@ -3,65 +3,138 @@ use agave_geyser_plugin_interface::geyser_plugin_interface::{
     ReplicaTransactionInfoVersions, Result as GeyserResult, SlotStatus,
 };
 use atlas_core::{
-    error::{AtlasError, AtlasResult},
+    alert::{Alert, Alerter},
+    bus::{
+        self, AccountUpdate, BlockMetaUpdate, ChainEvent, EventBus, EventSubscriber, SlotUpdate,
+        Topic, TransactionUpdate, TryRecvError,
+    },
+    config::{AtlasConfig, ConfigLoader, GeyserConfig, Severity},
+    health::{self, ComponentHealth, ConnectionState, HealthRegistry},
+    metrics::{self, Counter, Gauge},
+    supervisor::{CancellationToken, Stage, Supervisor},
     util::AtlasUtil,
 };
-use crossbeam::channel::{bounded, Receiver, Sender};
-use log::{error, info};
+use log::{debug, error, info, warn};
 use std::sync::{Arc, Mutex};
 use tokio::time::{sleep, Duration};
 
-static SOLONA_CHANNEL_SIZE: usize = 10_000;
+const COLLECTOR_SUBSCRIBER: &str = "geyser_collector";
 
 //=======================================================================
-#[derive(Debug, Clone)]
-pub struct SolonaGeyserConfig {}
-
-//=======================================================================
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct SolonaCollector {
-    receiver: Receiver<i32>,
+    subscriber: EventSubscriber,
+    metrics: GeyserMetrics,
+    health: ComponentHealth,
+    alerter: Alerter,
 }
 
 //=======================================================================
 #[derive(Debug, Clone)]
 pub struct SolonaGeyser {
-    sender: Sender<i32>,
+    bus: EventBus,
     thread_handle: Option<Arc<Mutex<std::thread::JoinHandle<()>>>>,
-    collector: Arc<Mutex<SolonaCollector>>,
+    collector: Arc<tokio::sync::Mutex<SolonaCollector>>,
+    shutdown: CancellationToken,
+    metrics: GeyserMetrics,
+}
+
+//=======================================================================
+#[derive(Debug, Clone)]
+struct GeyserMetrics {
+    accounts: Counter,
+    slots: Counter,
+    transactions: Counter,
+    block_metas: Counter,
+    channel_occupancy: Gauge,
+    channel_drops: Counter,
+    processed: Counter,
+}
+
+//=======================================================================
+impl GeyserMetrics {
+    fn new() -> Self {
+        let registry = metrics::global();
+        let event = |kind| {
+            registry.counter(
+                "atlas_geyser_events_total",
+                "Geyser notifications received, by kind",
+                &[("kind", kind)],
+            )
+        };
+        GeyserMetrics {
+            accounts: event("account"),
+            slots: event("slot"),
+            transactions: event("transaction"),
+            block_metas: event("block_meta"),
+            channel_occupancy: registry.gauge(
+                "atlas_geyser_channel_occupancy",
+                "Events queued between the geyser plugin and the collector",
+                &[],
+            ),
+            channel_drops: registry.counter(
+                "atlas_geyser_channel_drops_total",
+                "Events dropped because the collector queue was full",
+                &[],
+            ),
+            processed: registry.counter(
+                "atlas_geyser_collector_processed_total",
+                "Events drained by the collector",
+                &[],
+            ),
+        }
+    }
 }
 
 //=======================================================================
 pub struct SolonaManager {
-    config: SolonaGeyserConfig,
+    config: GeyserConfig,
 }
 
 //=======================================================================
 impl SolonaGeyser {
     //=======================================================================
     pub fn new() -> Self {
-        let (sender, receiver) = bounded(SOLONA_CHANNEL_SIZE);
-        let collector = SolonaCollector::new(receiver);
+        let bus = EventBus::new();
+        let capacity = GeyserConfig::default().channel_size;
+        let collector =
+            SolonaCollector::new(bus.subscribe(COLLECTOR_SUBSCRIBER, Topic::ALL, capacity));
         SolonaGeyser {
-            sender,
-            collector: Arc::new(Mutex::new(collector)),
+            bus,
+            collector: Arc::new(tokio::sync::Mutex::new(collector)),
             thread_handle: None,
+            shutdown: CancellationToken::new(),
+            metrics: GeyserMetrics::new(),
         }
     }
 
     //=======================================================================
-    pub async fn run(&self) -> AtlasResult<()> {
-        info!("SolonaGeyser starting...");
-        for i in 0..10 {
-            self.sender.send(i).unwrap();
-            sleep(Duration::from_millis(500)).await;
+    /// Bus the geyser callbacks publish to; subscribe to it to consume the
+    /// same events as the collector.
+    pub fn event_bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    //=======================================================================
+    /// Never blocks the validator; a subscriber that falls behind loses its
+    /// oldest events instead.
+    fn publish(&self, event: ChainEvent) {
+        if self.bus.publish(event) == 0 {
+            debug!("No subscribers for geyser event");
         }
-        self.sender.send(-1).unwrap();
-        info!("SolonaGeyser done.");
-        Ok(())
     }
 }
 
+//=======================================================================
+/// Geyser hands keys over as slices; anything but 32 bytes is a bug upstream.
+fn pubkey_bytes(bytes: &[u8]) -> GeyserResult<[u8; 32]> {
+    bytes
+        .try_into()
+        .map_err(|_| GeyserPluginError::AccountsUpdateError {
+            msg: format!("expected a 32 byte pubkey, got {} bytes", bytes.len()),
+        })
+}
+
 //=======================================================================
 impl GeyserPlugin for SolonaGeyser {
     //=======================================================================
@@ -71,18 +144,79 @@ impl GeyserPlugin for SolonaGeyser {
 
     //=======================================================================
     fn on_load(&mut self, config_file: &str, is_reload: bool) -> GeyserResult<()> {
-        AtlasUtil::setup_logger().unwrap();
-        info!("SolonaGeyser loading...");
+        // The validator hands us its geyser config.json; it is layered with
+        // ATLAS_* overrides like any other atlas config file.
+        let config = ConfigLoader::new()
+            .with_file(config_file)
+            .map(|loader| loader.with_process_env())
+            .and_then(|loader| AtlasConfig::from_loader(&loader));
+        let logging = config
+            .as_ref()
+            .map(|c| c.logging.clone())
+            .unwrap_or_default();
+        AtlasUtil::setup_logger_with(&logging)
+            .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
+        let config =
+            config.map_err(|e| GeyserPluginError::ConfigFileReadError { msg: e.to_string() })?;
+        info!(config_file = config_file, is_reload = is_reload; "SolonaGeyser loading...");
+        let subscriber = self.bus.subscribe(
+            COLLECTOR_SUBSCRIBER,
+            Topic::ALL,
+            config.solana.geyser.channel_size,
+        );
+        let health_config = config.health.clone();
+        let registry = HealthRegistry::new(&health_config);
+        let alerter = Alerter::new(&config.alerting, &config.retry);
+        self.collector = Arc::new(tokio::sync::Mutex::new(
+            SolonaCollector::new(subscriber)
+                .with_health(&registry)
+                .with_alerter(alerter),
+        ));
         let collector = self.collector.clone();
+        let metrics_config = config.metrics.clone();
+        let supervisor = Supervisor::new(&config.supervisor, &config.retry);
+        self.shutdown = supervisor.shutdown_token();
         let handle = std::thread::spawn(move || {
             info!("SolonaCollector thread starting...");
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
                 .build()
                 .unwrap();
-            rt.block_on(async {
-                let collector = collector.lock().unwrap();
-                collector.listen().await;
+            rt.block_on(async move {
+                let mut supervisor = supervisor;
+                if metrics_config.enabled {
+                    supervisor.spawn("metrics", Stage::Sink, move |token| {
+                        let listen = metrics_config.listen.clone();
+                        async move {
+                            tokio::select! {
+                                result = metrics::serve(&listen, metrics::global()) => result,
+                                _ = token.cancelled() => Ok(()),
+                            }
+                        }
+                    });
+                }
+                if health_config.enabled {
+                    supervisor.spawn("health", Stage::Sink, move |token| {
+                        let listen = health_config.listen.clone();
+                        let registry = registry.clone();
+                        async move {
+                            tokio::select! {
+                                result = health::serve(&listen, registry) => result,
+                                _ = token.cancelled() => Ok(()),
+                            }
+                        }
+                    });
+                }
+                supervisor.spawn("collector", Stage::Processor, move |token| {
+                    let collector = collector.clone();
+                    async move {
+                        collector.lock_owned().await.listen(token).await;
+                        Ok(())
+                    }
+                });
+                for report in supervisor.run_until_shutdown().await {
+                    info!(task = report.name.as_str(); "Stopped: {:?}", report.exit);
+                }
             });
         });
         self.thread_handle = Some(Arc::new(Mutex::new(handle)));
@@ -91,11 +225,21 @@ impl GeyserPlugin for SolonaGeyser {
     }
 
     //=======================================================================
+    /// The validator has stopped sending notifications by now, so the
+    /// collector drains what is queued and the thread exits.
     fn on_unload(&mut self) {
         info!("SolonaGeyser on_unload");
-        if let Some(handle) = self.thread_handle.take() {
-            let handle = Arc::try_unwrap(handle).unwrap().into_inner().unwrap();
-            handle.join().unwrap();
+        self.shutdown.cancel();
+        let Some(handle) = self.thread_handle.take() else {
+            return;
+        };
+        match Arc::try_unwrap(handle) {
+            Ok(handle) => {
+                if handle.into_inner().unwrap().join().is_err() {
+                    error!("SolonaCollector thread panicked");
+                }
+            }
+            Err(_) => warn!("SolonaCollector thread still shared, not joining"),
         }
     }
 
@@ -106,10 +250,28 @@ impl GeyserPlugin for SolonaGeyser {
         slot: u64,
         is_startup: bool,
     ) -> GeyserResult<()> {
-        info!(
-            "SolonaGeyser update_account slot: {}, is_startup: {}",
-            slot, is_startup
-        );
+        self.metrics.accounts.inc();
+        debug!(slot = slot, is_startup = is_startup; "SolonaGeyser update_account");
+        let (pubkey, owner, lamports, data, write_version) = match account {
+            ReplicaAccountInfoVersions::V0_0_1(a) => {
+                (a.pubkey, a.owner, a.lamports, a.data, a.write_version)
+            }
+            ReplicaAccountInfoVersions::V0_0_2(a) => {
+                (a.pubkey, a.owner, a.lamports, a.data, a.write_version)
+            }
+            ReplicaAccountInfoVersions::V0_0_3(a) => {
+                (a.pubkey, a.owner, a.lamports, a.data, a.write_version)
+            }
+        };
+        self.publish(ChainEvent::Account(AccountUpdate {
+            slot,
+            pubkey: pubkey_bytes(pubkey)?,
+            owner: pubkey_bytes(owner)?,
+            lamports,
+            data: data.to_vec(),
+            write_version,
+            is_startup,
+        }));
         Ok(())
     }
 
@@ -120,7 +282,20 @@ impl GeyserPlugin for SolonaGeyser {
         parent: Option<u64>,
         status: &SlotStatus,
     ) -> GeyserResult<()> {
-        info!("Updating slot {:?} at with status {:?}", slot, status);
+        self.metrics.slots.inc();
+        debug!(slot = slot, parent:? = parent, status:? = status; "Updating slot status");
+        let status = match status {
+            SlotStatus::Processed => bus::SlotStatus::Processed,
+            SlotStatus::Confirmed => bus::SlotStatus::Confirmed,
+            SlotStatus::Rooted => bus::SlotStatus::Rooted,
+            #[allow(unreachable_patterns)]
+            other => bus::SlotStatus::Other(other.as_str().to_string()),
+        };
+        self.publish(ChainEvent::Slot(SlotUpdate {
+            slot,
+            parent,
+            status,
+        }));
         Ok(())
     }
 
@@ -136,13 +311,70 @@ impl GeyserPlugin for SolonaGeyser {
         transaction_info: ReplicaTransactionInfoVersions,
         slot: u64,
     ) -> GeyserResult<()> {
-        info!("Notifying transaction");
+        self.metrics.transactions.inc();
+        debug!(slot = slot; "Notifying transaction");
+        let (signature, is_vote, index) = match transaction_info {
+            ReplicaTransactionInfoVersions::V0_0_1(t) => (t.signature, t.is_vote, None),
+            ReplicaTransactionInfoVersions::V0_0_2(t) => (t.signature, t.is_vote, Some(t.index)),
+        };
+        let signature: &[u8] = signature.as_ref();
+        let signature =
+            signature
+                .try_into()
+                .map_err(|_| GeyserPluginError::TransactionUpdateError {
+                    msg: format!(
+                        "expected a 64 byte signature, got {} bytes",
+                        signature.len()
+                    ),
+                })?;
+        self.publish(ChainEvent::Transaction(TransactionUpdate {
+            slot,
+            signature,
+            is_vote,
+            index,
+        }));
         Ok(())
     }
 
     //=======================================================================
     fn notify_block_metadata(&self, block_info: ReplicaBlockInfoVersions) -> GeyserResult<()> {
-        info!("Notifying block metadata");
+        self.metrics.block_metas.inc();
+        let update = match block_info {
+            ReplicaBlockInfoVersions::V0_0_1(b) => BlockMetaUpdate {
+                slot: b.slot,
+                parent_slot: None,
+                blockhash: b.blockhash.to_string(),
+                block_time: b.block_time,
+                block_height: b.block_height,
+                executed_transaction_count: None,
+            },
+            ReplicaBlockInfoVersions::V0_0_2(b) => BlockMetaUpdate {
+                slot: b.slot,
+                parent_slot: Some(b.parent_slot),
+                blockhash: b.blockhash.to_string(),
+                block_time: b.block_time,
+                block_height: b.block_height,
+                executed_transaction_count: Some(b.executed_transaction_count),
+            },
+            ReplicaBlockInfoVersions::V0_0_3(b) => BlockMetaUpdate {
+                slot: b.slot,
+                parent_slot: Some(b.parent_slot),
+                blockhash: b.blockhash.to_string(),
+                block_time: b.block_time,
+                block_height: b.block_height,
+                executed_transaction_count: Some(b.executed_transaction_count),
+            },
+            ReplicaBlockInfoVersions::V0_0_4(b) => BlockMetaUpdate {
+                slot: b.slot,
+                parent_slot: Some(b.parent_slot),
+                blockhash: b.blockhash.to_string(),
+                block_time: b.block_time,
+                block_height: b.block_height,
+                executed_transaction_count: Some(b.executed_transaction_count),
+            },
+        };
+        debug!(slot = update.slot; "Notifying block metadata");
+        self.publish(ChainEvent::BlockMeta(update));
         Ok(())
     }
 
@@ -160,23 +392,94 @@ impl GeyserPlugin for SolonaGeyser {
 //=======================================================================
 impl SolonaCollector {
     //=======================================================================
-    pub fn new(receiver: Receiver<i32>) -> Self {
-        SolonaCollector { receiver }
+    pub fn new(subscriber: EventSubscriber) -> Self {
+        SolonaCollector {
+            subscriber,
+            metrics: GeyserMetrics::new(),
+            health: ComponentHealth::default(),
+            alerter: Alerter::default(),
+        }
+    }
+
+    //=======================================================================
+    /// Report as `geyser_collector`: connected once the validator's events
+    /// reach it and until the bus closes, with the highest slot seen in the
+    /// events it drains.
+    pub fn with_health(mut self, registry: &HealthRegistry) -> Self {
+        self.health = registry.component(COLLECTOR_SUBSCRIBER);
+        self
     }
 
     //=======================================================================
-    pub async fn listen(&self) {
+    /// Alert when the collector falls behind the bus or the bus closes.
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        self.alerter = alerter;
+        self
+    }
+
+    //=======================================================================
+    /// Drain the bus once a second until `shutdown` is cancelled, then drain
+    /// whatever is left and return.
+    pub async fn listen(&mut self, shutdown: CancellationToken) {
+        self.health.set_connection(ConnectionState::Connecting);
+        self.drain(shutdown).await;
+        self.health.set_connection(ConnectionState::Disconnected);
+    }
+
+    //=======================================================================
+    async fn drain(&mut self, shutdown: CancellationToken) {
         loop {
-            sleep(Duration::from_secs(1)).await;
-            println!("Listener awake, checking for messages...");
-            let mut message_buffer = Vec::new();
-            while let Ok(message) = self.receiver.try_recv() {
-                if message == -1 {
-                    return;
+            let stopping = tokio::select! {
+                _ = shutdown.cancelled() => true,
+                _ = sleep(Duration::from_secs(1)) => false,
+            };
+            let mut event_buffer = Vec::new();
+            self.metrics
+                .channel_occupancy
+                .set(self.subscriber.len() as f64);
+            loop {
+                match self.subscriber.try_recv() {
+                    Ok(event) => event_buffer.push(event),
+                    Err(TryRecvError::Lagged(n)) => {
+                        self.metrics.channel_drops.inc_by(n);
+                        warn!(dropped = n; "SolonaCollector fell behind");
+                        self.alerter.fire(
+                            Alert::new(
+                                Severity::Warning,
+                                "collector_lagged",
+                                COLLECTOR_SUBSCRIBER,
+                                "collector fell behind the event bus",
+                            )
+                            .with_field("dropped", n),
+                        );
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Closed) => {
+                        info!("Event bus closed, SolonaCollector stopping");
+                        self.health.set_connection(ConnectionState::Disconnected);
+                        self.alerter.fire(Alert::new(
+                            Severity::Critical,
+                            "disconnected",
+                            COLLECTOR_SUBSCRIBER,
+                            "event bus closed",
+                        ));
+                        return;
+                    }
                 }
-                message_buffer.push(message);
             }
-            info!("Message buffer: {:?}", message_buffer);
+            self.metrics.processed.inc_by(event_buffer.len() as u64);
+            if !event_buffer.is_empty() {
+                self.health.set_connection(ConnectionState::Connected);
+                self.health.message();
+            }
+            if let Some(slot) = event_buffer.iter().filter_map(|e| e.slot()).max() {
+                self.health.slot(slot);
+            }
+            debug!(events = event_buffer.len(); "SolonaCollector drained events");
+            if stopping {
+                info!("SolonaCollector drained, stopping");
+                return;
+            }
         }
     }
 }
@@ -195,9 +498,6 @@ pub unsafe extern "C" fn _create_plugin() -> *mut dyn GeyserPlugin {
 
 #[cfg(test)]
 mod tests {
-
-    use super::*;
-
     #[tokio::test]
     async fn test_solona_geyser() {}
 }
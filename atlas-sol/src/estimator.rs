@@ -0,0 +1,260 @@
+use crate::fees::{block_fees, percentile, TransactionFees};
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status_client_types::EncodedConfirmedBlock;
+use std::collections::{BTreeMap, HashMap};
+
+//=======================================================================
+/// Unit prices that landed in one slot, overall and per writable account.
+#[derive(Debug, Default)]
+struct SlotPrices {
+    all: Vec<u64>,
+    by_account: HashMap<Pubkey, Vec<u64>>,
+}
+
+//=======================================================================
+/// Compute unit prices that landed over the last `window` slots. Feed it
+/// every block seen, from the block stream, geyser or a recording.
+#[derive(Debug)]
+pub struct FeeEstimator {
+    window: u64,
+    slots: BTreeMap<Slot, SlotPrices>,
+}
+
+//=======================================================================
+/// The price to bid and what it was derived from. Prices are in
+/// micro-lamports per compute unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeEstimate {
+    pub unit_price: u64,
+    /// Percentile over every transaction in the window; `None` when the
+    /// window is empty.
+    pub global: Option<u64>,
+    /// Percentile over transactions writing each account; `None` for
+    /// accounts nobody wrote in the window.
+    pub accounts: Vec<(Pubkey, Option<u64>)>,
+}
+
+//=======================================================================
+impl FeeEstimator {
+    //=======================================================================
+    pub fn new(window: u64) -> Self {
+        FeeEstimator {
+            window: window.max(1),
+            slots: BTreeMap::new(),
+        }
+    }
+
+    //=======================================================================
+    /// Record the transactions of `slot`. Votes and failed transactions
+    /// are skipped: neither says what it takes to land. Observing a slot
+    /// again replaces it; slots that fell out of the window are dropped.
+    pub fn observe(&mut self, slot: Slot, fees: &[TransactionFees]) {
+        if self
+            .newest()
+            .is_some_and(|newest| slot + self.window <= newest)
+        {
+            return;
+        }
+        let mut prices = SlotPrices::default();
+        for tx in fees.iter().filter(|tx| tx.succeeded && !tx.is_vote) {
+            let price = tx.budget.unit_price;
+            prices.all.push(price);
+            for account in &tx.writable {
+                prices.by_account.entry(*account).or_default().push(price);
+            }
+        }
+        self.slots.insert(slot, prices);
+        let newest = self.newest().unwrap_or(slot);
+        self.slots = self
+            .slots
+            .split_off(&newest.saturating_sub(self.window - 1));
+    }
+
+    //=======================================================================
+    /// Decode and record a block as `getBlock` or `blockSubscribe` returns
+    /// it. Transactions that fail to decode are left out rather than
+    /// losing the whole block.
+    pub fn observe_block(&mut self, slot: Slot, block: &EncodedConfirmedBlock) {
+        self.observe(slot, &block_fees(slot, block));
+    }
+
+    //=======================================================================
+    pub fn newest(&self) -> Option<Slot> {
+        self.slots.keys().next_back().copied()
+    }
+
+    //=======================================================================
+    /// Slots currently in the window.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    //=======================================================================
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    //=======================================================================
+    /// Price that would have beaten `confidence` percent of what landed,
+    /// globally and on each of `accounts`. The bid is the highest of them:
+    /// the most contended account sets the price.
+    pub fn estimate(&self, accounts: &[Pubkey], confidence: u8) -> FeeEstimate {
+        let mut all: Vec<u64> = self
+            .slots
+            .values()
+            .flat_map(|s| s.all.iter().copied())
+            .collect();
+        all.sort_unstable();
+        let global = percentile(&all, confidence);
+        let accounts: Vec<_> = accounts
+            .iter()
+            .map(|account| {
+                let mut prices: Vec<u64> = self
+                    .slots
+                    .values()
+                    .filter_map(|s| s.by_account.get(account))
+                    .flatten()
+                    .copied()
+                    .collect();
+                prices.sort_unstable();
+                (*account, percentile(&prices, confidence))
+            })
+            .collect();
+        FeeEstimate {
+            unit_price: accounts
+                .iter()
+                .filter_map(|(_, price)| *price)
+                .chain(global)
+                .max()
+                .unwrap_or(0),
+            global,
+            accounts,
+        }
+    }
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_transaction_status_client_types::EncodedTransactionWithStatusMeta;
+
+    const FIXTURES: [&str; 3] = [
+        include_str!("../test/fixtures/native_buy.json"),
+        include_str!("../test/fixtures/wsol_unwrap.json"),
+        include_str!("../test/fixtures/routed_swap.json"),
+    ];
+
+    //=======================================================================
+    fn recorded() -> TransactionFees {
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_str(FIXTURES[0]).unwrap();
+        TransactionFees::from_encoded(&tx).unwrap()
+    }
+
+    //=======================================================================
+    fn priced(base: &TransactionFees, price: u64, writable: &[Pubkey]) -> TransactionFees {
+        let mut tx = base.clone();
+        tx.budget.unit_price = price;
+        tx.writable = writable.to_vec();
+        tx
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_estimate_by_account() {
+        let base = recorded();
+        let hot = Pubkey::new_unique();
+        let cold = Pubkey::new_unique();
+        let mut estimator = FeeEstimator::new(3);
+
+        for slot in 100..110 {
+            let fees: Vec<_> = (1..=10)
+                .map(|i| priced(&base, i * 100, &[cold]))
+                .chain((1..=4).map(|i| priced(&base, 50_000 * i, &[hot])))
+                .collect();
+            estimator.observe(slot, &fees);
+        }
+        assert_eq!(estimator.len(), 3);
+        assert_eq!(estimator.newest(), Some(109));
+
+        let estimate = estimator.estimate(&[cold], 50);
+        assert_eq!(estimate.accounts, vec![(cold, Some(500))]);
+        assert_eq!(estimate.global, Some(700));
+        assert_eq!(estimate.unit_price, 700);
+
+        // The contended account sets the price.
+        let estimate = estimator.estimate(&[cold, hot], 75);
+        assert_eq!(estimate.accounts[1], (hot, Some(150_000)));
+        assert_eq!(estimate.unit_price, 150_000);
+
+        let unseen = Pubkey::new_unique();
+        assert_eq!(estimator.estimate(&[unseen], 90).accounts[0].1, None);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_observe_block() {
+        let txs: Vec<serde_json::Value> = FIXTURES
+            .iter()
+            .map(|f| serde_json::from_str(f).unwrap())
+            .collect();
+        let fees: Vec<_> = txs
+            .iter()
+            .map(|tx| TransactionFees::from_encoded(&serde_json::from_value(tx.clone()).unwrap()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mut broken = txs[0].clone();
+        broken["meta"] = serde_json::Value::Null;
+        let mut transactions = txs.clone();
+        transactions.insert(1, broken);
+        let block: EncodedConfirmedBlock = serde_json::from_value(serde_json::json!({
+            "previousBlockhash": "AkLLYdodNoPmJyXk6yGVu6yza66PRPMN3xwpwiSs2RpZ",
+            "blockhash": "AkLLYdodNoPmJyXk6yGVu6yza66PRPMN3xwpwiSs2RpZ",
+            "parentSlot": 41,
+            "transactions": transactions,
+            "rewards": [],
+        }))
+        .unwrap();
+
+        // The undecodable transaction is left out; the rest count as if
+        // observed one by one.
+        let mut from_block = FeeEstimator::new(4);
+        from_block.observe_block(42, &block);
+        let mut direct = FeeEstimator::new(4);
+        direct.observe(42, &fees);
+        assert_eq!(from_block.len(), 1);
+        let accounts: Vec<_> = fees.iter().flat_map(|f| f.writable.clone()).collect();
+        for confidence in [0, 50, 100] {
+            let estimate = from_block.estimate(&accounts, confidence);
+            assert_eq!(estimate, direct.estimate(&accounts, confidence));
+            assert!(estimate.global.is_some());
+        }
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_window_and_filtering() {
+        let base = recorded();
+        let account = base.writable[0];
+        let mut estimator = FeeEstimator::new(2);
+        assert_eq!(estimator.estimate(&[account], 90).unit_price, 0);
+
+        let mut vote = priced(&base, 1_000_000, &[account]);
+        vote.is_vote = true;
+        let mut failed = priced(&base, 1_000_000, &[account]);
+        failed.succeeded = false;
+        estimator.observe(10, &[base.clone(), vote, failed]);
+        assert_eq!(estimator.estimate(&[account], 100).unit_price, 100_000);
+
+        // Re-observing replaces the slot; late slots outside the window are
+        // ignored, newer ones push old ones out.
+        estimator.observe(10, &[priced(&base, 7, &[account])]);
+        estimator.observe(11, &[priced(&base, 9, &[account])]);
+        estimator.observe(9, &[priced(&base, 5_000, &[account])]);
+        assert_eq!(estimator.estimate(&[account], 100).unit_price, 9);
+        estimator.observe(13, &[priced(&base, 1, &[account])]);
+        assert_eq!(estimator.len(), 1);
+        assert_eq!(estimator.estimate(&[account], 100).unit_price, 1);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::balance::{fee_breakdown, FeeBreakdown};
+use crate::programs::ComputeBudget;
+use crate::transaction::{decode_confirmed_transaction, DecodedMessage, VOTE_PROGRAM_ID};
+use atlas_core::bus::BlockFeeUpdate;
+use atlas_core::error::AtlasResult;
+use log::{debug, warn};
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedBlock, EncodedTransactionWithStatusMeta,
+};
+
+/// Percentiles of unit prices reported per block.
+pub const FEE_PERCENTILES: [u8; 6] = [0, 25, 50, 75, 90, 100];
+
+//=======================================================================
+/// What one transaction asked for and paid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionFees {
+    pub signature: String,
+    pub is_vote: bool,
+    pub succeeded: bool,
+    pub fee: FeeBreakdown,
+    pub budget: ComputeBudget,
+    /// `None` on nodes too old to report it.
+    pub units_consumed: Option<u64>,
+    /// Accounts the transaction write-locks, fee payer first.
+    pub writable: Vec<Pubkey>,
+}
+
+//=======================================================================
+impl TransactionFees {
+    //=======================================================================
+    pub fn from_encoded(tx: &EncodedTransactionWithStatusMeta) -> AtlasResult<Self> {
+        let (signature, message, meta) = decode_confirmed_transaction(tx)?;
+        Self::from_message(
+            signature,
+            &message,
+            meta.fee,
+            meta.err.is_none(),
+            meta.compute_units_consumed.clone().into(),
+        )
+    }
+
+    //=======================================================================
+    /// For sources that hand over the message and status separately, such
+    /// as geyser.
+    pub fn from_message(
+        signature: String,
+        message: &DecodedMessage,
+        fee: u64,
+        succeeded: bool,
+        units_consumed: Option<u64>,
+    ) -> AtlasResult<Self> {
+        let budget = ComputeBudget::from_instructions(&message.instructions)
+            .map_err(|e| e.in_tx(&signature))?;
+        let payer = message
+            .account_keys
+            .first()
+            .map(|key| key.pubkey)
+            .unwrap_or_default();
+        let vote = VOTE_PROGRAM_ID.parse().unwrap();
+        Ok(TransactionFees {
+            is_vote: message.instructions.iter().any(|ix| ix.program_id == vote),
+            succeeded,
+            fee: fee_breakdown(payer, message, fee),
+            budget,
+            units_consumed,
+            writable: message
+                .account_keys
+                .iter()
+                .filter(|key| key.is_writable)
+                .map(|key| key.pubkey)
+                .collect(),
+            signature,
+        })
+    }
+
+    //=======================================================================
+    /// Priority fee implied by the compute budget. Matches `fee.priority`
+    /// unless the node charged something the budget does not explain.
+    pub fn priority_fee(&self) -> u64 {
+        self.budget.priority_fee()
+    }
+}
+
+//=======================================================================
+/// Fees of every transaction in `block`, in block order. Transactions that
+/// fail to decode are left out rather than losing the whole block.
+pub fn block_fees(slot: Slot, block: &EncodedConfirmedBlock) -> Vec<TransactionFees> {
+    let mut skipped = 0;
+    let fees: Vec<_> = block
+        .transactions
+        .iter()
+        .filter_map(|tx| {
+            TransactionFees::from_encoded(tx)
+                .map_err(|e| {
+                    skipped += 1;
+                    debug!(slot = slot; "{}", e);
+                })
+                .ok()
+        })
+        .collect();
+    if skipped > 0 {
+        warn!(
+            slot = slot;
+            "Left {} undecodable transactions out of block fees",
+            skipped
+        );
+    }
+    fees
+}
+
+//=======================================================================
+/// Fee statistics of a block's non-vote transactions, failed ones
+/// included since they paid too.
+pub fn block_fee_update(slot: Slot, fees: &[TransactionFees]) -> BlockFeeUpdate {
+    let paying: Vec<_> = fees.iter().filter(|f| !f.is_vote).collect();
+    let mut prices: Vec<u64> = paying.iter().map(|f| f.budget.unit_price).collect();
+    prices.sort_unstable();
+    BlockFeeUpdate {
+        slot,
+        transactions: paying.len() as u64,
+        units_consumed: paying.iter().filter_map(|f| f.units_consumed).sum(),
+        base_fees: paying.iter().map(|f| f.fee.base).sum(),
+        priority_fees: paying.iter().map(|f| f.fee.priority).sum(),
+        unit_prices: FEE_PERCENTILES
+            .iter()
+            .filter_map(|&p| percentile(&prices, p).map(|price| (p, price)))
+            .collect(),
+    }
+}
+
+//=======================================================================
+/// Nearest-rank percentile of `sorted`; `None` when it is empty.
+pub fn percentile(sorted: &[u64], p: u8) -> Option<u64> {
+    let rank = (p.min(100) as usize * sorted.len()).div_ceil(100);
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //=======================================================================
+    #[test]
+    fn test_block_fee_update() {
+        let tx: EncodedTransactionWithStatusMeta =
+            serde_json::from_str(include_str!("../test/fixtures/native_buy.json")).unwrap();
+        let buy = TransactionFees::from_encoded(&tx).unwrap();
+        assert_eq!(buy.budget.unit_limit, 200_000);
+        assert_eq!(buy.budget.unit_price, 100_000);
+        assert_eq!(buy.priority_fee(), buy.fee.priority);
+        assert!(!buy.is_vote && buy.succeeded);
+
+        let mut fees = vec![buy];
+        for (price, is_vote) in [(10, false), (0, true), (500, false), (0, false)] {
+            let mut other = fees[0].clone();
+            other.budget.unit_price = price;
+            other.is_vote = is_vote;
+            fees.push(other);
+        }
+        let update = block_fee_update(42, &fees);
+        assert_eq!(update.transactions, 4);
+        assert_eq!(update.priority_fees, 4 * 20_000);
+        assert_eq!(
+            update.unit_prices,
+            vec![
+                (0, 0),
+                (25, 0),
+                (50, 10),
+                (75, 500),
+                (90, 100_000),
+                (100, 100_000)
+            ]
+        );
+        assert!(block_fee_update(42, &[]).unit_prices.is_empty());
+        assert_eq!(percentile(&[1, 2, 3, 4, 5], 0), Some(1));
+    }
+}
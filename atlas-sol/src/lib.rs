@@ -1,2 +1,9 @@
+pub mod alt;
+pub mod balance;
+pub mod call_tree;
 pub mod collector;
+pub mod estimator;
+pub mod fees;
+pub mod programs;
+pub mod swap;
 pub mod transaction;
@@ -0,0 +1,79 @@
+use super::{deserialize, Accounts};
+use crate::transaction::DecodedInstruction;
+use atlas_core::error::AtlasResult;
+use solana_sdk::address_lookup_table::instruction::ProgramInstruction as Wire;
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressLookupTableInstruction {
+    CreateLookupTable {
+        table: Pubkey,
+        authority: Pubkey,
+        payer: Pubkey,
+        recent_slot: Slot,
+        bump_seed: u8,
+    },
+    FreezeLookupTable {
+        table: Pubkey,
+        authority: Pubkey,
+    },
+    /// `payer` funds the extra rent, when the table needs any.
+    ExtendLookupTable {
+        table: Pubkey,
+        authority: Pubkey,
+        payer: Option<Pubkey>,
+        new_addresses: Vec<Pubkey>,
+    },
+    DeactivateLookupTable {
+        table: Pubkey,
+        authority: Pubkey,
+    },
+    CloseLookupTable {
+        table: Pubkey,
+        authority: Pubkey,
+        recipient: Pubkey,
+    },
+}
+
+//=======================================================================
+pub(crate) fn decode(
+    instruction: &DecodedInstruction,
+) -> AtlasResult<AddressLookupTableInstruction> {
+    const PROGRAM: &str = "address lookup table instruction";
+    let keys = Accounts::new(PROGRAM, instruction);
+    Ok(match deserialize(PROGRAM, &instruction.data)? {
+        Wire::CreateLookupTable {
+            recent_slot,
+            bump_seed,
+        } => AddressLookupTableInstruction::CreateLookupTable {
+            table: keys.get(0)?,
+            authority: keys.get(1)?,
+            payer: keys.get(2)?,
+            recent_slot,
+            bump_seed,
+        },
+        Wire::FreezeLookupTable => AddressLookupTableInstruction::FreezeLookupTable {
+            table: keys.get(0)?,
+            authority: keys.get(1)?,
+        },
+        Wire::ExtendLookupTable { new_addresses } => {
+            AddressLookupTableInstruction::ExtendLookupTable {
+                table: keys.get(0)?,
+                authority: keys.get(1)?,
+                payer: keys.optional(2),
+                new_addresses,
+            }
+        }
+        Wire::DeactivateLookupTable => AddressLookupTableInstruction::DeactivateLookupTable {
+            table: keys.get(0)?,
+            authority: keys.get(1)?,
+        },
+        Wire::CloseLookupTable => AddressLookupTableInstruction::CloseLookupTable {
+            table: keys.get(0)?,
+            authority: keys.get(1)?,
+            recipient: keys.get(2)?,
+        },
+    })
+}
@@ -0,0 +1,217 @@
+use crate::transaction::{
+    DecodedInstruction, ADDRESS_LOOKUP_PROGRAM_ID, COMPUTE_BUDGET_PROGRAM_ID, CONFIG_PROGRAM_ID,
+    STAKE_PROGRAM_ID, SYS_PROGRAM_ID, VOTE_PROGRAM_ID,
+};
+use atlas_core::error::{AtlasError, AtlasResult};
+
+const PROGRAM: &str = "compute budget instruction";
+
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// Default budget of an instruction to a program that is not a builtin.
+pub const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+/// Default budget of an instruction to a builtin program.
+pub const BUILTIN_INSTRUCTION_COMPUTE_UNIT_LIMIT: u32 = 3_000;
+pub const MICRO_LAMPORTS_PER_LAMPORT: u64 = 1_000_000;
+
+/// Programs the runtime runs natively, which get the smaller default budget.
+static BUILTIN_PROGRAM_IDS: &[&str] = &[
+    SYS_PROGRAM_ID,
+    CONFIG_PROGRAM_ID,
+    STAKE_PROGRAM_ID,
+    VOTE_PROGRAM_ID,
+    ADDRESS_LOOKUP_PROGRAM_ID,
+    COMPUTE_BUDGET_PROGRAM_ID,
+    "BPFLoader1111111111111111111111111111111111",
+    "BPFLoader2111111111111111111111111111111111",
+    "BPFLoaderUpgradeab1e11111111111111111111111",
+    "LoaderV411111111111111111111111111111111111",
+];
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComputeBudgetInstruction {
+    /// Superseded by `SetComputeUnitLimit` and `SetComputeUnitPrice`; the
+    /// runtime rejects it.
+    RequestUnitsDeprecated {
+        units: u32,
+        additional_fee: u32,
+    },
+    /// Heap size in bytes, a multiple of 1024.
+    RequestHeapFrame {
+        bytes: u32,
+    },
+    SetComputeUnitLimit {
+        units: u32,
+    },
+    /// Price in micro-lamports per compute unit.
+    SetComputeUnitPrice {
+        micro_lamports: u64,
+    },
+    SetLoadedAccountsDataSizeLimit {
+        bytes: u32,
+    },
+}
+
+//=======================================================================
+/// A transaction's compute budget, as the runtime settles it from its
+/// compute budget instructions and the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComputeBudget {
+    pub unit_limit: u32,
+    /// Micro-lamports per compute unit; zero when none was set.
+    pub unit_price: u64,
+    pub heap_frame: Option<u32>,
+    pub loaded_accounts_data_size_limit: Option<u32>,
+}
+
+//=======================================================================
+impl ComputeBudget {
+    //=======================================================================
+    /// Budget of a message's instructions. When an instruction is repeated
+    /// the runtime fails the transaction; the first one is used here.
+    pub fn from_instructions(instructions: &[DecodedInstruction]) -> AtlasResult<Self> {
+        let mut unit_limit = None;
+        let mut unit_price = None;
+        let mut heap_frame = None;
+        let mut data_size = None;
+        let mut default_limit = 0u32;
+        for instruction in instructions {
+            let program_id = instruction.program_id.to_string();
+            if program_id == COMPUTE_BUDGET_PROGRAM_ID {
+                match decode(instruction)? {
+                    ComputeBudgetInstruction::SetComputeUnitLimit { units } => {
+                        unit_limit.get_or_insert(units);
+                    }
+                    ComputeBudgetInstruction::SetComputeUnitPrice { micro_lamports } => {
+                        unit_price.get_or_insert(micro_lamports);
+                    }
+                    ComputeBudgetInstruction::RequestHeapFrame { bytes } => {
+                        heap_frame.get_or_insert(bytes);
+                    }
+                    ComputeBudgetInstruction::SetLoadedAccountsDataSizeLimit { bytes } => {
+                        data_size.get_or_insert(bytes);
+                    }
+                    ComputeBudgetInstruction::RequestUnitsDeprecated { .. } => {}
+                }
+            }
+            default_limit = default_limit.saturating_add(
+                if BUILTIN_PROGRAM_IDS.contains(&program_id.as_str()) {
+                    BUILTIN_INSTRUCTION_COMPUTE_UNIT_LIMIT
+                } else {
+                    DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT
+                },
+            );
+        }
+        Ok(ComputeBudget {
+            unit_limit: unit_limit
+                .unwrap_or(default_limit)
+                .min(MAX_COMPUTE_UNIT_LIMIT),
+            unit_price: unit_price.unwrap_or(0),
+            heap_frame,
+            loaded_accounts_data_size_limit: data_size,
+        })
+    }
+
+    //=======================================================================
+    /// Lamports bid for priority: the price of every requested unit,
+    /// rounded up, whether or not the units are used.
+    pub fn priority_fee(&self) -> u64 {
+        let micro = self.unit_limit as u128 * self.unit_price as u128;
+        micro
+            .div_ceil(MICRO_LAMPORTS_PER_LAMPORT as u128)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+//=======================================================================
+pub(crate) fn decode(instruction: &DecodedInstruction) -> AtlasResult<ComputeBudgetInstruction> {
+    let data = &instruction.data;
+    let u32_at = |at: usize| {
+        data.get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| AtlasError::decode(PROGRAM, "instruction data is truncated"))
+    };
+    Ok(match data.first() {
+        Some(0) => ComputeBudgetInstruction::RequestUnitsDeprecated {
+            units: u32_at(1)?,
+            additional_fee: u32_at(5)?,
+        },
+        Some(1) => ComputeBudgetInstruction::RequestHeapFrame { bytes: u32_at(1)? },
+        Some(2) => ComputeBudgetInstruction::SetComputeUnitLimit { units: u32_at(1)? },
+        Some(3) => ComputeBudgetInstruction::SetComputeUnitPrice {
+            micro_lamports: data
+                .get(1..9)
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                .ok_or_else(|| AtlasError::decode(PROGRAM, "instruction data is truncated"))?,
+        },
+        Some(4) => ComputeBudgetInstruction::SetLoadedAccountsDataSizeLimit { bytes: u32_at(1)? },
+        Some(tag) => {
+            return Err(AtlasError::decode(
+                PROGRAM,
+                format!("unknown instruction {}", tag),
+            ))
+        }
+        None => return Err(AtlasError::decode(PROGRAM, "instruction data is empty")),
+    })
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction as Sdk;
+    use solana_sdk::pubkey::Pubkey;
+    #[allow(deprecated)]
+    use solana_sdk::system_instruction;
+
+    //=======================================================================
+    #[test]
+    fn test_compute_budget() {
+        let payer = Pubkey::new_unique();
+        let transfer = || DecodedInstruction::from(system_instruction::transfer(&payer, &payer, 1));
+        let program = || DecodedInstruction {
+            program_id: Pubkey::new_unique(),
+            data: vec![],
+            keys: vec![],
+        };
+
+        // Defaults: one builtin and two program instructions, no price.
+        let budget = ComputeBudget::from_instructions(&[transfer(), program(), program()]).unwrap();
+        assert_eq!(budget.unit_limit, 403_000);
+        assert_eq!(budget.priority_fee(), 0);
+
+        let instructions: Vec<DecodedInstruction> = vec![
+            Sdk::set_compute_unit_limit(150_001),
+            Sdk::set_compute_unit_price(3_333),
+            Sdk::request_heap_frame(256 * 1024),
+            Sdk::set_loaded_accounts_data_size_limit(64 * 1024),
+        ]
+        .into_iter()
+        .map(DecodedInstruction::from)
+        .chain([program()])
+        .collect();
+        assert_eq!(
+            decode(&instructions[1]).unwrap(),
+            ComputeBudgetInstruction::SetComputeUnitPrice {
+                micro_lamports: 3_333
+            }
+        );
+        let budget = ComputeBudget::from_instructions(&instructions).unwrap();
+        assert_eq!(
+            budget,
+            ComputeBudget {
+                unit_limit: 150_001,
+                unit_price: 3_333,
+                heap_frame: Some(256 * 1024),
+                loaded_accounts_data_size_limit: Some(64 * 1024),
+            }
+        );
+        // 150_001 * 3_333 = 499_953_333 micro-lamports, rounded up.
+        assert_eq!(budget.priority_fee(), 500);
+
+        let mut truncated = DecodedInstruction::from(Sdk::set_compute_unit_price(1));
+        truncated.data.truncate(5);
+        assert!(ComputeBudget::from_instructions(&[truncated]).is_err());
+    }
+}
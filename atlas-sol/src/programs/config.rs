@@ -0,0 +1,99 @@
+use super::Accounts;
+use crate::transaction::DecodedInstruction;
+use atlas_core::error::{AtlasError, AtlasResult};
+use solana_sdk::pubkey::Pubkey;
+
+const PROGRAM: &str = "config instruction";
+
+//=======================================================================
+/// The config program has a single instruction: store `data` in the config
+/// account, listing the keys that describe it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigInstruction {
+    Store {
+        config_account: Pubkey,
+        /// Accounts that signed for the change, after the config account.
+        signers: Vec<Pubkey>,
+        /// Each key and whether it must sign future changes.
+        keys: Vec<(Pubkey, bool)>,
+        data: Vec<u8>,
+    },
+}
+
+//=======================================================================
+/// Read a compact-u16 length, as `short_vec` writes it.
+fn short_len(data: &[u8]) -> AtlasResult<(usize, usize)> {
+    let mut len = 0usize;
+    for (i, byte) in data.iter().take(3).enumerate() {
+        len |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((len, i + 1));
+        }
+    }
+    Err(AtlasError::decode(PROGRAM, "bad key count"))
+}
+
+//=======================================================================
+pub(crate) fn decode(instruction: &DecodedInstruction) -> AtlasResult<ConfigInstruction> {
+    let accounts = Accounts::new(PROGRAM, instruction);
+    let data = &instruction.data;
+    let (count, mut at) = short_len(data)?;
+    let mut keys = Vec::with_capacity(count);
+    for _ in 0..count {
+        let entry = data
+            .get(at..at + 33)
+            .ok_or_else(|| AtlasError::decode(PROGRAM, "key list is truncated"))?;
+        let key = Pubkey::try_from(&entry[..32]).map_err(|e| AtlasError::decode(PROGRAM, e))?;
+        keys.push((key, entry[32] != 0));
+        at += 33;
+    }
+    Ok(ConfigInstruction::Store {
+        config_account: accounts.get(0)?,
+        signers: instruction.keys.iter().skip(1).map(|k| k.pubkey).collect(),
+        keys,
+        data: data[at..].to_vec(),
+    })
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::CONFIG_PROGRAM_ID;
+    use solana_sdk::instruction::AccountMeta;
+
+    //=======================================================================
+    #[test]
+    fn test_decode_store() {
+        let config_account = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let mut data = vec![2];
+        data.extend_from_slice(Pubkey::default().as_ref());
+        data.push(0);
+        data.extend_from_slice(signer.as_ref());
+        data.push(1);
+        data.extend_from_slice(b"validator-info");
+        let instruction = DecodedInstruction {
+            program_id: CONFIG_PROGRAM_ID.parse().unwrap(),
+            data,
+            keys: vec![
+                AccountMeta::new(config_account, false),
+                AccountMeta::new_readonly(signer, true),
+            ],
+        };
+        assert_eq!(
+            decode(&instruction).unwrap(),
+            ConfigInstruction::Store {
+                config_account,
+                signers: vec![signer],
+                keys: vec![(Pubkey::default(), false), (signer, true)],
+                data: b"validator-info".to_vec(),
+            }
+        );
+        assert_eq!(short_len(&[0x80, 0x01]).unwrap(), (128, 2));
+
+        let mut truncated = instruction;
+        truncated.data.truncate(40);
+        assert!(decode(&truncated).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::transaction::{
+    DecodedInstruction, ADDRESS_LOOKUP_PROGRAM_ID, COMPUTE_BUDGET_PROGRAM_ID, CONFIG_PROGRAM_ID,
+    STAKE_PROGRAM_ID, SYS_PROGRAM_ID, VOTE_PROGRAM_ID,
+};
+use atlas_core::error::{AtlasError, AtlasResult};
+use serde::de::DeserializeOwned;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt::Debug;
+
+pub mod address_lookup_table;
+pub mod compute_budget;
+pub mod config;
+pub mod precompile;
+pub mod stake;
+pub mod system;
+pub mod token;
+pub mod token_account;
+pub mod vote;
+
+pub use address_lookup_table::AddressLookupTableInstruction;
+pub use compute_budget::{ComputeBudget, ComputeBudgetInstruction};
+pub use config::ConfigInstruction;
+pub use stake::StakeInstruction;
+pub use system::SystemInstruction;
+pub use token::{
+    MovementKind, TokenInstruction, TokenMints, TokenMovement, TokenMovements, TokenProgram,
+};
+pub use token_account::{decode_token_account, TokenAccountData, TokenExtension};
+pub use vote::VoteInstruction;
+
+//=======================================================================
+/// An instruction to one of the native programs, with its accounts named.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NativeInstruction {
+    System(SystemInstruction),
+    Config(ConfigInstruction),
+    Stake(StakeInstruction),
+    Vote(VoteInstruction),
+    AddressLookupTable(AddressLookupTableInstruction),
+    ComputeBudget(ComputeBudgetInstruction),
+}
+
+//=======================================================================
+/// Which authority an `Authorize` instruction changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthorityKind {
+    Staker,
+    Withdrawer,
+    Voter,
+}
+
+//=======================================================================
+impl DecodedInstruction {
+    //=======================================================================
+    /// Typed form of the instruction; `None` when its program is not a
+    /// native one.
+    pub fn native(&self) -> AtlasResult<Option<NativeInstruction>> {
+        let program_id = self.program_id.to_string();
+        let native = match program_id.as_str() {
+            id if id == SYS_PROGRAM_ID => NativeInstruction::System(system::decode(self)?),
+            id if id == CONFIG_PROGRAM_ID => NativeInstruction::Config(config::decode(self)?),
+            id if id == STAKE_PROGRAM_ID => NativeInstruction::Stake(stake::decode(self)?),
+            id if id == VOTE_PROGRAM_ID => NativeInstruction::Vote(vote::decode(self)?),
+            id if id == ADDRESS_LOOKUP_PROGRAM_ID => {
+                NativeInstruction::AddressLookupTable(address_lookup_table::decode(self)?)
+            }
+            id if id == COMPUTE_BUDGET_PROGRAM_ID => {
+                NativeInstruction::ComputeBudget(compute_budget::decode(self)?)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(native))
+    }
+}
+
+//=======================================================================
+/// An instruction's accounts by position, with errors naming the program.
+pub(crate) struct Accounts<'a> {
+    program: &'static str,
+    keys: &'a [AccountMeta],
+}
+
+//=======================================================================
+impl<'a> Accounts<'a> {
+    pub(crate) fn new(program: &'static str, instruction: &'a DecodedInstruction) -> Self {
+        Accounts {
+            program,
+            keys: &instruction.keys,
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> AtlasResult<Pubkey> {
+        self.optional(index).ok_or_else(|| {
+            AtlasError::decode(
+                self.program,
+                format!(
+                    "expected account #{}, instruction has {}",
+                    index,
+                    self.keys.len()
+                ),
+            )
+        })
+    }
+
+    pub(crate) fn optional(&self, index: usize) -> Option<Pubkey> {
+        self.keys.get(index).map(|meta| meta.pubkey)
+    }
+}
+
+//=======================================================================
+/// Instruction data the way the runtime reads it: bincode, fixed-width
+/// integers, trailing bytes allowed.
+pub(crate) fn deserialize<T: DeserializeOwned>(program: &str, data: &[u8]) -> AtlasResult<T> {
+    bincode::deserialize(data).map_err(|e| AtlasError::decode(program, e))
+}
+
+//=======================================================================
+/// Variant name of an instruction we do not model field by field.
+pub(crate) fn variant_name(instruction: &impl Debug) -> String {
+    let debug = format!("{:?}", instruction);
+    debug
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    #[allow(deprecated)]
+    use solana_sdk::{address_lookup_table, stake, system_instruction, vote};
+
+    //=======================================================================
+    #[test]
+    fn test_decode_native_instructions() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let transfer = DecodedInstruction::from(system_instruction::transfer(&from, &to, 42));
+        assert_eq!(
+            transfer.native().unwrap(),
+            Some(NativeInstruction::System(SystemInstruction::Transfer {
+                from,
+                to,
+                lamports: 42
+            }))
+        );
+
+        let stake_account = Pubkey::new_unique();
+        let vote_account = Pubkey::new_unique();
+        let delegate = DecodedInstruction::from(stake::instruction::delegate_stake(
+            &stake_account,
+            &from,
+            &vote_account,
+        ));
+        assert_eq!(
+            delegate.native().unwrap(),
+            Some(NativeInstruction::Stake(StakeInstruction::Delegate {
+                stake: stake_account,
+                vote_account,
+                authority: from,
+            }))
+        );
+
+        let (create, table) =
+            address_lookup_table::instruction::create_lookup_table(from, to, 1_000);
+        assert!(matches!(
+            DecodedInstruction::from(create).native().unwrap(),
+            Some(NativeInstruction::AddressLookupTable(
+                AddressLookupTableInstruction::CreateLookupTable {
+                    table: t,
+                    authority,
+                    payer,
+                    recent_slot: 1_000,
+                    ..
+                }
+            )) if t == table && authority == from && payer == to
+        ));
+
+        let hash = Hash::new_unique();
+        let tower = vote::state::TowerSync::new_from_slots(vec![7, 8, 9], hash, Some(5));
+        let sync =
+            DecodedInstruction::from(vote::instruction::tower_sync(&vote_account, &from, tower));
+        assert_eq!(
+            sync.native().unwrap(),
+            Some(NativeInstruction::Vote(VoteInstruction::TowerSync {
+                vote_account,
+                authority: from,
+                root: Some(5),
+                last_voted_slot: Some(9),
+                hash,
+            }))
+        );
+
+        let mut truncated = DecodedInstruction::from(system_instruction::transfer(&from, &to, 1));
+        truncated.keys.pop();
+        assert!(truncated.native().is_err());
+        let unknown = DecodedInstruction {
+            program_id: Pubkey::new_unique(),
+            data: vec![],
+            keys: vec![],
+        };
+        assert_eq!(unknown.native().unwrap(), None);
+    }
+}
@@ -0,0 +1,437 @@
+use crate::transaction::{
+    DecodedInstruction, DecodedMessage, ED25519_PROGRAM_ID, SECP256K1_PROGRAM_ID,
+    SECP256R1_PROGRAM_ID,
+};
+use atlas_core::error::{AtlasError, AtlasResult};
+use p256::ecdsa::signature::Verifier;
+use solana_sdk::keccak;
+
+/// Offsets pointing at this value mean the precompile instruction itself.
+const CURRENT_INSTRUCTION: u16 = u16::MAX;
+const SIGNATURE_SIZE: usize = 64;
+
+//=======================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precompile {
+    Ed25519,
+    /// Recovers an Ethereum address rather than checking a public key.
+    Secp256k1,
+    Secp256r1,
+}
+
+//=======================================================================
+impl Precompile {
+    //=======================================================================
+    pub fn from_program_id(program_id: &str) -> Option<Self> {
+        match program_id {
+            id if id == ED25519_PROGRAM_ID => Some(Precompile::Ed25519),
+            id if id == SECP256K1_PROGRAM_ID => Some(Precompile::Secp256k1),
+            id if id == SECP256R1_PROGRAM_ID => Some(Precompile::Secp256r1),
+            _ => None,
+        }
+    }
+
+    fn what(self) -> &'static str {
+        match self {
+            Precompile::Ed25519 => "ed25519 instruction",
+            Precompile::Secp256k1 => "secp256k1 instruction",
+            Precompile::Secp256r1 => "secp256r1 instruction",
+        }
+    }
+
+    /// Size of the public key, or of the Ethereum address for secp256k1.
+    fn key_size(self) -> usize {
+        match self {
+            Precompile::Ed25519 => 32,
+            Precompile::Secp256k1 => 20,
+            Precompile::Secp256r1 => 33,
+        }
+    }
+}
+
+//=======================================================================
+/// Where one signature's parts live. Instruction indexes are positions in
+/// the transaction; secp256k1 stores them as `u8`, the others as `u16` with
+/// `u16::MAX` meaning the precompile instruction itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureOffsets {
+    pub signature_offset: u16,
+    pub signature_instruction_index: u16,
+    pub public_key_offset: u16,
+    pub public_key_instruction_index: u16,
+    pub message_data_offset: u16,
+    pub message_data_size: u16,
+    pub message_instruction_index: u16,
+}
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrecompileSignature {
+    pub precompile: Precompile,
+    pub offsets: SignatureOffsets,
+    pub signature: [u8; SIGNATURE_SIZE],
+    /// Only secp256k1 signatures carry one.
+    pub recovery_id: Option<u8>,
+    /// Ed25519 key, compressed secp256r1 key or 20-byte Ethereum address.
+    pub public_key: Vec<u8>,
+    pub message: Vec<u8>,
+}
+
+//=======================================================================
+impl PrecompileSignature {
+    //=======================================================================
+    /// Check the signature as the runtime would, without a node.
+    pub fn is_valid(&self) -> bool {
+        match self.precompile {
+            Precompile::Ed25519 => self.verify_ed25519(),
+            Precompile::Secp256k1 => self.recover_eth_address() == Some(self.public_key.clone()),
+            Precompile::Secp256r1 => self.verify_secp256r1(),
+        }
+    }
+
+    //=======================================================================
+    fn verify_ed25519(&self) -> bool {
+        let (Ok(key), Ok(signature)) = (
+            ed25519_dalek::PublicKey::from_bytes(&self.public_key),
+            ed25519_dalek::Signature::from_bytes(&self.signature),
+        ) else {
+            return false;
+        };
+        key.verify_strict(&self.message, &signature).is_ok()
+    }
+
+    //=======================================================================
+    /// Ethereum address of the key that signed, high-S signatures rejected.
+    pub fn recover_eth_address(&self) -> Option<Vec<u8>> {
+        let signature = libsecp256k1::Signature::parse_standard_slice(&self.signature).ok()?;
+        if signature.s.is_high() {
+            return None;
+        }
+        let recovery_id = libsecp256k1::RecoveryId::parse(self.recovery_id?).ok()?;
+        let digest = keccak::hash(&self.message);
+        let message = libsecp256k1::Message::parse_slice(digest.as_ref()).ok()?;
+        let key = libsecp256k1::recover(&message, &signature, &recovery_id).ok()?;
+        let hashed = keccak::hash(&key.serialize()[1..]);
+        Some(hashed.as_ref()[12..].to_vec())
+    }
+
+    //=======================================================================
+    fn verify_secp256r1(&self) -> bool {
+        let (Ok(key), Ok(signature)) = (
+            p256::ecdsa::VerifyingKey::from_sec1_bytes(&self.public_key),
+            p256::ecdsa::Signature::from_slice(&self.signature),
+        ) else {
+            return false;
+        };
+        // Only the low-S form is accepted, so a signature has one encoding.
+        signature.normalize_s().is_none() && key.verify(&self.message, &signature).is_ok()
+    }
+}
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrecompileInstruction {
+    pub precompile: Precompile,
+    pub signatures: Vec<PrecompileSignature>,
+}
+
+//=======================================================================
+impl PrecompileInstruction {
+    //=======================================================================
+    /// Whether every signature verifies, which is what the runtime requires
+    /// for the transaction to land.
+    pub fn is_valid(&self) -> bool {
+        self.signatures.iter().all(PrecompileSignature::is_valid)
+    }
+}
+
+//=======================================================================
+/// Parse `instructions[index]` if it is a precompile. The whole list is
+/// needed because offsets may point into other instructions.
+pub fn decode_precompile(
+    instructions: &[DecodedInstruction],
+    index: usize,
+) -> AtlasResult<Option<PrecompileInstruction>> {
+    let instruction = instructions
+        .get(index)
+        .ok_or_else(|| AtlasError::decode("precompile", format!("no instruction #{}", index)))?;
+    let Some(precompile) = Precompile::from_program_id(&instruction.program_id.to_string()) else {
+        return Ok(None);
+    };
+    let what = precompile.what();
+    let data = &instruction.data;
+    let count = data.first().copied().unwrap_or_default() as usize;
+    let (start, size) = match precompile {
+        Precompile::Secp256k1 => (1, 11),
+        _ => (2, 14),
+    };
+    if count == 0 || data.len() < start + count * size {
+        return Err(AtlasError::decode(
+            what,
+            format!(
+                "{} bytes cannot hold {} signature offsets",
+                data.len(),
+                count
+            ),
+        ));
+    }
+    let signatures = data[start..start + count * size]
+        .chunks_exact(size)
+        .map(|chunk| {
+            let offsets = parse_offsets(precompile, chunk);
+            let fetch = |instruction_index: u16, offset: u16, len: usize| {
+                let source = if instruction_index == CURRENT_INSTRUCTION
+                    && precompile != Precompile::Secp256k1
+                {
+                    instruction
+                } else {
+                    instructions
+                        .get(instruction_index as usize)
+                        .ok_or_else(|| {
+                            AtlasError::decode(
+                                what,
+                                format!("offset into missing instruction #{}", instruction_index),
+                            )
+                        })?
+                };
+                let start = offset as usize;
+                source.data.get(start..start + len).ok_or_else(|| {
+                    AtlasError::decode(
+                        what,
+                        format!(
+                            "{} bytes at {} is past the end of instruction #{}",
+                            len, start, instruction_index
+                        ),
+                    )
+                })
+            };
+            // secp256k1 appends the recovery id to the signature.
+            let signature_len = match precompile {
+                Precompile::Secp256k1 => SIGNATURE_SIZE + 1,
+                _ => SIGNATURE_SIZE,
+            };
+            let signature = fetch(
+                offsets.signature_instruction_index,
+                offsets.signature_offset,
+                signature_len,
+            )?;
+            Ok(PrecompileSignature {
+                precompile,
+                offsets,
+                signature: signature[..SIGNATURE_SIZE].try_into().unwrap(),
+                recovery_id: signature.get(SIGNATURE_SIZE).copied(),
+                public_key: fetch(
+                    offsets.public_key_instruction_index,
+                    offsets.public_key_offset,
+                    precompile.key_size(),
+                )?
+                .to_vec(),
+                message: fetch(
+                    offsets.message_instruction_index,
+                    offsets.message_data_offset,
+                    offsets.message_data_size as usize,
+                )?
+                .to_vec(),
+            })
+        })
+        .collect::<AtlasResult<_>>()?;
+    Ok(Some(PrecompileInstruction {
+        precompile,
+        signatures,
+    }))
+}
+
+//=======================================================================
+impl DecodedMessage {
+    //=======================================================================
+    /// Every precompile instruction in the message, with its position.
+    pub fn precompiles(&self) -> AtlasResult<Vec<(usize, PrecompileInstruction)>> {
+        let mut found = Vec::new();
+        for index in 0..self.instructions.len() {
+            if let Some(precompile) = decode_precompile(&self.instructions, index)? {
+                found.push((index, precompile));
+            }
+        }
+        Ok(found)
+    }
+}
+
+//=======================================================================
+fn parse_offsets(precompile: Precompile, chunk: &[u8]) -> SignatureOffsets {
+    let u16_at = |at: usize| u16::from_le_bytes([chunk[at], chunk[at + 1]]);
+    match precompile {
+        Precompile::Secp256k1 => SignatureOffsets {
+            signature_offset: u16_at(0),
+            signature_instruction_index: chunk[2] as u16,
+            public_key_offset: u16_at(3),
+            public_key_instruction_index: chunk[5] as u16,
+            message_data_offset: u16_at(6),
+            message_data_size: u16_at(8),
+            message_instruction_index: chunk[10] as u16,
+        },
+        _ => SignatureOffsets {
+            signature_offset: u16_at(0),
+            signature_instruction_index: u16_at(2),
+            public_key_offset: u16_at(4),
+            public_key_instruction_index: u16_at(6),
+            message_data_offset: u16_at(8),
+            message_data_size: u16_at(10),
+            message_instruction_index: u16_at(12),
+        },
+    }
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Signer;
+    use p256::ecdsa::signature::Signer as _;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn instruction(program_id: &str, data: Vec<u8>) -> DecodedInstruction {
+        DecodedInstruction {
+            program_id: program_id.parse().unwrap(),
+            data,
+            keys: vec![],
+        }
+    }
+
+    fn offsets(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_ed25519_with_message_in_another_instruction() {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let keypair = ed25519_dalek::Keypair { secret, public };
+        let message = b"price update".to_vec();
+        let signature = keypair.sign(&message);
+
+        // Instruction 0 is the oracle program carrying the message after a
+        // four byte prefix; instruction 1 is the precompile.
+        let mut oracle_data = vec![9, 9, 9, 9];
+        oracle_data.extend_from_slice(&message);
+        let oracle = DecodedInstruction {
+            program_id: Pubkey::new_unique(),
+            data: oracle_data,
+            keys: vec![],
+        };
+        let header = 2 + 14;
+        let mut data = vec![1, 0];
+        data.extend(offsets(&[
+            header as u16 + 32,
+            u16::MAX,
+            header as u16,
+            u16::MAX,
+            4,
+            message.len() as u16,
+            0,
+        ]));
+        data.extend_from_slice(public.as_bytes());
+        data.extend_from_slice(&signature.to_bytes());
+        let mut instructions = vec![oracle, instruction(ED25519_PROGRAM_ID, data)];
+
+        let decoded = decode_precompile(&instructions, 1).unwrap().unwrap();
+        assert_eq!(decoded.precompile, Precompile::Ed25519);
+        assert_eq!(decoded.signatures[0].message, message);
+        assert_eq!(decoded.signatures[0].public_key, public.as_bytes().to_vec());
+        assert!(decoded.is_valid());
+        assert_eq!(decode_precompile(&instructions, 0).unwrap(), None);
+
+        instructions[0].data[4] ^= 1;
+        assert!(!decode_precompile(&instructions, 1)
+            .unwrap()
+            .unwrap()
+            .is_valid());
+        instructions[0].data.truncate(6);
+        assert!(decode_precompile(&instructions, 1).is_err());
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_secp256k1_recovers_eth_address() {
+        let secret = libsecp256k1::SecretKey::parse(&[3; 32]).unwrap();
+        let public = libsecp256k1::PublicKey::from_secret_key(&secret);
+        let eth_address = keccak::hash(&public.serialize()[1..]).as_ref()[12..].to_vec();
+        let message = b"bridge transfer".to_vec();
+        let digest = keccak::hash(&message);
+        let (signature, recovery_id) = libsecp256k1::sign(
+            &libsecp256k1::Message::parse_slice(digest.as_ref()).unwrap(),
+            &secret,
+        );
+
+        let header = 1 + 11;
+        let mut data = vec![1];
+        data.extend_from_slice(&(header as u16 + 20).to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&(header as u16).to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&(header as u16 + 20 + 65).to_le_bytes());
+        data.extend_from_slice(&(message.len() as u16).to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&eth_address);
+        data.extend_from_slice(&signature.serialize());
+        data.push(recovery_id.serialize());
+        data.extend_from_slice(&message);
+        let instructions = vec![instruction(SECP256K1_PROGRAM_ID, data)];
+
+        let decoded = decode_precompile(&instructions, 0).unwrap().unwrap();
+        let parsed = &decoded.signatures[0];
+        assert_eq!(parsed.recovery_id, Some(recovery_id.serialize()));
+        assert_eq!(parsed.recover_eth_address(), Some(eth_address));
+        assert!(decoded.is_valid());
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_secp256r1_requires_low_s() {
+        let key = p256::ecdsa::SigningKey::from_slice(&[5; 32]).unwrap();
+        let public = key.verifying_key().to_encoded_point(true);
+        let message = b"passkey login".to_vec();
+        let signature: p256::ecdsa::Signature = key.sign(&message);
+        let signature = signature.normalize_s().unwrap_or(signature);
+
+        let build = |signature: &p256::ecdsa::Signature| {
+            let header = 2 + 14;
+            let mut data = vec![1, 0];
+            data.extend(offsets(&[
+                header + 33,
+                u16::MAX,
+                header,
+                u16::MAX,
+                header + 33 + 64,
+                message.len() as u16,
+                u16::MAX,
+            ]));
+            data.extend_from_slice(public.as_bytes());
+            data.extend_from_slice(&signature.to_bytes());
+            data.extend_from_slice(&message);
+            vec![instruction(SECP256R1_PROGRAM_ID, data)]
+        };
+
+        let decoded = decode_precompile(&build(&signature), 0).unwrap().unwrap();
+        assert_eq!(decoded.precompile, Precompile::Secp256r1);
+        assert_eq!(decoded.signatures[0].message, message);
+        assert_eq!(decoded.signatures[0].public_key, public.as_bytes().to_vec());
+        assert!(decoded.is_valid());
+
+        // The same signature with S negated verifies mathematically, but the
+        // runtime only takes the low-S form.
+        let (r, s) = signature.split_scalars();
+        let high_s = p256::ecdsa::Signature::from_scalars(r, -s).unwrap();
+        assert!(key.verifying_key().verify(&message, &high_s).is_ok());
+        let decoded = decode_precompile(&build(&high_s), 0).unwrap().unwrap();
+        assert!(!decoded.is_valid());
+
+        let mut instructions = build(&signature);
+        let last = instructions[0].data.len() - 1;
+        instructions[0].data[last] ^= 1;
+        assert!(!decode_precompile(&instructions, 0)
+            .unwrap()
+            .unwrap()
+            .is_valid());
+    }
+}
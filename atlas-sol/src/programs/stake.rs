@@ -0,0 +1,156 @@
+use super::{deserialize, variant_name, Accounts, AuthorityKind};
+use crate::transaction::DecodedInstruction;
+use atlas_core::error::AtlasResult;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::stake::instruction::StakeInstruction as Wire;
+use solana_sdk::stake::state::StakeAuthorize;
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StakeInstruction {
+    Initialize {
+        stake: Pubkey,
+        staker: Pubkey,
+        withdrawer: Pubkey,
+        custodian: Pubkey,
+    },
+    Authorize {
+        stake: Pubkey,
+        authority: Pubkey,
+        new_authority: Pubkey,
+        kind: AuthorityKind,
+    },
+    Delegate {
+        stake: Pubkey,
+        vote_account: Pubkey,
+        authority: Pubkey,
+    },
+    Split {
+        stake: Pubkey,
+        to: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    Withdraw {
+        stake: Pubkey,
+        to: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    Deactivate {
+        stake: Pubkey,
+        authority: Pubkey,
+    },
+    DeactivateDelinquent {
+        stake: Pubkey,
+        vote_account: Pubkey,
+    },
+    /// `source` is merged into `destination` and closed.
+    Merge {
+        destination: Pubkey,
+        source: Pubkey,
+        authority: Pubkey,
+    },
+    MoveStake {
+        from: Pubkey,
+        to: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    MoveLamports {
+        from: Pubkey,
+        to: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    /// Lockup and seed variants, and the read-only queries.
+    Other {
+        name: String,
+    },
+}
+
+//=======================================================================
+fn kind(authorize: StakeAuthorize) -> AuthorityKind {
+    match authorize {
+        StakeAuthorize::Staker => AuthorityKind::Staker,
+        StakeAuthorize::Withdrawer => AuthorityKind::Withdrawer,
+    }
+}
+
+//=======================================================================
+pub(crate) fn decode(instruction: &DecodedInstruction) -> AtlasResult<StakeInstruction> {
+    const PROGRAM: &str = "stake instruction";
+    let keys = Accounts::new(PROGRAM, instruction);
+    // Clock, rent and stake history sysvars are skipped by position.
+    Ok(match deserialize(PROGRAM, &instruction.data)? {
+        Wire::Initialize(authorized, lockup) => StakeInstruction::Initialize {
+            stake: keys.get(0)?,
+            staker: authorized.staker,
+            withdrawer: authorized.withdrawer,
+            custodian: lockup.custodian,
+        },
+        Wire::InitializeChecked => StakeInstruction::Initialize {
+            stake: keys.get(0)?,
+            staker: keys.get(2)?,
+            withdrawer: keys.get(3)?,
+            custodian: Pubkey::default(),
+        },
+        Wire::Authorize(new_authority, authorize) => StakeInstruction::Authorize {
+            stake: keys.get(0)?,
+            authority: keys.get(2)?,
+            new_authority,
+            kind: kind(authorize),
+        },
+        Wire::AuthorizeChecked(authorize) => StakeInstruction::Authorize {
+            stake: keys.get(0)?,
+            authority: keys.get(2)?,
+            new_authority: keys.get(3)?,
+            kind: kind(authorize),
+        },
+        Wire::DelegateStake => StakeInstruction::Delegate {
+            stake: keys.get(0)?,
+            vote_account: keys.get(1)?,
+            authority: keys.get(5)?,
+        },
+        Wire::Split(lamports) => StakeInstruction::Split {
+            stake: keys.get(0)?,
+            to: keys.get(1)?,
+            authority: keys.get(2)?,
+            lamports,
+        },
+        Wire::Withdraw(lamports) => StakeInstruction::Withdraw {
+            stake: keys.get(0)?,
+            to: keys.get(1)?,
+            authority: keys.get(4)?,
+            lamports,
+        },
+        Wire::Deactivate => StakeInstruction::Deactivate {
+            stake: keys.get(0)?,
+            authority: keys.get(2)?,
+        },
+        Wire::DeactivateDelinquent => StakeInstruction::DeactivateDelinquent {
+            stake: keys.get(0)?,
+            vote_account: keys.get(1)?,
+        },
+        Wire::Merge => StakeInstruction::Merge {
+            destination: keys.get(0)?,
+            source: keys.get(1)?,
+            authority: keys.get(4)?,
+        },
+        Wire::MoveStake(lamports) => StakeInstruction::MoveStake {
+            from: keys.get(0)?,
+            to: keys.get(1)?,
+            authority: keys.get(2)?,
+            lamports,
+        },
+        Wire::MoveLamports(lamports) => StakeInstruction::MoveLamports {
+            from: keys.get(0)?,
+            to: keys.get(1)?,
+            authority: keys.get(2)?,
+            lamports,
+        },
+        other => StakeInstruction::Other {
+            name: variant_name(&other),
+        },
+    })
+}
@@ -0,0 +1,210 @@
+use super::{deserialize, Accounts};
+use crate::transaction::DecodedInstruction;
+use atlas_core::error::AtlasResult;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction as Wire;
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemInstruction {
+    CreateAccount {
+        from: Pubkey,
+        to: Pubkey,
+        lamports: u64,
+        space: u64,
+        owner: Pubkey,
+    },
+    CreateAccountWithSeed {
+        from: Pubkey,
+        to: Pubkey,
+        base: Pubkey,
+        seed: String,
+        lamports: u64,
+        space: u64,
+        owner: Pubkey,
+    },
+    Assign {
+        account: Pubkey,
+        owner: Pubkey,
+    },
+    AssignWithSeed {
+        account: Pubkey,
+        base: Pubkey,
+        seed: String,
+        owner: Pubkey,
+    },
+    Transfer {
+        from: Pubkey,
+        to: Pubkey,
+        lamports: u64,
+    },
+    /// `from` is derived from `base`, `from_seed` and `from_owner`.
+    TransferWithSeed {
+        from: Pubkey,
+        base: Pubkey,
+        to: Pubkey,
+        lamports: u64,
+        from_seed: String,
+        from_owner: Pubkey,
+    },
+    Allocate {
+        account: Pubkey,
+        space: u64,
+    },
+    AllocateWithSeed {
+        account: Pubkey,
+        base: Pubkey,
+        seed: String,
+        space: u64,
+        owner: Pubkey,
+    },
+    InitializeNonceAccount {
+        nonce: Pubkey,
+        authority: Pubkey,
+    },
+    AdvanceNonceAccount {
+        nonce: Pubkey,
+        authority: Pubkey,
+    },
+    WithdrawNonceAccount {
+        nonce: Pubkey,
+        to: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    AuthorizeNonceAccount {
+        nonce: Pubkey,
+        authority: Pubkey,
+        new_authority: Pubkey,
+    },
+    UpgradeNonceAccount {
+        nonce: Pubkey,
+    },
+}
+
+//=======================================================================
+impl SystemInstruction {
+    /// Lamports moved from one account to another, if that is what this
+    /// instruction does.
+    pub fn lamports_moved(&self) -> Option<(Pubkey, Pubkey, u64)> {
+        match self {
+            SystemInstruction::CreateAccount {
+                from, to, lamports, ..
+            }
+            | SystemInstruction::CreateAccountWithSeed {
+                from, to, lamports, ..
+            }
+            | SystemInstruction::Transfer { from, to, lamports }
+            | SystemInstruction::TransferWithSeed {
+                from, to, lamports, ..
+            } => Some((*from, *to, *lamports)),
+            SystemInstruction::WithdrawNonceAccount {
+                nonce,
+                to,
+                lamports,
+                ..
+            } => Some((*nonce, *to, *lamports)),
+            _ => None,
+        }
+    }
+}
+
+//=======================================================================
+pub(crate) fn decode(instruction: &DecodedInstruction) -> AtlasResult<SystemInstruction> {
+    const PROGRAM: &str = "system instruction";
+    let keys = Accounts::new(PROGRAM, instruction);
+    Ok(match deserialize(PROGRAM, &instruction.data)? {
+        Wire::CreateAccount {
+            lamports,
+            space,
+            owner,
+        } => SystemInstruction::CreateAccount {
+            from: keys.get(0)?,
+            to: keys.get(1)?,
+            lamports,
+            space,
+            owner,
+        },
+        Wire::CreateAccountWithSeed {
+            base,
+            seed,
+            lamports,
+            space,
+            owner,
+        } => SystemInstruction::CreateAccountWithSeed {
+            from: keys.get(0)?,
+            to: keys.get(1)?,
+            base,
+            seed,
+            lamports,
+            space,
+            owner,
+        },
+        Wire::Assign { owner } => SystemInstruction::Assign {
+            account: keys.get(0)?,
+            owner,
+        },
+        Wire::AssignWithSeed { base, seed, owner } => SystemInstruction::AssignWithSeed {
+            account: keys.get(0)?,
+            base,
+            seed,
+            owner,
+        },
+        Wire::Transfer { lamports } => SystemInstruction::Transfer {
+            from: keys.get(0)?,
+            to: keys.get(1)?,
+            lamports,
+        },
+        Wire::TransferWithSeed {
+            lamports,
+            from_seed,
+            from_owner,
+        } => SystemInstruction::TransferWithSeed {
+            from: keys.get(0)?,
+            base: keys.get(1)?,
+            to: keys.get(2)?,
+            lamports,
+            from_seed,
+            from_owner,
+        },
+        Wire::Allocate { space } => SystemInstruction::Allocate {
+            account: keys.get(0)?,
+            space,
+        },
+        Wire::AllocateWithSeed {
+            base,
+            seed,
+            space,
+            owner,
+        } => SystemInstruction::AllocateWithSeed {
+            account: keys.get(0)?,
+            base,
+            seed,
+            space,
+            owner,
+        },
+        Wire::InitializeNonceAccount(authority) => SystemInstruction::InitializeNonceAccount {
+            nonce: keys.get(0)?,
+            authority,
+        },
+        // Accounts 1 (and 2 for withdraw) are sysvars.
+        Wire::AdvanceNonceAccount => SystemInstruction::AdvanceNonceAccount {
+            nonce: keys.get(0)?,
+            authority: keys.get(2)?,
+        },
+        Wire::WithdrawNonceAccount(lamports) => SystemInstruction::WithdrawNonceAccount {
+            nonce: keys.get(0)?,
+            to: keys.get(1)?,
+            authority: keys.get(4)?,
+            lamports,
+        },
+        Wire::AuthorizeNonceAccount(new_authority) => SystemInstruction::AuthorizeNonceAccount {
+            nonce: keys.get(0)?,
+            authority: keys.get(1)?,
+            new_authority,
+        },
+        Wire::UpgradeNonceAccount => SystemInstruction::UpgradeNonceAccount {
+            nonce: keys.get(0)?,
+        },
+    })
+}
@@ -0,0 +1,707 @@
+use super::token_account::{TokenAccountData, TransferFee};
+use super::{variant_name, Accounts};
+use crate::transaction::{DecodedInstruction, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use atlas_core::decimal::TokenAmount;
+use atlas_core::error::{AtlasError, AtlasResult};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status_client_types::{UiTransactionStatusMeta, UiTransactionTokenBalance};
+use spl_token_2022::extension::transfer_fee::instruction::TransferFeeInstruction;
+use spl_token_2022::instruction::TokenInstruction as Wire;
+use std::collections::HashMap;
+
+const PROGRAM: &str = "token instruction";
+
+//=======================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenProgram {
+    Token,
+    Token2022,
+}
+
+//=======================================================================
+/// SPL Token and Token-2022 instructions. The two programs share the base
+/// instruction set; Token-2022 extension instructions other than transfers
+/// with fee are reported as `Other`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenInstruction {
+    InitializeMint {
+        mint: Pubkey,
+        decimals: u8,
+        mint_authority: Pubkey,
+        freeze_authority: Option<Pubkey>,
+    },
+    /// `InitializeAccount`, `InitializeAccount2` and `InitializeAccount3`.
+    InitializeAccount {
+        account: Pubkey,
+        mint: Pubkey,
+        owner: Pubkey,
+    },
+    InitializeMultisig {
+        multisig: Pubkey,
+        signers: Vec<Pubkey>,
+        m: u8,
+    },
+    InitializeImmutableOwner {
+        account: Pubkey,
+    },
+    Transfer {
+        source: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        amount: u64,
+    },
+    TransferChecked {
+        source: Pubkey,
+        mint: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        amount: TokenAmount,
+        /// Withheld in the destination, for `TransferCheckedWithFee`.
+        fee: Option<u64>,
+    },
+    Approve {
+        source: Pubkey,
+        delegate: Pubkey,
+        owner: Pubkey,
+        amount: u64,
+        /// Set by `ApproveChecked`.
+        mint: Option<Pubkey>,
+        decimals: Option<u8>,
+    },
+    Revoke {
+        source: Pubkey,
+        owner: Pubkey,
+    },
+    SetAuthority {
+        account: Pubkey,
+        authority: Pubkey,
+        new_authority: Option<Pubkey>,
+        authority_type: String,
+    },
+    MintTo {
+        mint: Pubkey,
+        account: Pubkey,
+        authority: Pubkey,
+        amount: u64,
+        /// Set by `MintToChecked`.
+        decimals: Option<u8>,
+    },
+    Burn {
+        account: Pubkey,
+        mint: Pubkey,
+        authority: Pubkey,
+        amount: u64,
+        /// Set by `BurnChecked`.
+        decimals: Option<u8>,
+    },
+    CloseAccount {
+        account: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+    },
+    FreezeAccount {
+        account: Pubkey,
+        mint: Pubkey,
+        authority: Pubkey,
+    },
+    ThawAccount {
+        account: Pubkey,
+        mint: Pubkey,
+        authority: Pubkey,
+    },
+    /// Sets a wrapped SOL account's balance to its lamports above rent.
+    SyncNative {
+        account: Pubkey,
+    },
+    Other {
+        name: String,
+    },
+}
+
+//=======================================================================
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementKind {
+    Transfer,
+    Mint,
+    Burn,
+}
+
+//=======================================================================
+/// Tokens moving between accounts, created or destroyed, whichever
+/// instruction did it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenMovement {
+    pub kind: MovementKind,
+    pub mint: Pubkey,
+    /// `None` for mints.
+    pub source: Option<Pubkey>,
+    /// `None` for burns.
+    pub destination: Option<Pubkey>,
+    pub authority: Pubkey,
+    pub amount: TokenAmount,
+    /// Transfer fee withheld from `amount`, when the mint charges one.
+    pub fee: Option<u64>,
+}
+
+//=======================================================================
+/// Movements made by a run of instructions. Transfers, mints and burns
+/// whose mint or decimals cannot be worked out, and token instructions
+/// that do not decode, are left out and listed in `unresolved` by their
+/// index into the instructions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenMovements {
+    pub movements: Vec<TokenMovement>,
+    pub unresolved: Vec<usize>,
+}
+
+//=======================================================================
+/// What is known about token accounts and mints, to fill in the mint and
+/// decimals that unchecked instructions leave out.
+#[derive(Debug, Clone, Default)]
+pub struct TokenMints {
+    accounts: HashMap<Pubkey, Pubkey>,
+    decimals: HashMap<Pubkey, u8>,
+    fees: HashMap<Pubkey, TransferFee>,
+}
+
+//=======================================================================
+impl TokenMints {
+    //=======================================================================
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //=======================================================================
+    /// Seeded with every token account in the meta's pre and post token
+    /// balances, and the decimals of their mints. `keys` is the full
+    /// account list, loaded addresses included.
+    pub fn from_meta(meta: &UiTransactionStatusMeta, keys: &[Pubkey]) -> AtlasResult<Self> {
+        let mut mints = Self::new();
+        for balances in [&meta.pre_token_balances, &meta.post_token_balances] {
+            let balances: &[UiTransactionTokenBalance] =
+                Option::<&Vec<_>>::from(balances.as_ref()).map_or(&[], |v| v);
+            for balance in balances {
+                let index = balance.account_index as usize;
+                let account = *keys.get(index).ok_or_else(|| {
+                    AtlasError::decode(
+                        "token balance",
+                        format!("account #{} is out of range of {} keys", index, keys.len()),
+                    )
+                })?;
+                let mint = balance.mint.parse().map_err(|e| {
+                    AtlasError::decode("token balance", format!("{}: {}", balance.mint, e))
+                })?;
+                mints.insert_account(account, mint);
+                mints.insert_mint(mint, balance.ui_token_amount.decimals);
+            }
+        }
+        Ok(mints)
+    }
+
+    //=======================================================================
+    pub fn insert_account(&mut self, account: Pubkey, mint: Pubkey) {
+        self.accounts.insert(account, mint);
+    }
+
+    //=======================================================================
+    pub fn insert_mint(&mut self, mint: Pubkey, decimals: u8) {
+        self.decimals.insert(mint, decimals);
+    }
+
+    //=======================================================================
+    /// Learn from decoded account data, including the mint's current
+    /// transfer fee.
+    pub fn insert_account_data(&mut self, address: Pubkey, data: &TokenAccountData, epoch: u64) {
+        match data {
+            TokenAccountData::Account(account) => self.insert_account(address, account.mint),
+            TokenAccountData::Mint(mint) => {
+                self.insert_mint(address, mint.decimals);
+                if let Some(fee) = mint.transfer_fee(epoch) {
+                    self.fees.insert(address, fee);
+                }
+            }
+        }
+    }
+
+    //=======================================================================
+    /// Learn from an instruction earlier in the same transaction.
+    pub fn learn(&mut self, instruction: &TokenInstruction) {
+        match instruction {
+            TokenInstruction::InitializeMint { mint, decimals, .. } => {
+                self.insert_mint(*mint, *decimals)
+            }
+            TokenInstruction::InitializeAccount { account, mint, .. } => {
+                self.insert_account(*account, *mint)
+            }
+            TokenInstruction::TransferChecked {
+                source,
+                mint,
+                destination,
+                amount,
+                ..
+            } => {
+                self.insert_account(*source, *mint);
+                self.insert_account(*destination, *mint);
+                self.insert_mint(*mint, amount.decimals);
+            }
+            TokenInstruction::MintTo {
+                mint,
+                account,
+                decimals,
+                ..
+            }
+            | TokenInstruction::Burn {
+                mint,
+                account,
+                decimals,
+                ..
+            } => {
+                self.insert_account(*account, *mint);
+                if let Some(decimals) = decimals {
+                    self.insert_mint(*mint, *decimals);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    //=======================================================================
+    pub fn mint_of(&self, account: &Pubkey) -> Option<Pubkey> {
+        self.accounts.get(account).copied()
+    }
+
+    //=======================================================================
+    pub fn decimals_of(&self, mint: &Pubkey) -> Option<u8> {
+        self.decimals.get(mint).copied()
+    }
+
+    //=======================================================================
+    fn require_mint(&self, account: &Pubkey) -> AtlasResult<Pubkey> {
+        self.mint_of(account).ok_or_else(|| {
+            AtlasError::decode(
+                PROGRAM,
+                format!("mint of token account {} is unknown", account),
+            )
+        })
+    }
+
+    //=======================================================================
+    fn require_decimals(&self, mint: &Pubkey) -> AtlasResult<u8> {
+        self.decimals_of(mint).ok_or_else(|| {
+            AtlasError::decode(PROGRAM, format!("decimals of mint {} are unknown", mint))
+        })
+    }
+}
+
+//=======================================================================
+impl TokenInstruction {
+    //=======================================================================
+    /// The movement this instruction makes, with mint and decimals filled in
+    /// from `mints` where the instruction does not carry them. Fails when
+    /// they are needed but unknown.
+    pub fn movement(&self, mints: &TokenMints) -> AtlasResult<Option<TokenMovement>> {
+        let movement = match self {
+            TokenInstruction::Transfer {
+                source,
+                destination,
+                authority,
+                amount,
+            } => {
+                let mint = mints
+                    .require_mint(source)
+                    .or_else(|_| mints.require_mint(destination))?;
+                let decimals = mints.require_decimals(&mint)?;
+                let amount = TokenAmount::new(*amount, decimals);
+                TokenMovement {
+                    kind: MovementKind::Transfer,
+                    mint,
+                    source: Some(*source),
+                    destination: Some(*destination),
+                    authority: *authority,
+                    amount,
+                    fee: mints.fees.get(&mint).and_then(|fee| fee.fee(amount.amount)),
+                }
+            }
+            TokenInstruction::TransferChecked {
+                source,
+                mint,
+                destination,
+                authority,
+                amount,
+                fee,
+            } => TokenMovement {
+                kind: MovementKind::Transfer,
+                mint: *mint,
+                source: Some(*source),
+                destination: Some(*destination),
+                authority: *authority,
+                amount: *amount,
+                fee: fee.or_else(|| mints.fees.get(mint).and_then(|f| f.fee(amount.amount))),
+            },
+            TokenInstruction::MintTo {
+                mint,
+                account,
+                authority,
+                amount,
+                decimals,
+            } => TokenMovement {
+                kind: MovementKind::Mint,
+                mint: *mint,
+                source: None,
+                destination: Some(*account),
+                authority: *authority,
+                amount: TokenAmount::new(*amount, resolve(*decimals, mints, mint)?),
+                fee: None,
+            },
+            TokenInstruction::Burn {
+                account,
+                mint,
+                authority,
+                amount,
+                decimals,
+            } => TokenMovement {
+                kind: MovementKind::Burn,
+                mint: *mint,
+                source: Some(*account),
+                destination: None,
+                authority: *authority,
+                amount: TokenAmount::new(*amount, resolve(*decimals, mints, mint)?),
+                fee: None,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(movement))
+    }
+}
+
+//=======================================================================
+fn resolve(decimals: Option<u8>, mints: &TokenMints, mint: &Pubkey) -> AtlasResult<u8> {
+    decimals.map_or_else(|| mints.require_decimals(mint), Ok)
+}
+
+//=======================================================================
+impl DecodedInstruction {
+    //=======================================================================
+    /// Typed form of an SPL Token or Token-2022 instruction; `None` for any
+    /// other program.
+    pub fn token(&self) -> AtlasResult<Option<(TokenProgram, TokenInstruction)>> {
+        let program = match self.program_id.to_string().as_str() {
+            id if id == TOKEN_PROGRAM_ID => TokenProgram::Token,
+            id if id == TOKEN_2022_PROGRAM_ID => TokenProgram::Token2022,
+            _ => return Ok(None),
+        };
+        Ok(Some((program, decode(self)?)))
+    }
+
+    //=======================================================================
+    /// Token movements made by `instructions`, in order. Each instruction
+    /// also teaches `mints` about the accounts it sets up. Seed `mints`
+    /// with `TokenMints::from_meta` so transfers between accounts set up
+    /// before the transaction resolve too.
+    pub fn token_movements(
+        instructions: &[DecodedInstruction],
+        mints: &mut TokenMints,
+    ) -> AtlasResult<TokenMovements> {
+        let mut found = TokenMovements::default();
+        for (index, instruction) in instructions.iter().enumerate() {
+            let token = match instruction.token() {
+                Ok(Some((_, token))) => token,
+                Ok(None) => continue,
+                Err(_) => {
+                    found.unresolved.push(index);
+                    continue;
+                }
+            };
+            mints.learn(&token);
+            match token.movement(mints) {
+                Ok(movement) => found.movements.extend(movement),
+                Err(_) => found.unresolved.push(index),
+            }
+        }
+        Ok(found)
+    }
+}
+
+//=======================================================================
+#[allow(deprecated)]
+pub(crate) fn decode(instruction: &DecodedInstruction) -> AtlasResult<TokenInstruction> {
+    let keys = Accounts::new(PROGRAM, instruction);
+    let wire = Wire::unpack(&instruction.data).map_err(|e| AtlasError::decode(PROGRAM, e))?;
+    Ok(match wire {
+        Wire::InitializeMint {
+            decimals,
+            mint_authority,
+            freeze_authority,
+        }
+        | Wire::InitializeMint2 {
+            decimals,
+            mint_authority,
+            freeze_authority,
+        } => TokenInstruction::InitializeMint {
+            mint: keys.get(0)?,
+            decimals,
+            mint_authority,
+            freeze_authority: freeze_authority.into(),
+        },
+        Wire::InitializeAccount => TokenInstruction::InitializeAccount {
+            account: keys.get(0)?,
+            mint: keys.get(1)?,
+            owner: keys.get(2)?,
+        },
+        Wire::InitializeAccount2 { owner } | Wire::InitializeAccount3 { owner } => {
+            TokenInstruction::InitializeAccount {
+                account: keys.get(0)?,
+                mint: keys.get(1)?,
+                owner,
+            }
+        }
+        // The first form also passes the rent sysvar before the signers.
+        Wire::InitializeMultisig { m } => TokenInstruction::InitializeMultisig {
+            multisig: keys.get(0)?,
+            signers: instruction.keys.iter().skip(2).map(|k| k.pubkey).collect(),
+            m,
+        },
+        Wire::InitializeMultisig2 { m } => TokenInstruction::InitializeMultisig {
+            multisig: keys.get(0)?,
+            signers: instruction.keys.iter().skip(1).map(|k| k.pubkey).collect(),
+            m,
+        },
+        Wire::InitializeImmutableOwner => TokenInstruction::InitializeImmutableOwner {
+            account: keys.get(0)?,
+        },
+        Wire::Transfer { amount } => TokenInstruction::Transfer {
+            source: keys.get(0)?,
+            destination: keys.get(1)?,
+            authority: keys.get(2)?,
+            amount,
+        },
+        Wire::TransferChecked { amount, decimals } => TokenInstruction::TransferChecked {
+            source: keys.get(0)?,
+            mint: keys.get(1)?,
+            destination: keys.get(2)?,
+            authority: keys.get(3)?,
+            amount: TokenAmount::new(amount, decimals),
+            fee: None,
+        },
+        Wire::TransferFeeExtension => {
+            match TransferFeeInstruction::unpack(&instruction.data[1..])
+                .map_err(|e| AtlasError::decode(PROGRAM, e))?
+            {
+                TransferFeeInstruction::TransferCheckedWithFee {
+                    amount,
+                    decimals,
+                    fee,
+                } => TokenInstruction::TransferChecked {
+                    source: keys.get(0)?,
+                    mint: keys.get(1)?,
+                    destination: keys.get(2)?,
+                    authority: keys.get(3)?,
+                    amount: TokenAmount::new(amount, decimals),
+                    fee: Some(fee),
+                },
+                other => TokenInstruction::Other {
+                    name: variant_name(&other),
+                },
+            }
+        }
+        Wire::Approve { amount } => TokenInstruction::Approve {
+            source: keys.get(0)?,
+            delegate: keys.get(1)?,
+            owner: keys.get(2)?,
+            amount,
+            mint: None,
+            decimals: None,
+        },
+        Wire::ApproveChecked { amount, decimals } => TokenInstruction::Approve {
+            source: keys.get(0)?,
+            delegate: keys.get(2)?,
+            owner: keys.get(3)?,
+            amount,
+            mint: Some(keys.get(1)?),
+            decimals: Some(decimals),
+        },
+        Wire::Revoke => TokenInstruction::Revoke {
+            source: keys.get(0)?,
+            owner: keys.get(1)?,
+        },
+        Wire::SetAuthority {
+            authority_type,
+            new_authority,
+        } => TokenInstruction::SetAuthority {
+            account: keys.get(0)?,
+            authority: keys.get(1)?,
+            new_authority: new_authority.into(),
+            authority_type: format!("{:?}", authority_type),
+        },
+        Wire::MintTo { amount } => TokenInstruction::MintTo {
+            mint: keys.get(0)?,
+            account: keys.get(1)?,
+            authority: keys.get(2)?,
+            amount,
+            decimals: None,
+        },
+        Wire::MintToChecked { amount, decimals } => TokenInstruction::MintTo {
+            mint: keys.get(0)?,
+            account: keys.get(1)?,
+            authority: keys.get(2)?,
+            amount,
+            decimals: Some(decimals),
+        },
+        Wire::Burn { amount } => TokenInstruction::Burn {
+            account: keys.get(0)?,
+            mint: keys.get(1)?,
+            authority: keys.get(2)?,
+            amount,
+            decimals: None,
+        },
+        Wire::BurnChecked { amount, decimals } => TokenInstruction::Burn {
+            account: keys.get(0)?,
+            mint: keys.get(1)?,
+            authority: keys.get(2)?,
+            amount,
+            decimals: Some(decimals),
+        },
+        Wire::CloseAccount => TokenInstruction::CloseAccount {
+            account: keys.get(0)?,
+            destination: keys.get(1)?,
+            authority: keys.get(2)?,
+        },
+        Wire::FreezeAccount => TokenInstruction::FreezeAccount {
+            account: keys.get(0)?,
+            mint: keys.get(1)?,
+            authority: keys.get(2)?,
+        },
+        Wire::ThawAccount => TokenInstruction::ThawAccount {
+            account: keys.get(0)?,
+            mint: keys.get(1)?,
+            authority: keys.get(2)?,
+        },
+        Wire::SyncNative => TokenInstruction::SyncNative {
+            account: keys.get(0)?,
+        },
+        other => TokenInstruction::Other {
+            name: variant_name(&other),
+        },
+    })
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::confirmed_account_keys;
+    use solana_sdk::instruction::Instruction;
+    use solana_transaction_status_client_types::EncodedTransactionWithStatusMeta;
+    use spl_token_2022::instruction as ix;
+
+    //=======================================================================
+    #[test]
+    #[allow(deprecated)]
+    fn test_token_movements() {
+        let token_program: Pubkey = TOKEN_PROGRAM_ID.parse().unwrap();
+        let token_2022: Pubkey = TOKEN_2022_PROGRAM_ID.parse().unwrap();
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+
+        let instructions: Vec<DecodedInstruction> = vec![
+            ix::initialize_account3(&token_program, &source, &mint, &owner).unwrap(),
+            ix::mint_to_checked(&token_program, &mint, &source, &owner, &[], 5_000_000, 6).unwrap(),
+            ix::transfer(
+                &token_program,
+                &source,
+                &destination,
+                &owner,
+                &[],
+                1_500_000,
+            )
+            .unwrap(),
+            ix::burn(&token_program, &source, &mint, &owner, &[], 500_000).unwrap(),
+            ix::sync_native(&token_2022, &source).unwrap(),
+        ]
+        .into_iter()
+        .map(DecodedInstruction::from)
+        .collect();
+
+        assert_eq!(
+            instructions[4].token().unwrap(),
+            Some((
+                TokenProgram::Token2022,
+                TokenInstruction::SyncNative { account: source }
+            ))
+        );
+
+        let mut mints = TokenMints::new();
+        let found = DecodedInstruction::token_movements(&instructions, &mut mints).unwrap();
+        assert!(found.unresolved.is_empty());
+        let movements = found.movements;
+        assert_eq!(movements.len(), 3);
+        assert_eq!(movements[0].kind, MovementKind::Mint);
+        assert_eq!(
+            movements[1],
+            TokenMovement {
+                kind: MovementKind::Transfer,
+                mint,
+                source: Some(source),
+                destination: Some(destination),
+                authority: owner,
+                amount: TokenAmount::new(1_500_000, 6),
+                fee: None,
+            }
+        );
+        assert_eq!(movements[2].amount.ui_amount_string(), "0.5");
+
+        // Without the earlier instructions the transfer's mint is unknown,
+        // and so are the decimals the unchecked burn needs.
+        let found = DecodedInstruction::token_movements(&instructions[2..], &mut TokenMints::new())
+            .unwrap();
+        assert_eq!(found.unresolved, vec![0, 1]);
+        assert!(found.movements.is_empty());
+
+        // Knowing the mint's decimals resolves the burn; an instruction
+        // that does not decode is listed and the rest still come through.
+        let mut instructions = instructions[2..].to_vec();
+        instructions.insert(
+            0,
+            DecodedInstruction::from(Instruction::new_with_bytes(token_program, &[255], vec![])),
+        );
+        let mut mints = TokenMints::new();
+        mints.insert_mint(mint, 6);
+        let found = DecodedInstruction::token_movements(&instructions, &mut mints).unwrap();
+        assert_eq!(found.unresolved, vec![0, 1]);
+        assert_eq!(found.movements.len(), 1);
+        assert_eq!(found.movements[0].kind, MovementKind::Burn);
+        assert_eq!(found.movements[0].amount.ui_amount_string(), "0.5");
+    }
+
+    //=======================================================================
+    #[test]
+    #[allow(deprecated)]
+    fn test_mints_from_meta() {
+        let fixture = include_str!("../../test/fixtures/transfer.json");
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_str(fixture).unwrap();
+        let (_, keys, meta) = confirmed_account_keys(&tx).unwrap();
+        let mints = TokenMints::from_meta(meta, &keys).unwrap();
+        let jup: Pubkey = "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN"
+            .parse()
+            .unwrap();
+        for account in [keys[1], keys[2]] {
+            assert_eq!(mints.mint_of(&account), Some(jup));
+        }
+        assert_eq!(mints.decimals_of(&jup), Some(6));
+        assert_eq!(mints.mint_of(&keys[0]), None);
+
+        // A transfer between them resolves without any setup instruction.
+        let token_program: Pubkey = TOKEN_PROGRAM_ID.parse().unwrap();
+        let transfer = ix::transfer(&token_program, &keys[1], &keys[2], &keys[0], &[], 7).unwrap();
+        let found = DecodedInstruction::token_movements(
+            &[DecodedInstruction::from(transfer)],
+            &mut mints.clone(),
+        )
+        .unwrap();
+        assert_eq!(found.movements[0].amount, TokenAmount::new(7, 6));
+
+        assert!(TokenMints::from_meta(meta, &keys[..2]).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use atlas_core::decimal::TokenAmount;
+use atlas_core::error::{AtlasError, AtlasResult};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_2022::extension::interest_bearing_mint::InterestBearingConfig;
+use spl_token_2022::extension::metadata_pointer::MetadataPointer;
+use spl_token_2022::extension::transfer_fee::{
+    TransferFee as WireFee, TransferFeeAmount, TransferFeeConfig,
+};
+use spl_token_2022::extension::{
+    BaseState, BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
+use spl_token_2022::state::{Account, AccountState, Mint};
+
+const WHAT: &str = "token account";
+
+//=======================================================================
+/// Data of an account owned by SPL Token or Token-2022.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenAccountData {
+    Account(TokenAccount),
+    Mint(MintAccount),
+}
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    /// Raw amount; the decimals live on the mint.
+    pub amount: u64,
+    pub delegate: Option<Pubkey>,
+    pub delegated_amount: u64,
+    pub frozen: bool,
+    /// Rent-exempt reserve, for wrapped SOL accounts.
+    pub native_reserve: Option<u64>,
+    pub close_authority: Option<Pubkey>,
+    pub extensions: Vec<TokenExtension>,
+}
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MintAccount {
+    pub mint_authority: Option<Pubkey>,
+    pub supply: TokenAmount,
+    pub decimals: u8,
+    pub freeze_authority: Option<Pubkey>,
+    pub extensions: Vec<TokenExtension>,
+}
+
+//=======================================================================
+/// Transfer fee in force from `epoch` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferFee {
+    pub epoch: u64,
+    pub maximum_fee: u64,
+    pub basis_points: u16,
+}
+
+//=======================================================================
+/// Token-2022 extensions we read. The rest are kept by name only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenExtension {
+    TransferFeeConfig {
+        authority: Option<Pubkey>,
+        withdraw_authority: Option<Pubkey>,
+        withheld_amount: u64,
+        older: TransferFee,
+        newer: TransferFee,
+    },
+    /// Fees withheld in a token account, waiting to be harvested.
+    TransferFeeAmount {
+        withheld_amount: u64,
+    },
+    InterestBearingConfig {
+        rate_authority: Option<Pubkey>,
+        initialization_timestamp: i64,
+        pre_update_average_rate: i16,
+        last_update_timestamp: i64,
+        current_rate: i16,
+    },
+    MetadataPointer {
+        authority: Option<Pubkey>,
+        metadata_address: Option<Pubkey>,
+    },
+    Other(String),
+}
+
+//=======================================================================
+impl TransferFee {
+    //=======================================================================
+    /// Fee charged on a transfer of `amount`, rounded up and capped at
+    /// `maximum_fee`. `None` on overflow.
+    pub fn fee(&self, amount: u64) -> Option<u64> {
+        if self.basis_points == 0 || amount == 0 {
+            return Some(0);
+        }
+        let fee = (amount as u128 * self.basis_points as u128).div_ceil(10_000);
+        u64::try_from(fee).ok().map(|fee| fee.min(self.maximum_fee))
+    }
+
+    //=======================================================================
+    fn from_wire(fee: &WireFee) -> Self {
+        TransferFee {
+            epoch: fee.epoch.into(),
+            maximum_fee: fee.maximum_fee.into(),
+            basis_points: fee.transfer_fee_basis_points.into(),
+        }
+    }
+}
+
+//=======================================================================
+impl MintAccount {
+    //=======================================================================
+    /// The transfer fee that applies at `epoch`, if the mint charges one.
+    pub fn transfer_fee(&self, epoch: u64) -> Option<TransferFee> {
+        self.extensions.iter().find_map(|ext| match ext {
+            TokenExtension::TransferFeeConfig { older, newer, .. } => {
+                Some(if epoch >= newer.epoch { *newer } else { *older })
+            }
+            _ => None,
+        })
+    }
+}
+
+//=======================================================================
+/// Decode token account or mint data. Works for both programs: a Token
+/// account is a Token-2022 account without extensions.
+pub fn decode_token_account(data: &[u8]) -> AtlasResult<TokenAccountData> {
+    if let Ok(state) = StateWithExtensions::<Account>::unpack(data) {
+        let base = &state.base;
+        return Ok(TokenAccountData::Account(TokenAccount {
+            mint: base.mint,
+            owner: base.owner,
+            amount: base.amount,
+            delegate: base.delegate.into(),
+            delegated_amount: base.delegated_amount,
+            frozen: base.state == AccountState::Frozen,
+            native_reserve: base.is_native.into(),
+            close_authority: base.close_authority.into(),
+            extensions: extensions(&state)?,
+        }));
+    }
+    let state = StateWithExtensions::<Mint>::unpack(data).map_err(|e| {
+        AtlasError::decode(
+            WHAT,
+            format!(
+                "{} bytes are neither a token account nor a mint: {}",
+                data.len(),
+                e
+            ),
+        )
+    })?;
+    let base = &state.base;
+    Ok(TokenAccountData::Mint(MintAccount {
+        mint_authority: base.mint_authority.into(),
+        supply: TokenAmount::new(base.supply, base.decimals),
+        decimals: base.decimals,
+        freeze_authority: base.freeze_authority.into(),
+        extensions: extensions(&state)?,
+    }))
+}
+
+//=======================================================================
+fn extensions<S: BaseState + Pack>(
+    state: &StateWithExtensions<S>,
+) -> AtlasResult<Vec<TokenExtension>> {
+    let types = state
+        .get_extension_types()
+        .map_err(|e| AtlasError::decode(WHAT, e))?;
+    types
+        .into_iter()
+        .map(|kind| {
+            let ext = match kind {
+                ExtensionType::TransferFeeConfig => {
+                    let c = state
+                        .get_extension::<TransferFeeConfig>()
+                        .map_err(|e| AtlasError::decode(WHAT, e))?;
+                    TokenExtension::TransferFeeConfig {
+                        authority: c.transfer_fee_config_authority.into(),
+                        withdraw_authority: c.withdraw_withheld_authority.into(),
+                        withheld_amount: c.withheld_amount.into(),
+                        older: TransferFee::from_wire(&c.older_transfer_fee),
+                        newer: TransferFee::from_wire(&c.newer_transfer_fee),
+                    }
+                }
+                ExtensionType::TransferFeeAmount => TokenExtension::TransferFeeAmount {
+                    withheld_amount: state
+                        .get_extension::<TransferFeeAmount>()
+                        .map_err(|e| AtlasError::decode(WHAT, e))?
+                        .withheld_amount
+                        .into(),
+                },
+                ExtensionType::InterestBearingConfig => {
+                    let c = state
+                        .get_extension::<InterestBearingConfig>()
+                        .map_err(|e| AtlasError::decode(WHAT, e))?;
+                    TokenExtension::InterestBearingConfig {
+                        rate_authority: c.rate_authority.into(),
+                        initialization_timestamp: c.initialization_timestamp.into(),
+                        pre_update_average_rate: c.pre_update_average_rate.into(),
+                        last_update_timestamp: c.last_update_timestamp.into(),
+                        current_rate: c.current_rate.into(),
+                    }
+                }
+                ExtensionType::MetadataPointer => {
+                    let c = state
+                        .get_extension::<MetadataPointer>()
+                        .map_err(|e| AtlasError::decode(WHAT, e))?;
+                    TokenExtension::MetadataPointer {
+                        authority: c.authority.into(),
+                        metadata_address: c.metadata_address.into(),
+                    }
+                }
+                other => TokenExtension::Other(format!("{:?}", other)),
+            };
+            Ok(ext)
+        })
+        .collect()
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::program_option::COption;
+    use spl_token_2022::extension::{BaseStateWithExtensionsMut, StateWithExtensionsMut};
+
+    //=======================================================================
+    #[test]
+    fn test_decode_mint_with_extensions() {
+        let authority = Pubkey::new_unique();
+        let metadata = Pubkey::new_unique();
+        let kinds = [
+            ExtensionType::TransferFeeConfig,
+            ExtensionType::MetadataPointer,
+            ExtensionType::MintCloseAuthority,
+        ];
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&kinds).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        state.base = Mint {
+            mint_authority: COption::Some(authority),
+            supply: 12_345_000,
+            decimals: 3,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        let fees = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        fees.transfer_fee_config_authority = Some(authority).try_into().unwrap();
+        fees.withheld_amount = 40u64.into();
+        fees.older_transfer_fee.transfer_fee_basis_points = 0u16.into();
+        fees.newer_transfer_fee.epoch = 500u64.into();
+        fees.newer_transfer_fee.maximum_fee = 1_000u64.into();
+        fees.newer_transfer_fee.transfer_fee_basis_points = 150u16.into();
+        let pointer = state.init_extension::<MetadataPointer>(true).unwrap();
+        pointer.metadata_address = Some(metadata).try_into().unwrap();
+        state
+            .init_extension::<spl_token_2022::extension::mint_close_authority::MintCloseAuthority>(
+                true,
+            )
+            .unwrap();
+
+        let TokenAccountData::Mint(mint) = decode_token_account(&data).unwrap() else {
+            panic!("expected a mint");
+        };
+        assert_eq!(mint.supply.ui_amount_string(), "12345");
+        assert_eq!(mint.mint_authority, Some(authority));
+        assert_eq!(
+            mint.extensions[1],
+            TokenExtension::MetadataPointer {
+                authority: None,
+                metadata_address: Some(metadata),
+            }
+        );
+        assert_eq!(
+            mint.extensions[2],
+            TokenExtension::Other("MintCloseAuthority".to_string())
+        );
+        assert_eq!(mint.transfer_fee(499).unwrap().fee(10_000), Some(0));
+        let fee = mint.transfer_fee(500).unwrap();
+        assert_eq!(fee.fee(10_001), Some(151));
+        assert_eq!(fee.fee(u64::MAX), Some(1_000));
+
+        // A plain SPL Token account.
+        let owner = Pubkey::new_unique();
+        let mut data = vec![0; Account::LEN];
+        Account {
+            mint: authority,
+            owner,
+            amount: 9,
+            state: AccountState::Frozen,
+            ..Account::default()
+        }
+        .pack_into_slice(&mut data);
+        let TokenAccountData::Account(account) = decode_token_account(&data).unwrap() else {
+            panic!("expected a token account");
+        };
+        assert_eq!(
+            (account.owner, account.amount, account.frozen),
+            (owner, 9, true)
+        );
+        assert!(account.extensions.is_empty());
+
+        assert!(decode_token_account(&[0; 100]).is_err());
+    }
+}
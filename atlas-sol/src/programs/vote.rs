@@ -0,0 +1,144 @@
+use super::{deserialize, variant_name, Accounts, AuthorityKind};
+use crate::transaction::DecodedInstruction;
+use atlas_core::error::AtlasResult;
+use solana_sdk::clock::Slot;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::vote::instruction::VoteInstruction as Wire;
+use solana_sdk::vote::state::{Lockout, VoteAuthorize};
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoteInstruction {
+    InitializeAccount {
+        vote_account: Pubkey,
+        node: Pubkey,
+        authorized_voter: Pubkey,
+        authorized_withdrawer: Pubkey,
+        commission: u8,
+    },
+    Authorize {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        new_authority: Pubkey,
+        kind: AuthorityKind,
+    },
+    /// The original vote format, a list of slots.
+    Vote {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        slots: Vec<Slot>,
+        hash: Hash,
+    },
+    /// Full or compact vote state update, superseded by `TowerSync`.
+    UpdateVoteState {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        root: Option<Slot>,
+        last_voted_slot: Option<Slot>,
+        hash: Hash,
+    },
+    TowerSync {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        root: Option<Slot>,
+        last_voted_slot: Option<Slot>,
+        hash: Hash,
+    },
+    Withdraw {
+        vote_account: Pubkey,
+        to: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    UpdateValidatorIdentity {
+        vote_account: Pubkey,
+        node: Pubkey,
+        authority: Pubkey,
+    },
+    UpdateCommission {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        commission: u8,
+    },
+    /// Seed-derived authorizations.
+    Other { name: String },
+}
+
+//=======================================================================
+fn kind(authorize: VoteAuthorize) -> AuthorityKind {
+    match authorize {
+        VoteAuthorize::Voter => AuthorityKind::Voter,
+        VoteAuthorize::Withdrawer => AuthorityKind::Withdrawer,
+    }
+}
+
+//=======================================================================
+pub(crate) fn decode(instruction: &DecodedInstruction) -> AtlasResult<VoteInstruction> {
+    const PROGRAM: &str = "vote instruction";
+    let keys = Accounts::new(PROGRAM, instruction);
+    Ok(match deserialize(PROGRAM, &instruction.data)? {
+        Wire::InitializeAccount(init) => VoteInstruction::InitializeAccount {
+            vote_account: keys.get(0)?,
+            node: init.node_pubkey,
+            authorized_voter: init.authorized_voter,
+            authorized_withdrawer: init.authorized_withdrawer,
+            commission: init.commission,
+        },
+        Wire::Authorize(new_authority, authorize) => VoteInstruction::Authorize {
+            vote_account: keys.get(0)?,
+            authority: keys.get(2)?,
+            new_authority,
+            kind: kind(authorize),
+        },
+        Wire::AuthorizeChecked(authorize) => VoteInstruction::Authorize {
+            vote_account: keys.get(0)?,
+            authority: keys.get(2)?,
+            new_authority: keys.get(3)?,
+            kind: kind(authorize),
+        },
+        // Slot hashes and clock sysvars sit between the account and voter.
+        Wire::Vote(vote) | Wire::VoteSwitch(vote, _) => VoteInstruction::Vote {
+            vote_account: keys.get(0)?,
+            authority: keys.get(3)?,
+            slots: vote.slots,
+            hash: vote.hash,
+        },
+        Wire::UpdateVoteState(update)
+        | Wire::UpdateVoteStateSwitch(update, _)
+        | Wire::CompactUpdateVoteState(update)
+        | Wire::CompactUpdateVoteStateSwitch(update, _) => VoteInstruction::UpdateVoteState {
+            vote_account: keys.get(0)?,
+            authority: keys.get(1)?,
+            root: update.root,
+            last_voted_slot: update.lockouts.back().map(Lockout::slot),
+            hash: update.hash,
+        },
+        Wire::TowerSync(sync) | Wire::TowerSyncSwitch(sync, _) => VoteInstruction::TowerSync {
+            vote_account: keys.get(0)?,
+            authority: keys.get(1)?,
+            root: sync.root,
+            last_voted_slot: sync.lockouts.back().map(Lockout::slot),
+            hash: sync.hash,
+        },
+        Wire::Withdraw(lamports) => VoteInstruction::Withdraw {
+            vote_account: keys.get(0)?,
+            to: keys.get(1)?,
+            authority: keys.get(2)?,
+            lamports,
+        },
+        Wire::UpdateValidatorIdentity => VoteInstruction::UpdateValidatorIdentity {
+            vote_account: keys.get(0)?,
+            node: keys.get(1)?,
+            authority: keys.get(2)?,
+        },
+        Wire::UpdateCommission(commission) => VoteInstruction::UpdateCommission {
+            vote_account: keys.get(0)?,
+            authority: keys.get(1)?,
+            commission,
+        },
+        other => VoteInstruction::Other {
+            name: variant_name(&other),
+        },
+    })
+}
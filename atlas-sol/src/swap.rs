@@ -0,0 +1,541 @@
+use crate::balance::SolBalances;
+use crate::transaction::{confirmed_account_keys, decode_confirmed_transaction, NATIVE_MINT_ID};
+use atlas_core::decimal::{Decimal, TokenAmount};
+use atlas_core::error::{AtlasError, AtlasResult};
+use log::{debug, warn};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedBlock, EncodedTransactionWithStatusMeta, UiTransactionStatusMeta,
+    UiTransactionTokenBalance,
+};
+
+const WHAT: &str = "token balance";
+const SOL_DECIMALS: u8 = 9;
+
+//=======================================================================
+/// How one token account's balance changed over a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenDelta {
+    pub owner: Pubkey,
+    pub account: Pubkey,
+    pub mint: Pubkey,
+    /// Zero when the account was created by the transaction.
+    pub pre: TokenAmount,
+    /// Zero when the account was closed by the transaction.
+    pub post: TokenAmount,
+}
+
+//=======================================================================
+/// A transaction read as a swap: the leg received or sent by `leg.owner`,
+/// the counterparty leg that mirrors it exactly, and the leg in the other
+/// direction that paid for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolTransaction {
+    pub signature: String,
+    pub leg: TokenDelta,
+    pub counterparty: Option<TokenDelta>,
+    pub sold: Option<TokenDelta>,
+}
+
+//=======================================================================
+impl TokenDelta {
+    //=======================================================================
+    /// Raw change in the account's balance.
+    pub fn diff(&self) -> i128 {
+        self.post.amount as i128 - self.pre.amount as i128
+    }
+
+    //=======================================================================
+    /// Change in whole tokens, as the UI amounts show it.
+    pub fn ui_diff(&self) -> Decimal {
+        Decimal::new(self.diff(), self.post.decimals as u32)
+    }
+}
+
+//=======================================================================
+impl SolTransaction {
+    //=======================================================================
+    /// Swap legs of one transaction from a block or `getTransaction`.
+    /// `None` for failed transactions and those that move no tokens. Any
+    /// encoding works, `jsonParsed` included.
+    pub fn from_encoded(tx: &EncodedTransactionWithStatusMeta) -> AtlasResult<Option<Self>> {
+        let (signature, keys, meta) = confirmed_account_keys(tx)?;
+        if meta.err.is_some() {
+            return Ok(None);
+        }
+        let deltas = token_deltas(meta, &keys).map_err(|e| e.in_tx(&signature))?;
+        Ok(pair_legs(signature, &deltas))
+    }
+
+    //=======================================================================
+    /// Like `from_encoded`, with wrapped and native SOL merged into one SOL
+    /// leg per owner, so SOL paid straight from a wallet shows up and a
+    /// wrap or unwrap is not mistaken for a trade. The fee is read off the
+    /// instructions, so `jsonParsed` transactions are not accepted.
+    pub fn from_encoded_native(tx: &EncodedTransactionWithStatusMeta) -> AtlasResult<Option<Self>> {
+        let (signature, message, meta) = decode_confirmed_transaction(tx)?;
+        if meta.err.is_some() {
+            return Ok(None);
+        }
+        let keys: Vec<_> = message.account_keys.iter().map(|k| k.pubkey).collect();
+        let deltas = token_deltas(meta, &keys)
+            .and_then(|tokens| {
+                let sol = SolBalances::from_meta(signature.clone(), &message, meta)?;
+                Ok(merge_sol(&tokens, &sol))
+            })
+            .map_err(|e| e.in_tx(&signature))?;
+        Ok(pair_legs(signature, &deltas))
+    }
+}
+
+//=======================================================================
+/// Token deltas with wrapped SOL replaced by each owner's net SOL change,
+/// reported under the native mint with the owner as the account. The fee
+/// is added back to the payer's post balance.
+pub fn merge_sol(tokens: &[TokenDelta], sol: &SolBalances) -> Vec<TokenDelta> {
+    let native: Pubkey = NATIVE_MINT_ID.parse().unwrap();
+    let tokens = tokens.iter().filter(|d| d.mint != native).cloned();
+    let owners = sol.owners.iter().map(|o| TokenDelta {
+        owner: o.owner,
+        account: o.owner,
+        mint: native,
+        pre: TokenAmount::new(o.pre, SOL_DECIMALS),
+        post: TokenAmount::new(o.post + o.fee, SOL_DECIMALS),
+    });
+    tokens.chain(owners).collect()
+}
+
+//=======================================================================
+/// Swaps in every successful transaction of `block`, in block order.
+/// Transactions that fail to decode are left out rather than losing the
+/// whole block.
+pub fn block_swaps(block: &EncodedConfirmedBlock) -> Vec<SolTransaction> {
+    let blockhash = block.blockhash.as_str();
+    let mut skipped = 0;
+    let swaps: Vec<_> = block
+        .transactions
+        .iter()
+        .filter_map(|tx| {
+            SolTransaction::from_encoded(tx)
+                .map_err(|e| {
+                    skipped += 1;
+                    debug!(blockhash = blockhash; "{}", e);
+                })
+                .ok()
+                .flatten()
+        })
+        .collect();
+    if skipped > 0 {
+        warn!(
+            blockhash = blockhash;
+            "Left {} undecodable transactions out of block swaps",
+            skipped
+        );
+    }
+    swaps
+}
+
+//=======================================================================
+/// Pair up legs the way the desk reads a swap. Walking the deltas in
+/// order, the first whose amount in whole tokens is mirrored by another
+/// owner's delta, in any mint, and whose owner (or else the counterparty)
+/// also moved a balance the other way, wins. Failing that, the last delta
+/// is returned with whatever counterparty it has. The Python original
+/// compared float UI amounts within 1e-5; these compare exactly.
+pub fn pair_legs(signature: String, deltas: &[TokenDelta]) -> Option<SolTransaction> {
+    let opposite = |owner: &Pubkey, diff: i128| {
+        deltas
+            .iter()
+            .find(|d| d.owner == *owner && d.diff().signum() != diff.signum())
+    };
+    let mut last = None;
+    for leg in deltas {
+        let diff = leg.diff();
+        let counterparty = deltas
+            .iter()
+            .find(|cp| cp.owner != leg.owner && cp.ui_diff() == -leg.ui_diff());
+        let sold = counterparty
+            .and_then(|cp| opposite(&leg.owner, diff).or_else(|| opposite(&cp.owner, diff)));
+        let swap = SolTransaction {
+            signature: signature.clone(),
+            leg: leg.clone(),
+            counterparty: counterparty.cloned(),
+            sold: sold.cloned(),
+        };
+        if swap.sold.is_some() {
+            return Some(swap);
+        }
+        last = Some(swap);
+    }
+    last
+}
+
+//=======================================================================
+/// Per-account token balance changes, from the pre and post balances in
+/// the meta. `keys` is the full account list, loaded addresses included.
+pub fn token_deltas(
+    meta: &UiTransactionStatusMeta,
+    keys: &[Pubkey],
+) -> AtlasResult<Vec<TokenDelta>> {
+    let pre: &[UiTransactionTokenBalance] =
+        Option::<&Vec<_>>::from(meta.pre_token_balances.as_ref()).map_or(&[], |v| v);
+    let post: &[UiTransactionTokenBalance] =
+        Option::<&Vec<_>>::from(meta.post_token_balances.as_ref()).map_or(&[], |v| v);
+
+    let mut matched = vec![false; post.len()];
+    let mut deltas = Vec::new();
+    for before in pre {
+        let after = post
+            .iter()
+            .position(|b| b.account_index == before.account_index)
+            .map(|i| {
+                matched[i] = true;
+                &post[i]
+            });
+        let pre_amount = amount(before)?;
+        let post_amount = match after {
+            Some(after) => amount(after)?,
+            None => TokenAmount::new(0, pre_amount.decimals),
+        };
+        if pre_amount.amount != post_amount.amount {
+            deltas.push(delta(before, keys, pre_amount, post_amount)?);
+        }
+    }
+    for (after, _) in post.iter().zip(matched).filter(|(_, seen)| !seen) {
+        let post_amount = amount(after)?;
+        if post_amount.amount != 0 {
+            let pre_amount = TokenAmount::new(0, post_amount.decimals);
+            deltas.push(delta(after, keys, pre_amount, post_amount)?);
+        }
+    }
+    Ok(deltas)
+}
+
+//=======================================================================
+fn delta(
+    balance: &UiTransactionTokenBalance,
+    keys: &[Pubkey],
+    pre: TokenAmount,
+    post: TokenAmount,
+) -> AtlasResult<TokenDelta> {
+    let index = balance.account_index as usize;
+    let account = *keys.get(index).ok_or_else(|| {
+        AtlasError::decode(
+            WHAT,
+            format!("account #{} is out of range of {} keys", index, keys.len()),
+        )
+    })?;
+    let owner = Option::<&String>::from(balance.owner.as_ref())
+        .ok_or_else(|| AtlasError::decode(WHAT, format!("account {} has no owner", account)))?;
+    Ok(TokenDelta {
+        owner: pubkey(owner)?,
+        account,
+        mint: pubkey(&balance.mint)?,
+        pre,
+        post,
+    })
+}
+
+//=======================================================================
+fn amount(balance: &UiTransactionTokenBalance) -> AtlasResult<TokenAmount> {
+    let ui = &balance.ui_token_amount;
+    let amount = ui
+        .amount
+        .parse()
+        .map_err(|e| AtlasError::decode(WHAT, format!("amount {:?}: {}", ui.amount, e)))?;
+    Ok(TokenAmount::new(amount, ui.decimals))
+}
+
+//=======================================================================
+fn pubkey(key: &str) -> AtlasResult<Pubkey> {
+    key.parse()
+        .map_err(|e| AtlasError::decode(WHAT, format!("{}: {}", key, e)))
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_core::config::AtlasConfig;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_client::rpc_config::RpcTransactionConfig;
+    use solana_sdk::transaction::TransactionError;
+    use solana_transaction_status_client_types::{
+        EncodedTransaction, UiMessage, UiTransactionEncoding,
+    };
+
+    //=======================================================================
+    fn swap(fixture: &str) -> SolTransaction {
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_str(fixture).unwrap();
+        SolTransaction::from_encoded(&tx).unwrap().unwrap()
+    }
+
+    //=======================================================================
+    fn key(s: &str) -> Pubkey {
+        s.parse().unwrap()
+    }
+
+    //=======================================================================
+    /// `signature` as `getTransaction` returns it from the configured node,
+    /// read as a swap. The Python tests ran against mainnet the same way.
+    async fn fetch(signature: &str) -> SolTransaction {
+        let config = AtlasConfig::from_env().unwrap();
+        let client = RpcClient::new(config.solana.rpc_url.clone());
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Json),
+            commitment: None,
+            max_supported_transaction_version: Some(0),
+        };
+        let tx = client
+            .get_transaction_with_config(&signature.parse().unwrap(), config)
+            .await
+            .unwrap();
+        SolTransaction::from_encoded(&tx.transaction)
+            .unwrap()
+            .unwrap()
+    }
+
+    //=======================================================================
+    #[tokio::test]
+    #[ignore = "fetches from a Solana RPC node"]
+    async fn test_get_transaction_simple() {
+        let signature = "3boyDShobz2MhfCie975MdAcufzM2fbVDKjipRc7xmmWQhQQhHGtaHatVZgcYmqyMnkUDCGv85hNDRYNkkDCvJqK";
+        let tx = fetch(signature).await;
+        assert_eq!(tx.signature, signature);
+        assert_eq!(
+            tx.leg,
+            TokenDelta {
+                owner: key("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"),
+                account: key("ViaWBq2QPE83wYCsv6Yhe16jEgPSxZmXTXBgZqSNJhX"),
+                mint: key("9PPeYELzXxnxTuWkX7P9JH9J9hsXc9b8uoGNJwA1pump"),
+                pre: TokenAmount::new(293_920_207_516_883, 6),
+                post: TokenAmount::new(297_594_312_850_405, 6),
+            }
+        );
+        assert_eq!(tx.leg.diff(), 3_674_105_333_522);
+
+        // The seller's token account is closed in the same transaction.
+        let cp = tx.counterparty.unwrap();
+        assert_eq!(
+            cp.owner,
+            key("J3g4GWCCcQkeZjzWu6Hn1VRj3Qouz4SwYKG84akxTdgS")
+        );
+        assert_eq!(
+            cp.account,
+            key("AKbRjX1vyvGCJqTQ5gqERJKvrzoJmFNb5xnAHx7MNyaq")
+        );
+        assert_eq!(cp.pre.ui_amount_string(), "3674105.333522");
+        assert_eq!(cp.post.ui_amount_string(), "0");
+
+        // `mint_cp` in the Python port is the mint of the sold leg.
+        let sold = tx.sold.unwrap();
+        assert_eq!(sold.mint, key(NATIVE_MINT_ID));
+        assert_eq!(
+            sold.account,
+            key("E95KPBjkeLVy2to5ahJDnLA21dYRBq7c13L916cuobgd")
+        );
+        assert_eq!(sold.pre.ui_amount_string(), "59.635501108");
+        assert_eq!(sold.post.ui_amount_string(), "58.901058004");
+        assert_eq!(sold.diff(), -734_443_104);
+    }
+
+    //=======================================================================
+    #[tokio::test]
+    #[ignore = "fetches from a Solana RPC node"]
+    async fn test_get_transaction_simple_2() {
+        // The counterparty's account comes from a lookup table.
+        let signature = "2u3AMnzckiFetYRjoZEy28H3z4JpFTfdFQxoGPdQQvKbySBtM8TwSbTZhfjzgg7jRhkVCBoKNJajXx7CxXkMjmPs";
+        let tx = fetch(signature).await;
+        assert_eq!(tx.signature, signature);
+        assert_eq!(
+            tx.leg,
+            TokenDelta {
+                owner: key("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"),
+                account: key("4raiFCHdS9UixPnsPpbEnAHp94Fjp5qH9ZijuzHK1n9X"),
+                mint: key("GU4XtyVdkTKKJ6gyaBTHbNTTrXkpMxsNkziGW4RTpump"),
+                pre: TokenAmount::new(91_774_486_983_288, 6),
+                post: TokenAmount::new(100_066_425_275_343, 6),
+            }
+        );
+        assert_eq!(tx.leg.diff(), 8_291_938_292_055);
+
+        let cp = tx.counterparty.unwrap();
+        assert_eq!(
+            cp.owner,
+            key("EEdqNkpXofifBo8jGYCWdwu3yJHZZ3zLbiMBctLtQLCv")
+        );
+        assert_eq!(
+            cp.account,
+            key("B4HRBtqmx4QN5vqjNkJS246o31LkG7DcQ5c4VNxJSBG")
+        );
+        assert_eq!(cp.pre.ui_amount_string(), "8291938.292055");
+        assert_eq!(cp.post.amount, 0);
+
+        let sold = tx.sold.unwrap();
+        assert_eq!(sold.mint, key(NATIVE_MINT_ID));
+        assert_eq!(
+            sold.account,
+            key("6sENC2ee7HXBLGDtFpEwLXhkjYBAVu6ti86e6kfLxqya")
+        );
+        assert_eq!(sold.pre.ui_amount_string(), "192.634345634");
+        assert_eq!(sold.post.ui_amount_string(), "176.708434781");
+        assert_eq!(sold.diff(), -15_925_910_853);
+    }
+
+    //=======================================================================
+    #[tokio::test]
+    #[ignore = "fetches from a Solana RPC node"]
+    async fn test_get_transfer() {
+        // A plain transfer into a freshly created account: the receiving leg
+        // is both the counterparty and the "sold" leg.
+        let signature = "5yBphEw19aAkNbsYLai2qModFVCHVae2W8ZKXt3sa9JCpJeGZKGHgA5AW7xK9QSBtDnHVWa19FYNVNtpWe1LgPXP";
+        let tx = fetch(signature).await;
+        let jup = key("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN");
+        assert_eq!(tx.signature, signature);
+        assert_eq!(
+            tx.leg,
+            TokenDelta {
+                owner: key("FnvLGtucz4E1ppJHRTev6Qv4X7g8Pw6WPStHCcbAKbfx"),
+                account: key("H3cnJE7YcistPDM11AWzR6CgLYHky9aYGLxcX5GU2ciJ"),
+                mint: jup,
+                pre: TokenAmount::new(200_000_001, 6),
+                post: TokenAmount::new(0, 6),
+            }
+        );
+        assert_eq!(tx.leg.diff(), -200_000_001);
+
+        let receiver = TokenDelta {
+            owner: key("DAnUT7fSUzGyUJwgpSE8pJEqtNGdGrLGA9GQP9C46vND"),
+            account: key("ACoZ83R4hrB4z4h7RLd1RjVxdg57P3BjaAKr1xVE2Cuf"),
+            mint: jup,
+            pre: TokenAmount::new(0, 6),
+            post: TokenAmount::new(200_000_001, 6),
+        };
+        assert_eq!(tx.counterparty, Some(receiver.clone()));
+        assert_eq!(tx.sold, Some(receiver));
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_sol_leg_paid_from_wallet() {
+        let fixture = include_str!("../test/fixtures/native_buy.json");
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_str(fixture).unwrap();
+        let curve = key("HaBq2gUmrmEhEA4HPAwxdQSQ4cK8DDugaXs5qb1mnTuA");
+
+        // Token balances alone cannot see what the user paid with, so the
+        // bought tokens stand in for the sold leg.
+        let tokens = SolTransaction::from_encoded(&tx).unwrap().unwrap();
+        assert_eq!(tokens.leg.owner, curve);
+        assert_eq!(tokens.sold.unwrap().mint, tokens.leg.mint);
+
+        let swap = SolTransaction::from_encoded_native(&tx).unwrap().unwrap();
+        assert_eq!(
+            swap.counterparty.unwrap().owner,
+            key("7xMg4Hx2yPYjUqNHRE1QhD5rE9Eh6CWnp8rBYtRfdaPf")
+        );
+        let sold = swap.sold.unwrap();
+        assert_eq!((sold.owner, sold.account), (curve, curve));
+        assert_eq!(sold.mint, key(NATIVE_MINT_ID));
+        assert_eq!(sold.diff(), 1_000_000_000);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_counterparty_mirrors_whole_tokens() {
+        let delta = |owner: Pubkey, mint: Pubkey, decimals: u8, pre: u64, post: u64| TokenDelta {
+            owner,
+            account: Pubkey::new_unique(),
+            mint,
+            pre: TokenAmount::new(pre, decimals),
+            post: TokenAmount::new(post, decimals),
+        };
+        let (buyer, pool, dust) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (bought, quote, paid) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let leg = delta(buyer, bought, 6, 0, 1_500_000);
+        // Same raw amount, but 0.0015 tokens: not a mirror.
+        let same_raw = delta(dust, quote, 9, 1_500_000, 0);
+        // 1.5 tokens of another mint mirrors the leg, as in the Python port.
+        let mirror = delta(pool, quote, 9, 1_500_000_000, 0);
+        let sold = delta(buyer, paid, 9, 2_000_000_000, 1_000_000_000);
+        let deltas = [leg.clone(), same_raw, mirror.clone(), sold.clone()];
+
+        let swap = pair_legs("sig".to_string(), &deltas).unwrap();
+        assert_eq!(swap.leg, leg);
+        assert_eq!(swap.counterparty, Some(mirror));
+        assert_eq!(swap.sold, Some(sold));
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_parsed_message() {
+        // The same transfer as `jsonParsed` returns it: keys come as objects
+        // and there is no header to rebuild the message from.
+        let fixture = include_str!("../test/fixtures/transfer.json");
+        let mut value: serde_json::Value = serde_json::from_str(fixture).unwrap();
+        let message = value["transaction"]["message"].as_object_mut().unwrap();
+        message.remove("header");
+        let keys = message["accountKeys"].as_array_mut().unwrap();
+        for key in keys.iter_mut() {
+            *key = serde_json::json!({
+                "pubkey": key.clone(),
+                "writable": false,
+                "signer": false,
+                "source": "transaction",
+            });
+        }
+        let parsed: EncodedTransactionWithStatusMeta = serde_json::from_value(value).unwrap();
+        assert!(matches!(
+            &parsed.transaction,
+            EncodedTransaction::Json(ui) if matches!(ui.message, UiMessage::Parsed(_))
+        ));
+        assert_eq!(
+            SolTransaction::from_encoded(&parsed).unwrap(),
+            Some(swap(fixture))
+        );
+        assert!(SolTransaction::from_encoded_native(&parsed).is_err());
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_block_swaps_skip_undecodable() {
+        let fixture = include_str!("../test/fixtures/transfer.json");
+        let tx: serde_json::Value = serde_json::from_str(fixture).unwrap();
+        let mut broken = tx.clone();
+        broken["meta"] = serde_json::Value::Null;
+        let block: EncodedConfirmedBlock = serde_json::from_value(serde_json::json!({
+            "previousBlockhash": "AkLLYdodNoPmJyXk6yGVu6yza66PRPMN3xwpwiSs2RpZ",
+            "blockhash": "AkLLYdodNoPmJyXk6yGVu6yza66PRPMN3xwpwiSs2RpZ",
+            "parentSlot": 300_000_000,
+            "transactions": [broken, tx],
+            "rewards": [],
+        }))
+        .unwrap();
+        assert_eq!(block_swaps(&block), vec![swap(fixture)]);
+    }
+
+    //=======================================================================
+    #[test]
+    fn test_failed_and_tokenless_transactions() {
+        let fixture = include_str!("../test/fixtures/transfer.json");
+        let mut failed: EncodedTransactionWithStatusMeta = serde_json::from_str(fixture).unwrap();
+        let meta = failed.meta.as_mut().unwrap();
+        meta.err = Some(TransactionError::AccountNotFound);
+        meta.status = Err(TransactionError::AccountNotFound);
+        assert_eq!(SolTransaction::from_encoded(&failed).unwrap(), None);
+
+        let mut tokenless: EncodedTransactionWithStatusMeta =
+            serde_json::from_str(fixture).unwrap();
+        let meta = tokenless.meta.as_mut().unwrap();
+        meta.pre_token_balances = Some(vec![]).into();
+        meta.post_token_balances = Some(vec![]).into();
+        assert_eq!(SolTransaction::from_encoded(&tokenless).unwrap(), None);
+    }
+}
@@ -1,78 +1,440 @@
+use crate::alt::{parse_loaded_addresses, AltResolver};
+use atlas_core::error::{AtlasError, AtlasResult};
+use base64::Engine;
 use serde::{Deserialize, Serialize};
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::instruction::Instruction;
-use solana_sdk::program_error::ProgramError;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::bs58;
+use solana_sdk::clock::Slot;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::{AccountMeta, CompiledInstruction, Instruction};
+use solana_sdk::message::v0::{self, LoadedAddresses, MessageAddressTableLookup};
+use solana_sdk::message::{Message as LegacyMessage, VersionedMessage};
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::Signature;
-use solana_sdk::transaction::{Message, Transaction};
-use std::collections::HashMap;
+use solana_transaction_status_client_types::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, UiLoadedAddresses, UiMessage,
+    UiRawMessage, UiTransactionStatusMeta,
+};
 
 //https://docs.anza.xyz/runtime/programs/#config-program
-static SYS_PROGRAM_ID: &str = "11111111111111111111111111111111";
-static CONFIG_PROGRAM_ID: &str = "Config1111111111111111111111111111111111111";
-static STAKE_PROGRAM_ID: &str = "Stake11111111111111111111111111111111111111";
-static VOTE_PROGRAM_ID: &str = "Vote111111111111111111111111111111111111111";
-static ADDRESS_LOOKUP_PROGRAM_ID: &str = "AddressLookupTab1e1111111111111111111111111";
-static ED25519_PROGRAM_ID: &str = "Ed25519SigVerify111111111111111111111111111";
-static SECP256K1_PROGRAM_ID: &str = "KeccakSecp256k11111111111111111111111111111";
-static SECP256R1_PROGRAM_ID: &str = "Secp256r1SigVerify1111111111111111111111111";
+pub static SYS_PROGRAM_ID: &str = "11111111111111111111111111111111";
+pub static CONFIG_PROGRAM_ID: &str = "Config1111111111111111111111111111111111111";
+pub static STAKE_PROGRAM_ID: &str = "Stake11111111111111111111111111111111111111";
+pub static VOTE_PROGRAM_ID: &str = "Vote111111111111111111111111111111111111111";
+pub static ADDRESS_LOOKUP_PROGRAM_ID: &str = "AddressLookupTab1e1111111111111111111111111";
+pub static COMPUTE_BUDGET_PROGRAM_ID: &str = "ComputeBudget111111111111111111111111111111";
+pub static ED25519_PROGRAM_ID: &str = "Ed25519SigVerify111111111111111111111111111";
+pub static SECP256K1_PROGRAM_ID: &str = "KeccakSecp256k11111111111111111111111111111";
+pub static SECP256R1_PROGRAM_ID: &str = "Secp256r1SigVerify1111111111111111111111111";
+pub static TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub static TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+pub static NATIVE_MINT_ID: &str = "So11111111111111111111111111111111111111112";
 
 //=======================================================================
-#[derive(Debug, Serialize, Deserialize)]
+/// How a serialized message is handed to us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageEncoding {
+    Binary,
+    Base58,
+    Base64,
+}
+
+//=======================================================================
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DecodedInstruction {
     pub program_id: Pubkey,
     pub data: Vec<u8>,
-    pub keys: Vec<Pubkey>,
+    pub keys: Vec<AccountMeta>,
+}
+
+//=======================================================================
+impl From<Instruction> for DecodedInstruction {
+    fn from(instruction: Instruction) -> Self {
+        DecodedInstruction {
+            program_id: instruction.program_id,
+            data: instruction.data,
+            keys: instruction.accounts,
+        }
+    }
 }
 
 //=======================================================================
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DecodedMessage {
+    /// `None` for legacy messages.
+    pub version: Option<u8>,
+    /// Static keys, then keys loaded from lookup tables: writable first,
+    /// then readonly, as the runtime orders them.
+    pub account_keys: Vec<AccountMeta>,
     pub instructions: Vec<DecodedInstruction>,
+    /// The wallet's own entry, if it takes part in the message.
+    pub wallet: Option<AccountMeta>,
     pub raw_message: Vec<u8>,
 }
 
 //=======================================================================
-async fn to_instruction(
+/// Raw message bytes from `input` in the given wire format.
+pub fn message_bytes(input: &[u8], encoding: MessageEncoding) -> AtlasResult<Vec<u8>> {
+    match encoding {
+        MessageEncoding::Binary => Ok(input.to_vec()),
+        MessageEncoding::Base58 => bs58::decode(input.trim_ascii())
+            .into_vec()
+            .map_err(|e| AtlasError::decode("base58 message", e)),
+        MessageEncoding::Base64 => base64::engine::general_purpose::STANDARD
+            .decode(input.trim_ascii())
+            .map_err(|e| AtlasError::decode("base64 message", e)),
+    }
+}
+
+//=======================================================================
+/// Decode a legacy or v0 message. Address table lookups are loaded through
+/// `connection` using every address the tables hold now.
+pub async fn decode_message(
+    connection: &RpcClient,
+    wallet_pubkey: &Pubkey,
+    message: &[u8],
+) -> AtlasResult<DecodedMessage> {
+    decode_message_at(
+        connection,
+        &AltResolver::new(),
+        wallet_pubkey,
+        message,
+        Slot::MAX,
+        None,
+    )
+    .await
+}
+
+//=======================================================================
+/// Decode a message that landed in `slot`, resolving lookups through
+/// `resolver` and falling back to the meta's `loaded` addresses.
+pub async fn decode_message_at(
     connection: &RpcClient,
+    resolver: &AltResolver,
     wallet_pubkey: &Pubkey,
-    account_keys: &[Pubkey],
-    instruction: &Instruction,
-    instruction_index: usize,
-) -> Result<DecodedInstruction, ProgramError> {
-    let program_id = instruction.program_id.clone();
-    let data = instruction.data.clone();
-    let keys = instruction.accounts.clone();
-    Ok(DecodedInstruction {
-        program_id,
-        data,
-        keys,
-    })
+    message: &[u8],
+    slot: Slot,
+    loaded: Option<&LoadedAddresses>,
+) -> AtlasResult<DecodedMessage> {
+    let message: VersionedMessage =
+        bincode::deserialize(message).map_err(|e| AtlasError::decode("transaction message", e))?;
+    let lookups = message.address_table_lookups().unwrap_or_default();
+    let loaded = resolver.resolve(connection, lookups, slot, loaded).await?;
+    decode_versioned_message(&message, &loaded, wallet_pubkey)
 }
 
 //=======================================================================
-pub async fn decode_message(
+/// Like `decode_message` for any wire format.
+pub async fn decode_encoded_message(
     connection: &RpcClient,
     wallet_pubkey: &Pubkey,
     message: &[u8],
-) -> Result<DecodedMessage, ProgramError> {
-    let message = Message::deserialize(message)?;
-    let instructions = message.instructions;
-    let mut decoded_instructions: Vec<DecodedInstruction> = Vec::new();
-    for (i, instruction) in instructions.iter().enumerate() {
-        // Process each instruction
-        let decoded_instruction = to_instruction(
-            connection,
-            wallet_pubkey,
-            &message.account_keys,
-            instruction,
-            i,
-        )
-        .await?;
-        decoded_instructions.push(decoded_instruction);
-    }
+    encoding: MessageEncoding,
+) -> AtlasResult<DecodedMessage> {
+    let bytes = message_bytes(message, encoding)?;
+    decode_message(connection, wallet_pubkey, &bytes).await
+}
+
+//=======================================================================
+/// Decode `message` with its lookup tables already resolved into `loaded`.
+pub fn decode_versioned_message(
+    message: &VersionedMessage,
+    loaded: &LoadedAddresses,
+    wallet_pubkey: &Pubkey,
+) -> AtlasResult<DecodedMessage> {
+    let account_keys = account_metas(message, loaded);
+    let meta = |index: u8| {
+        account_keys.get(index as usize).cloned().ok_or_else(|| {
+            AtlasError::decode(
+                "transaction message",
+                format!(
+                    "account index {} out of range for {} keys",
+                    index,
+                    account_keys.len()
+                ),
+            )
+        })
+    };
+    let instructions = message
+        .instructions()
+        .iter()
+        .map(|instruction| {
+            Ok(DecodedInstruction {
+                program_id: meta(instruction.program_id_index)?.pubkey,
+                data: instruction.data.clone(),
+                keys: instruction
+                    .accounts
+                    .iter()
+                    .map(|&index| meta(index))
+                    .collect::<AtlasResult<_>>()?,
+            })
+        })
+        .collect::<AtlasResult<_>>()?;
+    let wallet = account_keys
+        .iter()
+        .find(|key| key.pubkey == *wallet_pubkey)
+        .cloned();
     Ok(DecodedMessage {
-        instructions: decoded_instructions,
-        raw_message: message.serialize()?,
+        version: match message {
+            VersionedMessage::Legacy(_) => None,
+            VersionedMessage::V0(_) => Some(0),
+        },
+        account_keys,
+        instructions,
+        wallet,
+        raw_message: message.serialize(),
     })
 }
+
+//=======================================================================
+/// Decode a transaction as `getBlock` and `getTransaction` return it, with
+/// lookups taken from its meta. Also returns the first signature and the
+/// meta, which must be present.
+pub fn decode_confirmed_transaction(
+    tx: &EncodedTransactionWithStatusMeta,
+) -> AtlasResult<(String, DecodedMessage, &UiTransactionStatusMeta)> {
+    let meta = confirmed_meta(tx)?;
+    let (signature, message) = match &tx.transaction {
+        EncodedTransaction::Json(ui) => match &ui.message {
+            UiMessage::Raw(raw) => (
+                ui.signatures.first().cloned().unwrap_or_default(),
+                raw_message(raw)?,
+            ),
+            UiMessage::Parsed(_) => {
+                return Err(AtlasError::decode(
+                    "transaction",
+                    "jsonParsed messages cannot be rebuilt, request json or base64",
+                ))
+            }
+        },
+        other => {
+            let versioned = other.decode().ok_or_else(|| {
+                AtlasError::decode("transaction", "encoding does not carry the message")
+            })?;
+            let signature = versioned
+                .signatures
+                .first()
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            (signature, versioned.message)
+        }
+    };
+    let loaded = Option::<&UiLoadedAddresses>::from(meta.loaded_addresses.as_ref())
+        .map(|ui| parse_loaded_addresses(&ui.writable, &ui.readonly))
+        .transpose()?
+        .unwrap_or_default();
+    let decoded = decode_versioned_message(&message, &loaded, &Pubkey::default())
+        .map_err(|e| e.in_tx(&signature))?;
+    Ok((signature, decoded, meta))
+}
+
+//=======================================================================
+/// First signature, full account list (loaded addresses included) and
+/// meta of a confirmed transaction. Unlike `decode_confirmed_transaction`
+/// this reads `jsonParsed` transactions too, which list their keys
+/// already resolved.
+pub fn confirmed_account_keys(
+    tx: &EncodedTransactionWithStatusMeta,
+) -> AtlasResult<(String, Vec<Pubkey>, &UiTransactionStatusMeta)> {
+    if let EncodedTransaction::Json(ui) = &tx.transaction {
+        if let UiMessage::Parsed(parsed) = &ui.message {
+            let meta = confirmed_meta(tx)?;
+            let keys = parsed
+                .account_keys
+                .iter()
+                .map(|key| {
+                    key.pubkey.parse::<Pubkey>().map_err(|e| {
+                        AtlasError::decode("transaction message", format!("{}: {}", key.pubkey, e))
+                    })
+                })
+                .collect::<AtlasResult<Vec<_>>>()?;
+            return Ok((
+                ui.signatures.first().cloned().unwrap_or_default(),
+                keys,
+                meta,
+            ));
+        }
+    }
+    let (signature, message, meta) = decode_confirmed_transaction(tx)?;
+    let keys = message.account_keys.iter().map(|key| key.pubkey).collect();
+    Ok((signature, keys, meta))
+}
+
+//=======================================================================
+fn confirmed_meta(tx: &EncodedTransactionWithStatusMeta) -> AtlasResult<&UiTransactionStatusMeta> {
+    tx.meta
+        .as_ref()
+        .ok_or_else(|| AtlasError::decode("transaction", "status meta is missing"))
+}
+
+//=======================================================================
+/// The wire message behind a `json` encoded one.
+fn raw_message(raw: &UiRawMessage) -> AtlasResult<VersionedMessage> {
+    const WHAT: &str = "transaction message";
+    let pubkey = |key: &String| {
+        key.parse::<Pubkey>()
+            .map_err(|e| AtlasError::decode(WHAT, format!("{}: {}", key, e)))
+    };
+    let account_keys = raw
+        .account_keys
+        .iter()
+        .map(pubkey)
+        .collect::<AtlasResult<Vec<_>>>()?;
+    let recent_blockhash = raw
+        .recent_blockhash
+        .parse::<Hash>()
+        .map_err(|e| AtlasError::decode(WHAT, e))?;
+    let instructions = raw
+        .instructions
+        .iter()
+        .map(|ix| {
+            Ok(CompiledInstruction {
+                program_id_index: ix.program_id_index,
+                accounts: ix.accounts.clone(),
+                data: bs58::decode(&ix.data)
+                    .into_vec()
+                    .map_err(|e| AtlasError::decode(WHAT, e))?,
+            })
+        })
+        .collect::<AtlasResult<Vec<_>>>()?;
+    let Some(lookups) = &raw.address_table_lookups else {
+        return Ok(VersionedMessage::Legacy(LegacyMessage {
+            header: raw.header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+        }));
+    };
+    let address_table_lookups = lookups
+        .iter()
+        .map(|lookup| {
+            Ok(MessageAddressTableLookup {
+                account_key: pubkey(&lookup.account_key)?,
+                writable_indexes: lookup.writable_indexes.clone(),
+                readonly_indexes: lookup.readonly_indexes.clone(),
+            })
+        })
+        .collect::<AtlasResult<Vec<_>>>()?;
+    Ok(VersionedMessage::V0(v0::Message {
+        header: raw.header,
+        account_keys,
+        recent_blockhash,
+        instructions,
+        address_table_lookups,
+    }))
+}
+
+//=======================================================================
+/// Signer and writable flags follow from the header: signers come first,
+/// and each group ends with its readonly accounts. Loaded keys are never
+/// signers.
+fn account_metas(message: &VersionedMessage, loaded: &LoadedAddresses) -> Vec<AccountMeta> {
+    let header = message.header();
+    let keys = message.static_account_keys();
+    let signed = header.num_required_signatures as usize;
+    let writable_signed = signed.saturating_sub(header.num_readonly_signed_accounts as usize);
+    let writable_unsigned = keys
+        .len()
+        .saturating_sub(header.num_readonly_unsigned_accounts as usize);
+    let static_keys = keys.iter().enumerate().map(|(i, pubkey)| AccountMeta {
+        pubkey: *pubkey,
+        is_signer: i < signed,
+        is_writable: if i < signed {
+            i < writable_signed
+        } else {
+            i < writable_unsigned
+        },
+    });
+    let writable = loaded
+        .writable
+        .iter()
+        .map(|pubkey| AccountMeta::new(*pubkey, false));
+    let readonly = loaded
+        .readonly
+        .iter()
+        .map(|pubkey| AccountMeta::new_readonly(*pubkey, false));
+    static_keys.chain(writable).chain(readonly).collect()
+}
+
+//=======================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::message::MessageHeader;
+
+    //=======================================================================
+    #[test]
+    fn test_decode_v0_message() {
+        let payer = Pubkey::new_unique();
+        let cosigner = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        let table_key = Pubkey::new_unique();
+        let table: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let lookup = MessageAddressTableLookup {
+            account_key: table_key,
+            writable_indexes: vec![2],
+            readonly_indexes: vec![0, 3],
+        };
+        let message = VersionedMessage::V0(v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 2,
+                num_readonly_signed_accounts: 1,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![payer, cosigner, destination, program],
+            recent_blockhash: Hash::new_unique(),
+            // Accounts 4.. come from the table: 4 writable, 5 and 6 readonly.
+            instructions: vec![CompiledInstruction::new_from_raw_parts(
+                3,
+                vec![1, 2, 3],
+                vec![0, 1, 2, 4, 6],
+            )],
+            address_table_lookups: vec![lookup],
+        });
+        let raw = message.serialize();
+        let encoded = bs58::encode(&raw).into_string();
+        assert_eq!(
+            message_bytes(encoded.as_bytes(), MessageEncoding::Base58).unwrap(),
+            raw
+        );
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&raw);
+        assert_eq!(
+            message_bytes(encoded.as_bytes(), MessageEncoding::Base64).unwrap(),
+            raw
+        );
+        let parsed: VersionedMessage = bincode::deserialize(&raw).unwrap();
+
+        let loaded = LoadedAddresses {
+            writable: vec![table[2]],
+            readonly: vec![table[0], table[3]],
+        };
+        let decoded = decode_versioned_message(&parsed, &loaded, &cosigner).unwrap();
+        assert_eq!(decoded.version, Some(0));
+        assert_eq!(decoded.raw_message, raw);
+        assert_eq!(decoded.account_keys.len(), 7);
+        assert_eq!(
+            decoded.wallet,
+            Some(AccountMeta::new_readonly(cosigner, true))
+        );
+
+        let instruction = &decoded.instructions[0];
+        assert_eq!(instruction.program_id, program);
+        assert_eq!(instruction.data, vec![1, 2, 3]);
+        assert_eq!(
+            instruction.keys,
+            vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(cosigner, true),
+                AccountMeta::new(destination, false),
+                AccountMeta::new(table[2], false),
+                AccountMeta::new_readonly(table[3], false),
+            ]
+        );
+
+        let truncated = LoadedAddresses {
+            writable: vec![table[2]],
+            readonly: vec![],
+        };
+        assert!(decode_versioned_message(&parsed, &truncated, &cosigner).is_err());
+    }
+}
@@ -1,57 +1,109 @@
-use atlas_core::{error::AtlasResult, util::AtlasUtil};
-use futures::{SinkExt, StreamExt};
+use atlas_core::{
+    alert::{Alert, Alerter},
+    bus::{BlockMetaUpdate, ChainEvent, EventBus},
+    clock::SharedClock,
+    config::{AtlasConfig, SolanaConfig},
+    error::{AtlasError, AtlasResult},
+    health::{ComponentHealth, HealthRegistry},
+    metrics::{self, Counter, Histogram, LATENCY_BUCKETS},
+    ratelimit::RateLimiter,
+    retry::CallPolicy,
+    supervisor::CancellationToken,
+    util::AtlasUtil,
+    ws::{JsonRpcProtocol, SubscribeRequest, WsClient},
+};
+use atlas_sol::estimator::FeeEstimator;
+use atlas_sol::fees::{block_fee_update, block_fees};
+use futures::StreamExt;
 use log::{error, info};
 use serde::Deserialize;
 use serde_json::json;
+use solana_client::client_error::{ClientError, ClientErrorKind};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_client::rpc_request::RpcError;
 use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
 use solana_sdk::{bs58::encode, clock::Slot};
-use solana_transaction_status_client_types::EncodedConfirmedBlock;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-
-const CHAINSTAKE_SOLONA_HTTPS: &str =
-    "https://solana-mainnet.core.chainstack.com/2fa69914c087050cc7b9887511cd7d35";
-const CHAINSTAKE_SOLONA_WS: &str =
-    "wss://solana-mainnet.core.chainstack.com/2fa69914c087050cc7b9887511cd7d35";
+use solana_transaction_status_client_types::{
+    EncodedConfirmedBlock, EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding,
+};
+use std::sync::{Arc, Mutex};
 
 //==============================================================================
-pub struct SolanaRpcWrapper {
+/// Plain JSON-RPC calls to `solana.rpc_url`, with no websocket behind them.
+/// One-shot commands use this directly; `SolanaRpcWrapper` adds the block
+/// stream on top.
+pub struct SolanaRpc {
     client: RpcClient,
+    url: String,
+    policy: CallPolicy,
 }
 
 //==============================================================================
-#[derive(Debug, Deserialize)]
-pub struct BlockResponse {
-    pub jsonrpc: String,
-    pub method: String,
-    pub params: BlockParams,
+pub struct SolanaRpcWrapper {
+    rpc: SolanaRpc,
+    config: SolanaConfig,
+    ws: WsClient,
+    metrics: BlockStreamMetrics,
+    bus: Option<EventBus>,
+    estimator: Option<Arc<Mutex<FeeEstimator>>>,
+    health: ComponentHealth,
+    alerter: Alerter,
 }
 
 //==============================================================================
-impl BlockResponse {
-    pub fn has_error(&self) -> bool {
-        self.params.result.value.err.is_some()
-    }
-
-    pub fn get_encoded_block(&self) -> Option<&EncodedConfirmedBlock> {
-        self.params.result.value.block.as_ref()
-    }
+struct BlockStreamMetrics {
+    messages: Counter,
+    parse_errors: Counter,
+    latency: Histogram,
 }
 
 //==============================================================================
-#[derive(Debug, Deserialize)]
-pub struct BlockParams {
-    pub result: BlockResult,
-    pub subscription: u64,
+impl BlockStreamMetrics {
+    fn new(endpoint: &str) -> Self {
+        let registry = metrics::global();
+        let labels = [("endpoint", endpoint)];
+        BlockStreamMetrics {
+            messages: registry.counter(
+                "atlas_block_stream_messages_total",
+                "Notifications received on the block stream",
+                &labels,
+            ),
+            parse_errors: registry.counter(
+                "atlas_block_stream_parse_errors_total",
+                "Block stream messages that failed to parse",
+                &labels,
+            ),
+            latency: registry.histogram(
+                "atlas_block_stream_latency_seconds",
+                "Time from reading a block notification off the socket to having it decoded",
+                &labels,
+                LATENCY_BUCKETS,
+            ),
+        }
+    }
 }
 
 //==============================================================================
+/// Payload of a `blockNotification`.
 #[derive(Debug, Deserialize)]
 pub struct BlockResult {
     pub context: BlockContext,
     pub value: BlockWrapper,
 }
 
+//==============================================================================
+impl BlockResult {
+    pub fn has_error(&self) -> bool {
+        self.value.err.is_some()
+    }
+
+    pub fn get_encoded_block(&self) -> Option<&EncodedConfirmedBlock> {
+        self.value.block.as_ref()
+    }
+}
+
 //==============================================================================
 #[derive(Debug, Deserialize)]
 pub struct BlockContext {
@@ -67,111 +119,294 @@ pub struct BlockWrapper {
 }
 
 //==============================================================================
-impl SolanaRpcWrapper {
+impl SolanaRpc {
     //==============================================================================
-    pub fn new() -> Self {
-        AtlasUtil::setup_logger().unwrap();
-        let client = RpcClient::new(CHAINSTAKE_SOLONA_HTTPS.to_string());
-        SolanaRpcWrapper { client }
+    pub fn new(config: &AtlasConfig) -> Self {
+        let url = config.solana.rpc_url.clone();
+        SolanaRpc {
+            client: RpcClient::new(url.clone()),
+            policy: CallPolicy::from_config(&url, &config.retry),
+            url,
+        }
+    }
+
+    //==============================================================================
+    /// Share `limiter` with every other client and job calling the same
+    /// provider; RPC calls wait for budget instead of being throttled.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.policy = self.policy.with_rate_limiter(limiter);
+        self
+    }
+
+    //==============================================================================
+    /// Run RPC backoff on `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.policy = self.policy.with_clock(clock);
+        self
+    }
+
+    //==============================================================================
+    /// The underlying client, for calls this type does not wrap. They bypass
+    /// the rate limiter and retry policy.
+    pub fn client(&self) -> &RpcClient {
+        &self.client
     }
 
     //==============================================================================
-    async fn get_slot(&self) -> Result<Slot, String> {
-        match self
-            .client
-            .get_slot_with_commitment(CommitmentConfig::finalized())
+    pub async fn get_slot(&self) -> AtlasResult<Slot> {
+        self.policy
+            .call("getSlot", || async {
+                self.client
+                    .get_slot_with_commitment(CommitmentConfig::finalized())
+                    .await
+                    .map_err(|err| rpc_error(&self.url, "getSlot", err))
+            })
             .await
-        {
-            Ok(slot) => Ok(slot),
-            Err(err) => Err(format!("Error fetching slot: {}", err)),
-        }
     }
 
     //==============================================================================
-    async fn stream_block(&self) -> AtlasResult<()> {
-        info!("WebSocket connected!");
-        let (ws_stream, _) = connect_async(CHAINSTAKE_SOLONA_WS)
+    /// Confirmed transaction by signature, base64 encoded so it can be decoded
+    /// locally. Versioned transactions are included.
+    pub async fn get_transaction(
+        &self,
+        signature: &Signature,
+    ) -> AtlasResult<EncodedConfirmedTransactionWithStatusMeta> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        self.policy
+            .call("getTransaction", || async {
+                self.client
+                    .get_transaction_with_config(signature, config)
+                    .await
+                    .map_err(|err| rpc_error(&self.url, "getTransaction", err))
+            })
             .await
-            .expect("Failed to connect");
-        let (mut write, mut read) = ws_stream.split();
-        let subscription_request = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "blockSubscribe",
-            "params": [
+    }
+}
+
+//==============================================================================
+impl SolanaRpcWrapper {
+    //==============================================================================
+    /// Must be called inside a tokio runtime; the block stream connection task
+    /// is spawned here.
+    pub fn new(config: &AtlasConfig) -> Self {
+        AtlasUtil::setup_logger().unwrap();
+        let solana = config.solana.clone();
+        let ws = WsClient::spawn(
+            &solana.ws_url,
+            Arc::new(JsonRpcProtocol),
+            &config.websocket,
+            &config.retry,
+        );
+        let metrics = BlockStreamMetrics::new(ws.endpoint());
+        SolanaRpcWrapper {
+            rpc: SolanaRpc::new(config),
+            config: solana,
+            ws,
+            metrics,
+            bus: None,
+            estimator: None,
+            health: ComponentHealth::default(),
+            alerter: Alerter::default(),
+        }
+    }
+
+    //==============================================================================
+    /// Publish every streamed block's metadata to `bus` as well.
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    //==============================================================================
+    /// Feed every streamed block's transaction fees to `estimator` as well.
+    pub fn with_fee_estimator(mut self, estimator: Arc<Mutex<FeeEstimator>>) -> Self {
+        self.estimator = Some(estimator);
+        self
+    }
+
+    //==============================================================================
+    /// Report the block stream's connection, messages and slots as
+    /// `solana_blocks`.
+    pub fn with_health(mut self, registry: &HealthRegistry) -> Self {
+        self.health = registry.component("solana_blocks");
+        self.health.follow_connection(self.ws.watch_connection());
+        self
+    }
+
+    //==============================================================================
+    /// Alert when the block stream disconnects or gives up.
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        alerter.watch_connection(
+            "solana_blocks",
+            self.ws.endpoint(),
+            self.ws.watch_connection(),
+        );
+        self.alerter = alerter;
+        self
+    }
+
+    //==============================================================================
+    /// Share `limiter` with every other client and job calling the same
+    /// provider; RPC calls wait for budget instead of being throttled.
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rpc = self.rpc.with_rate_limiter(limiter);
+        self
+    }
+
+    //==============================================================================
+    /// Run RPC backoff on `clock`.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.rpc = self.rpc.with_clock(clock);
+        self
+    }
+
+    //==============================================================================
+    /// RPC calls against the same node as the block stream.
+    pub fn rpc(&self) -> &SolanaRpc {
+        &self.rpc
+    }
+
+    //==============================================================================
+    /// Stream blocks until `shutdown` is cancelled. Subscribe to the event bus
+    /// set with `with_event_bus` to consume them.
+    pub async fn stream_block(&self, shutdown: &CancellationToken) -> AtlasResult<()> {
+        let endpoint = self.ws.endpoint();
+        let request = SubscribeRequest::new(
+            "blockSubscribe",
+            json!([
                 "all",
                 {
-                    "commitment": "confirmed",
+                    "commitment": self.config.commitment,
                     "encoding": "base64",
                     "showRewards": true,
                     "transactionDetails": "full",
                     "maxSupportedTransactionVersion": 0
-
-                }
-            ]
-        });
-        write
-            .send(Message::Text(subscription_request.to_string()))
-            .await
-            .expect("Failed to send subscription request");
-        info!("Subscription request sent!");
-        while let Some(msg) = read.next().await {
-            let now = chrono::Utc::now();
-            info!("Received message at {}", now.to_rfc3339());
-            match msg {
-                Ok(Message::Text(text)) => {
-                    if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&text) {
-                        let value: Result<BlockResponse, serde_json::Error> =
-                            serde_json::from_value(parsed.clone());
-                        if value.is_err() {
-                            error!("Error parsing block: {:?}", value.err());
-                            continue;
-                        }
-                        let block = value.unwrap();
-                        if block.has_error() {
-                            error!("Error fetching block");
-                            continue;
-                        }
-                        let now_end = chrono::Utc::now();
-                        let elapsed = now_end - now;
-                        let encoded_block = block.get_encoded_block().unwrap();
-                        info!(
-                            "Received block: {} at {}, Elapsed time: {:?}",
-                            encoded_block.blockhash,
-                            now.to_rfc3339(),
-                            elapsed
-                        );
-                    }
                 }
-                Ok(Message::Ping(payload)) => {
-                    info!("Received ping: {:?}", payload);
-                    if let Err(e) = write.send(Message::Pong(payload)).await {
-                        error!("Failed to send Pong: {:?}", e);
-                    }
-                }
-                Ok(_) => {
-                    info!("Received non-text message: {:?}", msg);
+            ]),
+        )
+        .with_unsubscribe("blockUnsubscribe");
+        let mut blocks = self.ws.subscribe::<BlockResult>(request)?;
+        info!(endpoint = endpoint; "Block subscription registered");
+        loop {
+            let block = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                block = blocks.next() => block,
+            };
+            let Some(block) = block else {
+                break;
+            };
+            self.metrics.messages.inc();
+            self.health.message();
+            let (block, received_at) = match block {
+                Ok(received) => (received.value, received.received_at),
+                Err(e @ AtlasError::Protocol { .. }) => {
+                    self.alerter
+                        .fire(Alert::action_failed("solana_blocks", "blockSubscribe", &e));
+                    return Err(e);
                 }
                 Err(e) => {
-                    log::error!("Error receiving message: {:?}", e);
-                    break;
+                    self.metrics.parse_errors.inc();
+                    error!(endpoint = endpoint; "Error parsing block: {}", e);
+                    continue;
                 }
+            };
+            if block.has_error() {
+                error!(
+                    endpoint = endpoint,
+                    slot = block.context.slot;
+                    "Error fetching block"
+                );
+                continue;
+            }
+            let elapsed = received_at.elapsed();
+            self.metrics.latency.observe(elapsed.as_secs_f64());
+            let Some(encoded_block) = block.get_encoded_block() else {
+                let err = AtlasError::decode("block notification", "missing block")
+                    .at_slot(block.context.slot);
+                error!("{}", err);
+                continue;
+            };
+            info!(
+                endpoint = endpoint,
+                slot = block.value.slot,
+                latency_ms = elapsed.as_secs_f64() * 1000.0;
+                "Received block: {}",
+                encoded_block.blockhash
+            );
+            let slot = block.value.slot;
+            self.health.slot(slot);
+            if self.bus.is_none() && self.estimator.is_none() {
+                continue;
+            }
+            let fees = block_fees(slot, encoded_block);
+            if let Some(estimator) = &self.estimator {
+                estimator.lock().unwrap().observe(slot, &fees);
+            }
+            if let Some(bus) = &self.bus {
+                bus.publish(ChainEvent::BlockMeta(BlockMetaUpdate {
+                    slot,
+                    parent_slot: Some(encoded_block.parent_slot),
+                    blockhash: encoded_block.blockhash.clone(),
+                    block_time: encoded_block.block_time,
+                    block_height: encoded_block.block_height,
+                    executed_transaction_count: Some(encoded_block.transactions.len() as u64),
+                }));
+                bus.publish(ChainEvent::BlockFees(block_fee_update(slot, &fees)));
             }
         }
-        info!("WebSocket disconnected!");
+        info!(endpoint = endpoint; "Block subscription closed");
         Ok(())
     }
 }
 
+//==============================================================================
+/// Keep the JSON-RPC error code when the node returned one so the error can be
+/// classified as retryable (node behind) or fatal (slot skipped). The client's
+/// messages quote the full `url`; only its redacted form is kept.
+fn rpc_error(url: &str, method: &str, err: ClientError) -> AtlasError {
+    let endpoint = AtlasUtil::redact_url(url);
+    let endpoint = endpoint.as_str();
+    let scrub = |message: String| message.replace(url, endpoint);
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => {
+            AtlasError::Rpc {
+                endpoint: endpoint.to_string(),
+                method: method.to_string(),
+                code: Some(*code),
+                message: message.clone(),
+            }
+        }
+        ClientErrorKind::Reqwest(e) if e.status().is_some() => AtlasError::Http {
+            endpoint: endpoint.to_string(),
+            status: e.status().map(|s| s.as_u16()).unwrap_or_default(),
+            message: scrub(e.to_string()),
+        },
+        ClientErrorKind::SerdeJson(e) => AtlasError::decode(method, e),
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => {
+            AtlasError::transport(endpoint, scrub(err.to_string()))
+        }
+        _ => AtlasError::rpc(endpoint, method, scrub(err.to_string())),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use atlas_core::config::AtlasConfig;
     use solana_sdk::pubkey::Pubkey;
 
     #[tokio::test]
     async fn test_block_stream() {
-        let wrapper = SolanaRpcWrapper::new();
-        wrapper.stream_block().await.unwrap();
+        let config = AtlasConfig::from_env().unwrap();
+        let wrapper = SolanaRpcWrapper::new(&config);
+        wrapper
+            .stream_block(&CancellationToken::new())
+            .await
+            .unwrap();
     }
 
     #[tokio::test]